@@ -0,0 +1,288 @@
+use crate::de::{Deserializer, MAX_PREALLOCATED_LEN};
+use crate::error::{Error, Result};
+use crate::frame::{FrameWriter, MAX_HEADER_LEN};
+
+/// A source of bytes for the frame readers, over either kind of `AsyncRead`.
+trait Source {
+    async fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize>;
+}
+
+/// A sink of bytes for the frame writers, over either kind of `AsyncWrite`.
+trait Sink {
+    async fn write_all(&mut self, buf: &[u8]) -> ::std::io::Result<()>;
+
+    async fn flush(&mut self) -> ::std::io::Result<()>;
+}
+
+async fn read_exact<S>(source: &mut S, mut buf: &mut [u8]) -> Result<()>
+where
+    S: Source,
+{
+    while !buf.is_empty() {
+        match source.read(buf).await {
+            Ok(0) => return Err(Error::Io(::std::io::ErrorKind::UnexpectedEof.into())),
+            Ok(n) => buf = &mut buf[n..],
+            Err(e) if e.kind() == ::std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(Error::Io(e)),
+        }
+    }
+    Ok(())
+}
+
+/// Read the payload of the next checksummed frame and verify its checksum.
+/// Returns `None` if the source ends before the frame starts.
+async fn read_frame<S>(source: &mut S, max_len: usize) -> Result<Option<Vec<u8>>>
+where
+    S: Source,
+{
+    let mut header = [0u8; MAX_HEADER_LEN];
+    loop {
+        match source.read(&mut header[..1]).await {
+            Ok(0) => return Ok(None),
+            Ok(_) => break,
+            Err(e) if e.kind() == ::std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(Error::Io(e)),
+        }
+    }
+    let mut n = 1;
+    while header[n - 1] >= 0x80 && n < MAX_HEADER_LEN {
+        read_exact(source, &mut header[n..n + 1]).await?;
+        n += 1;
+    }
+    let len64 = Deserializer::from_slice(&header[..n]).read_uvarint()?;
+    let len = usize::try_from(len64).map_err(|e| Error::Generic(e.to_string()))?;
+    if len > max_len {
+        return Err(Error::Invalid(format!("length: {len}")));
+    }
+    // The buffer grows as the payload arrives, so that a forged length can't allocate
+    // more than the bytes that were actually sent.
+    let mut buf = Vec::with_capacity((len + 4).min(MAX_PREALLOCATED_LEN));
+    while buf.len() < len + 4 {
+        let start = buf.len();
+        buf.resize(start + (len + 4 - start).min(MAX_PREALLOCATED_LEN), 0);
+        read_exact(source, &mut buf[start..]).await?;
+    }
+    let checksum = buf.split_off(len);
+    crate::frame::verify(
+        &buf,
+        u32::from_le_bytes(checksum[..].try_into().expect("4 bytes")),
+    )?;
+    Ok(Some(buf))
+}
+
+/// Write a payload as a checksummed frame.
+async fn write_frame<S>(sink: &mut S, buf: &[u8]) -> Result<()>
+where
+    S: Sink,
+{
+    let mut frame = FrameWriter::new(Vec::with_capacity(buf.len() + MAX_HEADER_LEN + 4));
+    frame.write_raw(buf)?;
+    sink.write_all(&frame.into_inner()).await.map_err(Error::Io)
+}
+
+/// Generate the frame reader and writer for one kind of `AsyncRead` and `AsyncWrite`.
+macro_rules! async_frames {
+    ($source:ident, $sink:ident) => {
+        use crate::error::{Error, Result};
+        use crate::frame::DEFAULT_MAX_FRAME_LEN;
+        use crate::options::Options;
+
+        /// Encode a value into a writer as a checksummed frame, and flush the writer.
+        pub async fn to_async_writer<V, W>(v: V, w: W) -> Result<()>
+        where
+            V: ::serde::Serialize,
+            W: $sink + Unpin,
+        {
+            let mut writer = AsyncFrameWriter::new(w);
+            writer.write(v).await?;
+            writer.flush().await
+        }
+
+        /// Decode a value from a checksummed frame in a reader.
+        pub async fn from_async_reader<V, R>(r: R) -> Result<V>
+        where
+            V: ::serde::de::DeserializeOwned,
+            R: $source + Unpin,
+        {
+            AsyncFrameReader::new(r)
+                .read()
+                .await?
+                .ok_or_else(|| Error::Io(::std::io::ErrorKind::UnexpectedEof.into()))
+        }
+
+        /// An asynchronous writer for the checksummed frames of [`crate::frame`].
+        pub struct AsyncFrameWriter<W> {
+            writer: W,
+            options: Options,
+        }
+
+        impl<W> AsyncFrameWriter<W>
+        where
+            W: $sink + Unpin,
+        {
+            /// Create a new frame writer.
+            pub fn new(writer: W) -> Self {
+                Self::with_options(writer, Options::default())
+            }
+
+            /// Create a new frame writer whose payloads are encoded with the given options.
+            pub fn with_options(writer: W, options: Options) -> Self {
+                Self { writer, options }
+            }
+
+            /// Write a value as a frame.
+            pub async fn write<V>(&mut self, v: V) -> Result<()>
+            where
+                V: ::serde::Serialize,
+            {
+                let buf = crate::to_bytes_with_options(v, self.options)?;
+                self.write_raw(&buf).await
+            }
+
+            /// Write a payload that has already been encoded as a frame.
+            pub async fn write_raw(&mut self, buf: &[u8]) -> Result<()> {
+                super::write_frame(&mut Io(&mut self.writer), buf).await
+            }
+
+            /// Flush the underlying writer.
+            pub async fn flush(&mut self) -> Result<()> {
+                super::Sink::flush(&mut Io(&mut self.writer))
+                    .await
+                    .map_err(Error::Io)
+            }
+
+            /// Unwrap the underlying writer.
+            pub fn into_inner(self) -> W {
+                self.writer
+            }
+        }
+
+        /// An asynchronous reader for the checksummed frames of [`crate::frame`].
+        ///
+        /// The whole payload of every frame is read before it is decoded with the
+        /// [`Deserializer`](crate::de::Deserializer), and payloads that are longer than
+        /// the maximum frame length are rejected before they are read.
+        pub struct AsyncFrameReader<R> {
+            reader: R,
+            options: Options,
+            max_len: usize,
+        }
+
+        impl<R> AsyncFrameReader<R>
+        where
+            R: $source + Unpin,
+        {
+            /// Create a new frame reader.
+            pub fn new(reader: R) -> Self {
+                Self::with_options(reader, Options::default(), DEFAULT_MAX_FRAME_LEN)
+            }
+
+            /// Create a new frame reader whose payloads are decoded with the given options,
+            /// and which rejects payloads longer than the given length.
+            pub fn with_options(reader: R, options: Options, max_len: usize) -> Self {
+                Self {
+                    reader,
+                    options,
+                    max_len,
+                }
+            }
+
+            /// Read the payload of the next frame and verify its checksum, without decoding it.
+            /// Returns `None` once the end of the stream has been reached.
+            pub async fn read_raw(&mut self) -> Result<Option<Vec<u8>>> {
+                super::read_frame(&mut Io(&mut self.reader), self.max_len).await
+            }
+
+            /// Read the next frame, verify its checksum and decode it.
+            /// Returns `None` once the end of the stream has been reached.
+            pub async fn read<V>(&mut self) -> Result<Option<V>>
+            where
+                V: ::serde::de::DeserializeOwned,
+            {
+                match self.read_raw().await? {
+                    Some(raw) => crate::from_bytes_with_options(&raw, self.options).map(Some),
+                    None => Ok(None),
+                }
+            }
+
+            /// Unwrap the underlying reader.
+            pub fn into_inner(self) -> R {
+                self.reader
+            }
+        }
+
+        /// An adapter from the `AsyncRead` and `AsyncWrite` traits to [`super::Source`]
+        /// and [`super::Sink`].
+        struct Io<'a, T>(&'a mut T);
+    };
+}
+
+/// Frames over the [`futures_io::AsyncRead`] and [`futures_io::AsyncWrite`] traits.
+#[cfg(feature = "futures-io")]
+pub mod futures {
+    use ::futures_io::{AsyncRead, AsyncWrite};
+
+    async_frames!(AsyncRead, AsyncWrite);
+
+    impl<T> super::Source for Io<'_, T>
+    where
+        T: AsyncRead + Unpin,
+    {
+        async fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+            ::std::future::poll_fn(|cx| ::std::pin::Pin::new(&mut *self.0).poll_read(cx, buf)).await
+        }
+    }
+
+    impl<T> super::Sink for Io<'_, T>
+    where
+        T: AsyncWrite + Unpin,
+    {
+        async fn write_all(&mut self, mut buf: &[u8]) -> ::std::io::Result<()> {
+            while !buf.is_empty() {
+                let n = ::std::future::poll_fn(|cx| {
+                    ::std::pin::Pin::new(&mut *self.0).poll_write(cx, buf)
+                })
+                .await?;
+                if n == 0 {
+                    return Err(::std::io::ErrorKind::WriteZero.into());
+                }
+                buf = &buf[n..];
+            }
+            Ok(())
+        }
+
+        async fn flush(&mut self) -> ::std::io::Result<()> {
+            ::std::future::poll_fn(|cx| ::std::pin::Pin::new(&mut *self.0).poll_flush(cx)).await
+        }
+    }
+}
+
+/// Frames over the [`::tokio::io::AsyncRead`] and [`::tokio::io::AsyncWrite`] traits.
+#[cfg(feature = "tokio")]
+pub mod tokio {
+    use ::tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+
+    async_frames!(AsyncRead, AsyncWrite);
+
+    impl<T> super::Source for Io<'_, T>
+    where
+        T: AsyncRead + Unpin,
+    {
+        async fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+            self.0.read(buf).await
+        }
+    }
+
+    impl<T> super::Sink for Io<'_, T>
+    where
+        T: AsyncWrite + Unpin,
+    {
+        async fn write_all(&mut self, buf: &[u8]) -> ::std::io::Result<()> {
+            self.0.write_all(buf).await
+        }
+
+        async fn flush(&mut self) -> ::std::io::Result<()> {
+            self.0.flush().await
+        }
+    }
+}
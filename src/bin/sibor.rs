@@ -0,0 +1,337 @@
+//! # `sibor`
+//!
+//! A command-line tool for inspecting SiBOR payloads offline.
+//!
+//! ```text
+//! sibor decode   --schema SCHEMA [--hex] [FILE]   decode SiBOR into JSON, one value per line
+//! sibor encode   --schema SCHEMA [--hex] [FILE]   encode JSON values into SiBOR
+//! sibor dump                     [--hex] [FILE]   print the raw varint and byte tokens
+//! sibor trace    --schema SCHEMA [--hex] [FILE]   print a hex dump annotated with field paths
+//! sibor validate --schema SCHEMA [--hex] [FILE]   check that the input matches the schema
+//! sibor stats    --schema SCHEMA [--hex] [FILE]   print encoded size statistics per field path
+//! ```
+//!
+//! Schemas are JSON files in the serde representation of [`serde_sibor::Schema`].
+//! Input is read from `FILE`, or from stdin if no file (or `-`) is given. With `--hex`,
+//! binary input and output are hex dumps, and whitespace in hex input is ignored.
+//! Inputs that start with the container magic bytes are read as containers, and
+//! their embedded schema is used when no `--schema` is given.
+
+use std::io::{Read, Write};
+use std::process::ExitCode;
+
+use serde_sibor::container::{ContainerReader, MAGIC};
+use serde_sibor::error::{Error, Result};
+use serde_sibor::{Deserializer, Options, Schema, Value};
+
+const USAGE: &str = "\
+usage: sibor <command> [--schema SCHEMA] [--hex] [FILE]
+
+commands:
+  decode    decode SiBOR into JSON, one value per line
+  encode    encode JSON values into SiBOR
+  dump      print the raw varint and byte tokens, without a schema
+  trace     print a hex dump annotated with the field path of every byte
+  validate  check that the input matches the schema
+  stats     print encoded size statistics per field path";
+
+/// The parsed command line.
+struct Args {
+    command: String,
+    schema: Option<String>,
+    hex: bool,
+    input: Option<String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let command = args
+            .next()
+            .ok_or_else(|| Error::Generic("missing command".into()))?;
+        let mut parsed = Self {
+            command,
+            schema: None,
+            hex: false,
+            input: None,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--schema" => {
+                    let path = args
+                        .next()
+                        .ok_or_else(|| Error::Generic("--schema needs a file".into()))?;
+                    parsed.schema = Some(path);
+                }
+                "--hex" => parsed.hex = true,
+                _ if parsed.input.is_none() => parsed.input = Some(arg),
+                _ => return Err(Error::Generic(format!("unexpected argument `{arg}`"))),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn read_input(&self) -> Result<Vec<u8>> {
+        let mut raw = Vec::new();
+        match self.input.as_deref() {
+            None | Some("-") => std::io::stdin().read_to_end(&mut raw),
+            Some(path) => std::fs::File::open(path).and_then(|mut f| f.read_to_end(&mut raw)),
+        }
+        .map_err(Error::Io)?;
+        Ok(raw)
+    }
+
+    fn read_binary_input(&self) -> Result<Vec<u8>> {
+        let raw = self.read_input()?;
+        if self.hex {
+            from_hex(&raw)
+        } else {
+            Ok(raw)
+        }
+    }
+
+    fn read_schema(&self) -> Result<Option<Schema>> {
+        match &self.schema {
+            Some(path) => {
+                let raw = std::fs::read(path).map_err(Error::Io)?;
+                serde_json::from_slice(&raw)
+                    .map(Some)
+                    .map_err(|e| Error::Generic(format!("{path}: {e}")))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// The payloads of the input, along with the schema and options to decode them with.
+struct Payloads {
+    schema: Schema,
+    options: Options,
+    payloads: Vec<Vec<u8>>,
+}
+
+/// Split the input into payloads and find the schema to decode them with.
+fn payloads(args: &Args) -> Result<Payloads> {
+    let input = args.read_binary_input()?;
+    let schema = args.read_schema()?;
+    if input.starts_with(&MAGIC) {
+        let mut reader = ContainerReader::new(&input[..])?;
+        let schema = schema
+            .or_else(|| reader.schema().cloned())
+            .ok_or_else(|| Error::Generic("container has no schema; use --schema".into()))?;
+        let options = reader.header().options;
+        let mut payloads = Vec::new();
+        while let Some(raw) = reader.read_raw()? {
+            let mut de = Deserializer::from_slice_with_options(&raw, options);
+            Value::decode(&schema, &mut de)
+                .and_then(|_| match raw.len() - de.position() {
+                    0 => Ok(()),
+                    n => Err(Error::Invalid(format!("payload: {n} trailing bytes"))),
+                })
+                .map_err(|e| Error::Generic(format!("payload {}: {e}", payloads.len())))?;
+            payloads.push(raw);
+        }
+        return Ok(Payloads {
+            schema,
+            options,
+            payloads,
+        });
+    }
+
+    // A bare stream of values, one after another.
+    let schema = schema.ok_or_else(|| Error::Generic("missing --schema".into()))?;
+    let mut payloads = Vec::new();
+    let mut offset = 0;
+    while offset < input.len() {
+        let mut de = Deserializer::from_slice(&input[offset..]);
+        Value::decode(&schema, &mut de)
+            .map_err(|e| Error::Generic(format!("value at offset {offset}: {e}")))?;
+        if de.position() == 0 {
+            return Err(Error::Invalid("schema: values take no bytes".into()));
+        }
+        payloads.push(input[offset..offset + de.position()].to_vec());
+        offset += de.position();
+    }
+    Ok(Payloads {
+        schema,
+        options: Options::default(),
+        payloads,
+    })
+}
+
+fn decode(args: &Args) -> Result<()> {
+    let Payloads {
+        schema,
+        options,
+        payloads,
+    } = payloads(args)?;
+    let mut out = std::io::stdout().lock();
+    for payload in payloads {
+        serde_sibor::transcode::transcode(
+            &schema,
+            &mut Deserializer::with_options(&payload[..], options),
+            &mut serde_json::Serializer::new(&mut out),
+        )
+        .map_err(|e| Error::Generic(e.to_string()))?;
+        writeln!(out).map_err(Error::Io)?;
+    }
+    Ok(())
+}
+
+fn encode(args: &Args) -> Result<()> {
+    let schema = args
+        .read_schema()?
+        .ok_or_else(|| Error::Generic("missing --schema".into()))?;
+    let input = args.read_input()?;
+    let mut encoded = Vec::new();
+    let stream = serde_json::Deserializer::from_slice(&input).into_iter::<serde_json::Value>();
+    for json in stream {
+        let json = json.map_err(|e| Error::Generic(e.to_string()))?;
+        encoded.extend(serde_sibor::transcode::transcode_from_to_bytes(
+            &schema, json,
+        )?);
+    }
+    let mut out = std::io::stdout().lock();
+    if args.hex {
+        writeln!(out, "{}", to_hex(&encoded)).map_err(Error::Io)
+    } else {
+        out.write_all(&encoded).map_err(Error::Io)
+    }
+}
+
+fn dump(args: &Args) -> Result<()> {
+    let input = args.read_binary_input()?;
+    let mut out = std::io::stdout().lock();
+    let mut rest = &input[..];
+    while !rest.is_empty() {
+        let offset = input.len() - rest.len();
+        let before = rest;
+        let token = Deserializer::new(&mut rest).read_uvarint();
+        let consumed = &before[..before.len() - rest.len()];
+        let line = match token {
+            Ok(v) => {
+                let zigzag = Deserializer::new(consumed).read_ivarint()?;
+                // A varint that is followed by at least that many bytes of text is
+                // likely to be the length prefix of a string.
+                match usize::try_from(v).ok().and_then(|len| rest.get(..len)) {
+                    Some(text) if !text.is_empty() && is_text(text) => {
+                        rest = &rest[text.len()..];
+                        format!(
+                            "{:<30} len={v} {:?}",
+                            to_hex(&before[..consumed.len() + text.len()]),
+                            String::from_utf8_lossy(text)
+                        )
+                    }
+                    _ => format!("{:<30} u={v} i={zigzag}", to_hex(consumed)),
+                }
+            }
+            Err(e) => {
+                rest = &[];
+                format!("{:<30} error: {e}", to_hex(before))
+            }
+        };
+        writeln!(out, "{offset:>8}  {line}").map_err(Error::Io)?;
+    }
+    Ok(())
+}
+
+fn trace(args: &Args) -> Result<()> {
+    let Payloads {
+        schema,
+        options,
+        payloads,
+    } = payloads(args)?;
+    for (i, payload) in payloads.iter().enumerate() {
+        let spans = serde_sibor::trace::trace_bytes_with_options(&schema, payload, options)?;
+        println!("value {i}:");
+        print!("{}", serde_sibor::trace::render(payload, &spans));
+    }
+    Ok(())
+}
+
+fn validate(args: &Args) -> Result<()> {
+    let Payloads { payloads, .. } = payloads(args)?;
+    println!("ok: {} values", payloads.len());
+    Ok(())
+}
+
+fn stats(args: &Args) -> Result<()> {
+    let Payloads {
+        schema,
+        options,
+        payloads,
+    } = payloads(args)?;
+    let mut sizes = Vec::new();
+    let mut profiler = serde_sibor::profile::Profiler::with_options(options);
+    for payload in &payloads {
+        sizes.push(payload.len());
+        profiler.add_bytes(&schema, payload)?;
+    }
+    let total: usize = sizes.iter().sum();
+    println!("values: {}", sizes.len());
+    println!("total bytes: {total}");
+    if let (Some(min), Some(max)) = (sizes.iter().min(), sizes.iter().max()) {
+        println!("min bytes: {min}");
+        println!("max bytes: {max}");
+        println!("mean bytes: {:.1}", total as f64 / sizes.len() as f64);
+    }
+    println!();
+    print!("{}", profiler.report());
+    Ok(())
+}
+
+fn is_text(buf: &[u8]) -> bool {
+    std::str::from_utf8(buf).is_ok_and(|s| s.chars().all(|c| !c.is_control() || c == '\n'))
+}
+
+fn to_hex(buf: &[u8]) -> String {
+    Vec::from_iter(buf.iter().map(|b| format!("{b:02x}"))).join(" ")
+}
+
+fn from_hex(raw: &[u8]) -> Result<Vec<u8>> {
+    let digits = Vec::from_iter(raw.iter().copied().filter(|b| !b.is_ascii_whitespace()));
+    if digits.len() % 2 != 0 {
+        return Err(Error::Invalid("hex input: odd number of digits".into()));
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| Error::Invalid(format!("hex input: {:?}", pair)))
+        })
+        .collect()
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("sibor: {e}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let result = match args.command.as_str() {
+        "decode" => decode(&args),
+        "encode" => encode(&args),
+        "dump" => dump(&args),
+        "trace" => trace(&args),
+        "validate" => validate(&args),
+        "stats" => stats(&args),
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        command => {
+            eprintln!("sibor: unknown command `{command}`\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("sibor: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
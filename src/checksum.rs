@@ -0,0 +1,60 @@
+/// The reflected CRC32C (Castagnoli) polynomial.
+const POLYNOMIAL: u32 = 0x82f6_3b78;
+
+/// The lookup table for one byte at a time.
+const TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 1 {
+                0 => crc >> 1,
+                _ => (crc >> 1) ^ POLYNOMIAL,
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Compute the CRC32C checksum of a byte slice.
+pub fn crc32c(buf: &[u8]) -> u32 {
+    let mut crc = Crc32c::new();
+    crc.update(buf);
+    crc.finish()
+}
+
+/// An incremental CRC32C (Castagnoli) checksum.
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32c {
+    state: u32,
+}
+
+impl Crc32c {
+    /// Create a new checksum of no bytes.
+    pub fn new() -> Self {
+        Self { state: !0 }
+    }
+
+    /// Add bytes to the checksum.
+    pub fn update(&mut self, buf: &[u8]) {
+        for b in buf {
+            self.state = TABLE[((self.state ^ *b as u32) & 0xff) as usize] ^ (self.state >> 8);
+        }
+    }
+
+    /// Get the checksum of the bytes added so far.
+    pub fn finish(&self) -> u32 {
+        !self.state
+    }
+}
+
+impl Default for Crc32c {
+    fn default() -> Self {
+        Self::new()
+    }
+}
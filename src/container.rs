@@ -0,0 +1,196 @@
+use std::io::Read as _;
+
+use crate::de::Deserializer;
+use crate::error::{Error, Result};
+use crate::options::Options;
+use crate::schema::Schema;
+use crate::ser::Serializer;
+use crate::value::Value;
+
+/// The magic bytes at the start of every SiBOR container.
+pub const MAGIC: [u8; 4] = *b"SIBR";
+
+/// The version of the container format written by [`ContainerWriter`].
+pub const FORMAT_VERSION: u64 = 1;
+
+/// The header of a SiBOR container.
+///
+/// A container starts with the [`MAGIC`] bytes, followed by the format version
+/// and the encoding options as variable-length unsigned integers, followed by an
+/// optional length-prefixed schema. Every payload after the header is written as
+/// a length-prefixed byte string, until the end of the stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    /// The version of the container format.
+    pub version: u64,
+    /// The encoding options of the payloads, stored as [`Options::to_bits`].
+    pub options: Options,
+    /// The schema of every payload, if one was embedded.
+    pub schema: Option<Schema>,
+}
+
+/// A writer for SiBOR containers.
+pub struct ContainerWriter<W> {
+    ser: Serializer<W>,
+    options: Options,
+}
+
+impl<W> ContainerWriter<W>
+where
+    W: ::std::io::Write,
+{
+    /// Create a new container writer, and write the header with the given schema to the writer.
+    pub fn new(writer: W, schema: Option<&Schema>) -> Result<Self> {
+        Self::with_options(writer, schema, Options::default())
+    }
+
+    /// Create a new container writer whose payloads are encoded with the given options,
+    /// and write the header with the given schema to the writer.
+    pub fn with_options(writer: W, schema: Option<&Schema>, options: Options) -> Result<Self> {
+        let mut ser = Serializer::new(writer);
+        for b in MAGIC {
+            ser.write_u8(b)?;
+        }
+        ser.write_uvarint(FORMAT_VERSION)?;
+        ser.write_uvarint(options.to_bits())?;
+        match schema {
+            Some(schema) => {
+                ser.write_bool(true)?;
+                ser.write_bytes(&crate::to_bytes(schema)?)?;
+            }
+            None => ser.write_bool(false)?,
+        }
+        Ok(Self { ser, options })
+    }
+
+    /// Write a payload.
+    pub fn write<V>(&mut self, v: V) -> Result<()>
+    where
+        V: ::serde::Serialize,
+    {
+        self.ser
+            .write_bytes(&crate::to_bytes_with_options(v, self.options)?)
+    }
+
+    /// Write a dynamically typed payload.
+    pub fn write_value(&mut self, v: &Value) -> Result<()> {
+        self.write(v)
+    }
+
+    /// Write a payload that has already been encoded.
+    pub fn write_raw(&mut self, buf: &[u8]) -> Result<()> {
+        self.ser.write_bytes(buf)
+    }
+
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.ser.into_inner()
+    }
+}
+
+/// A reader for SiBOR containers.
+pub struct ContainerReader<R> {
+    reader: R,
+    header: Header,
+}
+
+impl<R> ContainerReader<R>
+where
+    R: ::std::io::Read,
+{
+    /// Create a new container reader, and read the header from the reader.
+    ///
+    /// Payloads are decoded with the options stored in the header, which don't include
+    /// [`Options::field_numbers`] or [`Options::canonical`]. Containers that use them must
+    /// be read with [`ContainerReader::with_options`].
+    pub fn new(reader: R) -> Result<Self> {
+        Self::read_header(reader, None)
+    }
+
+    /// Create a new container reader whose payloads are decoded with the given options,
+    /// and read the header from the reader. Returns an error if the options don't match
+    /// the options stored in the header.
+    pub fn with_options(reader: R, options: Options) -> Result<Self> {
+        Self::read_header(reader, Some(options))
+    }
+
+    fn read_header(mut reader: R, options: Option<Options>) -> Result<Self> {
+        let mut de = Deserializer::new(&mut reader);
+        let mut magic = [0u8; 4];
+        for b in magic.iter_mut() {
+            *b = de.read_u8()?;
+        }
+        if magic != MAGIC {
+            return Err(Error::Invalid("container magic".into()));
+        }
+        let version = de.read_uvarint()?;
+        if version != FORMAT_VERSION {
+            return Err(Error::Unsupported(format!("container version {version}")));
+        }
+        let bits = de.read_uvarint()?;
+        let options = match options {
+            Some(options) => options.check_bits(bits)?,
+            None => Options::from_bits(bits)?,
+        };
+        let schema = match de.read_bool()? {
+            true => Some(crate::from_bytes(&de.read_bytes(0, usize::MAX)?)?),
+            false => None,
+        };
+        Ok(Self {
+            reader,
+            header: Header {
+                version,
+                options,
+                schema,
+            },
+        })
+    }
+
+    /// Get the header of the container.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Get the schema embedded in the container, if any.
+    pub fn schema(&self) -> Option<&Schema> {
+        self.header.schema.as_ref()
+    }
+
+    /// Read the next payload without decoding it.
+    /// Returns `None` once the end of the container has been reached.
+    pub fn read_raw(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut first = [0u8];
+        if self.reader.read(&mut first[..]).map_err(Error::Io)? == 0 {
+            return Ok(None);
+        }
+        let mut de = Deserializer::new(first.chain(&mut self.reader));
+        de.read_bytes(0, usize::MAX).map(Some)
+    }
+
+    /// Read and decode the next payload.
+    /// Returns `None` once the end of the container has been reached.
+    pub fn read<V>(&mut self) -> Result<Option<V>>
+    where
+        V: ::serde::de::DeserializeOwned,
+    {
+        match self.read_raw()? {
+            Some(raw) => crate::from_bytes_with_options(&raw, self.header.options).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Read and decode the next payload with the embedded schema.
+    /// Returns `None` once the end of the container has been reached.
+    pub fn read_value(&mut self) -> Result<Option<Value>> {
+        if self.header.schema.is_none() {
+            return Err(Error::Generic("container has no embedded schema".into()));
+        }
+        match (self.read_raw()?, &self.header.schema) {
+            (Some(raw), Some(schema)) => {
+                let mut de = Deserializer::with_options(&raw[..], self.header.options);
+                Value::decode(schema, &mut de).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+}
@@ -1,4 +1,84 @@
+use std::borrow::Cow;
+
 use crate::error::{Error, Result};
+use crate::options::{EnumEncoding, Options, StructEncoding, WIRE_LEN, WIRE_VARINT};
+
+/// A source of encoded bytes for a [`Deserializer`].
+///
+/// This is implemented for every [`std::io::Read`], which always produces owned data,
+/// and for [`SliceReader`], which lets strings and byte strings borrow from the input.
+pub trait Read<'de> {
+    /// Read exactly the number of bytes necessary to fill the given buffer.
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()>;
+
+    /// Read exactly `len` bytes, borrowing them from the input when possible.
+    fn read_slice(&mut self, len: usize) -> Result<Cow<'de, [u8]>>;
+}
+
+impl<'de, R> Read<'de> for R
+where
+    R: ::std::io::Read,
+{
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        ::std::io::Read::read_exact(self, buf).map_err(Error::Io)
+    }
+
+    fn read_slice(&mut self, len: usize) -> Result<Cow<'de, [u8]>> {
+        // The length comes from the input, so the buffer only grows as bytes arrive.
+        let len64 = u64::try_from(len).map_err(|e| Error::Generic(e.to_string()))?;
+        let mut raw = Vec::with_capacity(len.min(MAX_PREALLOCATED_LEN));
+        ::std::io::Read::read_to_end(&mut ::std::io::Read::take(self, len64), &mut raw)
+            .map_err(Error::Io)?;
+        if raw.len() < len {
+            return Err(Error::Io(::std::io::ErrorKind::UnexpectedEof.into()));
+        }
+        Ok(Cow::Owned(raw))
+    }
+}
+
+/// The largest buffer that is allocated up front for a value of untrusted length from a reader.
+pub(crate) const MAX_PREALLOCATED_LEN: usize = 1 << 16;
+
+/// A reader over a byte slice that lends out borrowed strings and byte strings.
+#[derive(Debug, Clone)]
+pub struct SliceReader<'de> {
+    buf: &'de [u8],
+}
+
+impl<'de> SliceReader<'de> {
+    /// Create a new reader over the given slice.
+    pub fn new(buf: &'de [u8]) -> Self {
+        Self { buf }
+    }
+
+    /// Get the bytes that have not been read yet.
+    pub fn remaining(&self) -> &'de [u8] {
+        self.buf
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'de [u8]> {
+        if len > self.buf.len() {
+            return Err(Error::Io(::std::io::Error::new(
+                ::std::io::ErrorKind::UnexpectedEof,
+                "failed to fill whole buffer",
+            )));
+        }
+        let (taken, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(taken)
+    }
+}
+
+impl<'de> Read<'de> for SliceReader<'de> {
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        buf.copy_from_slice(self.take(buf.len())?);
+        Ok(())
+    }
+
+    fn read_slice(&mut self, len: usize) -> Result<Cow<'de, [u8]>> {
+        self.take(len).map(Cow::Borrowed)
+    }
+}
 
 /// A helper for deserializing statically structured data such as
 /// tuples, structs, and fixed-length arrays.
@@ -8,7 +88,7 @@ struct DeserializeTuple<'a, R> {
 
 impl<'de, 'a, R> ::serde::de::SeqAccess<'de> for DeserializeTuple<'a, R>
 where
-    R: ::std::io::Read,
+    R: Read<'de>,
 {
     type Error = Error;
 
@@ -23,12 +103,15 @@ where
 
 /// A helper for deserializing the tag in tagged union values.
 struct DeserializeEnum<'a, R> {
+    name: &'static str,
+    variants: &'static [&'static str],
+    open: bool,
     de: &'a mut Deserializer<R>,
 }
 
 impl<'de, 'a, R> ::serde::de::EnumAccess<'de> for DeserializeEnum<'a, R>
 where
-    R: ::std::io::Read,
+    R: Read<'de>,
 {
     type Error = Error;
 
@@ -38,57 +121,233 @@ where
     where
         V: serde::de::DeserializeSeed<'de>,
     {
-        let variant = seed.deserialize(&mut *self.de)?;
-        Ok((variant, DeserializeEnumVariant { de: &mut *self.de }))
+        use ::serde::de::IntoDeserializer as _;
+
+        let index = self.de.read_uvarint()?;
+        let end = self.de.begin_variant()?;
+        let variant = usize::try_from(index)
+            .ok()
+            .and_then(|index| self.variants.get(index).copied());
+        if variant.is_none() && self.open {
+            return Err(self.de.capture_unknown(self.name, index, end));
+        }
+        let value = seed.deserialize(index.into_deserializer())?;
+        Ok((
+            value,
+            DeserializeEnumVariant {
+                name: self.name,
+                variant,
+                end,
+                de: &mut *self.de,
+            },
+        ))
     }
 }
 
 /// A helper for deserializing a member of a tagged union.
+/// With length-prefixed enums, the payload ends at `end`, and the rest of it is skipped.
 struct DeserializeEnumVariant<'a, R> {
+    name: &'static str,
+    variant: Option<&'static str>,
+    end: Option<usize>,
     de: &'a mut Deserializer<R>,
 }
 
+impl<'de, 'a, R> DeserializeEnumVariant<'a, R>
+where
+    R: Read<'de>,
+{
+    fn finish<T>(self, value: T) -> Result<T> {
+        self.de.end_body(self.end)?;
+        Ok(value)
+    }
+}
+
 impl<'de, 'a, R> ::serde::de::VariantAccess<'de> for DeserializeEnumVariant<'a, R>
 where
-    R: ::std::io::Read,
+    R: Read<'de>,
 {
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
-        Ok(())
+        // Unknown variants end up here through `#[serde(other)]`.
+        if self.variant.is_none() && self.end.is_none() {
+            return Err(Error::Invalid(format!(
+                "enum {}: the payload of an unknown variant can only be skipped \
+                 with length-prefixed enums",
+                self.name
+            )));
+        }
+        match (self.variant, self.end) {
+            (None, Some(end)) => self.de.skip_to(end),
+            _ => self.finish(()),
+        }
     }
 
     fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
     where
         T: serde::de::DeserializeSeed<'de>,
     {
-        seed.deserialize(&mut *self.de)
+        let value = seed.deserialize(&mut *self.de)?;
+        self.finish(value)
     }
 
     fn tuple_variant<V>(self, _: usize, v: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        v.visit_seq(DeserializeTuple { de: &mut *self.de })
+        let value = v.visit_seq(DeserializeTuple { de: &mut *self.de })?;
+        self.finish(value)
     }
 
-    fn struct_variant<V>(self, _: &'static [&'static str], v: V) -> Result<V::Value>
+    fn struct_variant<V>(self, fields: &'static [&'static str], v: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        v.visit_seq(DeserializeTuple { de: &mut *self.de })
+        let value = self.de.deserialize_struct_body(
+            self.name,
+            Some(self.variant.unwrap_or("")),
+            fields,
+            v,
+        )?;
+        self.finish(value)
+    }
+}
+
+/// A helper for deserializing an [`crate::raw::Open`] enum.
+///
+/// The first element is the enum if its variant is known. Otherwise it is missing, and
+/// is followed by the index and the payload of the unknown variant.
+struct DeserializeOpen<'a, R> {
+    element: usize,
+    unknown: Option<(u32, Vec<u8>)>,
+    de: &'a mut Deserializer<R>,
+}
+
+impl<'de, 'a, R> ::serde::de::SeqAccess<'de> for DeserializeOpen<'a, R>
+where
+    R: Read<'de>,
+{
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: serde::de::DeserializeSeed<'de>,
+    {
+        use ::serde::de::IntoDeserializer as _;
+
+        self.element += 1;
+        match (self.element, self.unknown.as_mut()) {
+            (1, _) => {
+                self.de.open = true;
+                let result = seed.deserialize(&mut *self.de);
+                self.de.open = false;
+                match (result, self.de.unknown.take()) {
+                    (Err(_), Some(unknown)) => {
+                        self.unknown = Some(unknown);
+                        Ok(None)
+                    }
+                    (result, _) => result.map(Some),
+                }
+            }
+            (2, Some((index, _))) => seed.deserialize(index.into_deserializer()).map(Some),
+            (3, Some((_, payload))) => seed
+                .deserialize(::std::mem::take(payload).into_deserializer())
+                .map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// A helper for deserializing the fields of structs.
+/// With length-prefixed structs, the fields end after the number of fields in the body.
+struct DeserializeStruct<'a, R> {
+    index: usize,
+    body: Option<Body>,
+    de: &'a mut Deserializer<R>,
+}
+
+impl<'de, 'a, R> ::serde::de::SeqAccess<'de> for DeserializeStruct<'a, R>
+where
+    R: Read<'de>,
+{
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: serde::de::DeserializeSeed<'de>,
+    {
+        if !self.de.has_field(self.body, self.index) {
+            return Ok(None);
+        }
+        self.index += 1;
+        let x = seed.deserialize(&mut *self.de)?;
+        Ok(Some(x))
+    }
+}
+
+/// A helper for deserializing the fields of numbered structs.
+struct DeserializeFields<'a, R> {
+    numbers: Vec<u64>,
+    previous: Option<u64>,
+    remaining: usize,
+    end: Option<usize>,
+    de: &'a mut Deserializer<R>,
+}
+
+impl<'de, 'a, R> ::serde::de::MapAccess<'de> for DeserializeFields<'a, R>
+where
+    R: Read<'de>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: serde::de::DeserializeSeed<'de>,
+    {
+        use ::serde::de::IntoDeserializer as _;
+
+        while self.remaining > 0 {
+            self.remaining -= 1;
+            let (number, end) = self.de.read_field_key(&mut self.previous)?;
+            match self.numbers.iter().position(|n| *n == number) {
+                Some(index) => {
+                    self.end = end;
+                    return seed
+                        .deserialize((index as u64).into_deserializer())
+                        .map(Some);
+                }
+                None => self.de.skip_field_value(end)?,
+            }
+        }
+        Ok(None)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: serde::de::DeserializeSeed<'de>,
+    {
+        let value = seed.deserialize(&mut *self.de)?;
+        self.de.end_body(self.end.take())?;
+        Ok(value)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
     }
 }
 
 /// A helper for deserializing elements of a dynamically sized collection.
+/// If the collection is a canonical set, the order of its elements is checked.
 struct DeserializeCollection<'a, R> {
     remaining: usize,
+    sorted: Option<Option<Vec<u8>>>,
     de: &'a mut Deserializer<R>,
 }
 
 impl<'de, 'a, R> ::serde::de::SeqAccess<'de> for DeserializeCollection<'a, R>
 where
-    R: ::std::io::Read,
+    R: Read<'de>,
 {
     type Error = Error;
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
@@ -100,26 +359,226 @@ where
         }
         self.remaining -= 1;
 
-        let x = seed.deserialize(&mut *self.de)?;
+        let x = match self.sorted.as_mut() {
+            Some(previous) => self.de.read_sorted(previous, |de| seed.deserialize(de))?,
+            None => seed.deserialize(&mut *self.de)?,
+        };
         Ok(Some(x))
     }
 }
 
-/// A deserializer that can deserialize owned values from a reader.
+/// A helper for deserializing the entries of maps.
+/// If the map is canonical, the order of its keys is checked.
+struct DeserializeMap<'a, R> {
+    remaining: usize,
+    sorted: Option<Option<Vec<u8>>>,
+    de: &'a mut Deserializer<R>,
+}
+
+impl<'de, 'a, R> ::serde::de::MapAccess<'de> for DeserializeMap<'a, R>
+where
+    R: Read<'de>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: serde::de::DeserializeSeed<'de>,
+    {
+        if self.remaining < 1 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+
+        let key = match self.sorted.as_mut() {
+            Some(previous) => self.de.read_sorted(previous, |de| seed.deserialize(de))?,
+            None => seed.deserialize(&mut *self.de)?,
+        };
+        Ok(Some(key))
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: serde::de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+/// A lazy reader for sequences, which deserializes one element at a time.
+///
+/// The count of the sequence is read when the reader is created, and the elements are
+/// read as the reader is iterated. After an error, the reader yields no more elements,
+/// since the position of the next element is unknown.
+pub struct SeqReader<'a, R, T> {
+    remaining: usize,
+    de: &'a mut Deserializer<R>,
+    element: ::std::marker::PhantomData<fn() -> T>,
+}
+
+impl<'de, 'a, R, T> SeqReader<'a, R, T>
+where
+    R: Read<'de>,
+    T: ::serde::Deserialize<'de>,
+{
+    /// Start reading a sequence, and read its count.
+    pub fn new(de: &'a mut Deserializer<R>) -> Result<Self> {
+        let remaining64 = de.read_uvarint()?;
+        let remaining = usize::try_from(remaining64).map_err(|e| Error::Generic(e.to_string()))?;
+        Ok(Self {
+            remaining,
+            de,
+            element: ::std::marker::PhantomData,
+        })
+    }
+
+    /// Get the number of elements that are still to be read.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+}
+
+impl<'de, 'a, R, T> Iterator for SeqReader<'a, R, T>
+where
+    R: Read<'de>,
+    T: ::serde::Deserialize<'de>,
+{
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining < 1 {
+            return None;
+        }
+        self.remaining -= 1;
+        let element = T::deserialize(&mut *self.de);
+        if element.is_err() {
+            self.remaining = 0;
+        }
+        Some(element)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
+    }
+}
+
+/// A reader over the contents of a byte string, returned by
+/// [`Deserializer::read_bytes_reader`], which ends with the byte string.
+pub struct BytesReader<'a, R> {
+    remaining: usize,
+    de: &'a mut Deserializer<R>,
+}
+
+impl<'de, 'a, R> BytesReader<'a, R>
+where
+    R: Read<'de>,
+{
+    /// Get the number of bytes of the byte string that are still to be read.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Skip the rest of the byte string, so that the deserializer can go on.
+    pub fn finish(mut self) -> Result<()> {
+        let mut buf = [0u8; 8192];
+        while self.remaining > 0 {
+            let n = self.remaining.min(buf.len());
+            self.de.read_exact(&mut buf[..n])?;
+            self.remaining -= n;
+        }
+        Ok(())
+    }
+}
+
+impl<'de, 'a, R> ::std::io::Read for BytesReader<'a, R>
+where
+    R: Read<'de>,
+{
+    fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+        let n = self.remaining.min(buf.len());
+        self.de.read_exact(&mut buf[..n]).map_err(|e| match e {
+            Error::Io(e) => e,
+            e => ::std::io::Error::other(e),
+        })?;
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+/// The body of a length-prefixed struct.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Body {
+    /// The position where the body ends.
+    pub(crate) end: usize,
+    /// The number of fields in the body.
+    pub(crate) fields: usize,
+}
+
+/// A deserializer that can deserialize values from a reader.
 pub struct Deserializer<R> {
     reader: R,
+    options: Options,
+    position: usize,
+    open: bool,
+    unknown: Option<(u32, Vec<u8>)>,
+    set: bool,
+    recording: Vec<Vec<u8>>,
 }
 
 impl<R> Deserializer<R> {
     /// Create a new deserializer from the given reader.
     pub fn new(reader: R) -> Self {
-        Self { reader }
+        Self::with_options(reader, Options::default())
+    }
+
+    /// Create a new deserializer from the given reader with the given options.
+    pub fn with_options(reader: R, options: Options) -> Self {
+        Self {
+            reader,
+            options,
+            position: 0,
+            open: false,
+            unknown: None,
+            set: false,
+            recording: Vec::new(),
+        }
+    }
+
+    /// Get the options of this deserializer.
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Get the number of bytes read so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Unwrap the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<'de> Deserializer<SliceReader<'de>> {
+    /// Create a new deserializer that borrows strings and byte strings from the given slice.
+    pub fn from_slice(buf: &'de [u8]) -> Self {
+        Self::new(SliceReader::new(buf))
+    }
+
+    /// Create a new deserializer that borrows from the given slice, with the given options.
+    pub fn from_slice_with_options(buf: &'de [u8], options: Options) -> Self {
+        Self::with_options(SliceReader::new(buf), options)
     }
 }
 
-impl<R> Deserializer<R>
+impl<'de, R> Deserializer<R>
 where
-    R: ::std::io::Read,
+    R: Read<'de>,
 {
     /// The maximum number of bytes that can be used to encode a variable-length integer.
     /// Currently, this is 10 bytes and variable-length integers are limited to 64-bit values.
@@ -128,7 +587,229 @@ where
     /// A utility function to read exactly the number of bytes
     /// necessary to fill the given buffer.
     fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
-        self.reader.read_exact(&mut buf[..]).map_err(Error::Io)
+        self.reader.read_exact(&mut buf[..])?;
+        self.advance(buf);
+        Ok(())
+    }
+
+    /// Account for bytes that were read, and record them for the keys being checked.
+    fn advance(&mut self, buf: &[u8]) {
+        self.position += buf.len();
+        for recording in &mut self.recording {
+            recording.extend_from_slice(buf);
+        }
+    }
+
+    /// Read a key of a canonical map or set, and check that its bytes sort after the
+    /// bytes of the previous key.
+    fn read_sorted<T>(
+        &mut self,
+        previous: &mut Option<Vec<u8>>,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        self.recording.push(Vec::new());
+        let result = f(self);
+        let key = self.recording.pop().unwrap_or_default();
+        let value = result?;
+        if previous.as_ref().is_some_and(|previous| *previous >= key) {
+            return Err(Error::Invalid(
+                "canonical encoding: keys are not sorted and unique".into(),
+            ));
+        }
+        *previous = Some(key);
+        Ok(value)
+    }
+
+    /// A utility function to read and discard exactly `n` bytes.
+    fn skip_exact(&mut self, mut n: usize) -> Result<()> {
+        let mut buf = [0u8; 256];
+        while n > 0 {
+            let chunk = n.min(buf.len());
+            self.read_exact(&mut buf[..chunk])?;
+            n -= chunk;
+        }
+        Ok(())
+    }
+
+    /// Read a length, and get the position where a value of that length ends.
+    fn read_end(&mut self) -> Result<usize> {
+        let len64 = self.read_uvarint()?;
+        usize::try_from(len64)
+            .ok()
+            .and_then(|len| self.position.checked_add(len))
+            .ok_or_else(|| Error::Invalid(format!("length: {len64}")))
+    }
+
+    /// Skip to the end of a length-prefixed value.
+    pub(crate) fn skip_to(&mut self, end: usize) -> Result<()> {
+        match end.checked_sub(self.position) {
+            Some(unread) => self.skip_exact(unread),
+            None => Err(Error::Invalid(format!(
+                "length-prefixed value: read {} bytes past its end",
+                self.position - end
+            ))),
+        }
+    }
+
+    /// Start reading the body of a struct.
+    /// Returns where the body ends and how many fields it holds if structs are length-prefixed.
+    /// Numbered structs are read field by field with [`Deserializer::read_field_key`] instead.
+    pub(crate) fn begin_struct(&mut self) -> Result<Option<Body>> {
+        match self.options.struct_encoding {
+            StructEncoding::Tuple | StructEncoding::Numbered => Ok(None),
+            StructEncoding::LengthPrefixed => {
+                let end = self.read_end()?;
+                let fields64 = self.read_uvarint()?;
+                let fields =
+                    usize::try_from(fields64).map_err(|e| Error::Generic(e.to_string()))?;
+                Ok(Some(Body { end, fields }))
+            }
+        }
+    }
+
+    /// Stop treating the next enum as the one inside an [`crate::raw::Open`], because a
+    /// compound value was reached first. Newtypes keep the flag, as they share the
+    /// encoding of the value they wrap.
+    fn close_open(&mut self) {
+        self.open = false;
+    }
+
+    /// Start reading the payload of an enum variant.
+    /// Returns the position where the payload ends if enums are length-prefixed.
+    pub(crate) fn begin_variant(&mut self) -> Result<Option<usize>> {
+        match self.options.enum_encoding {
+            EnumEncoding::Plain => Ok(None),
+            EnumEncoding::LengthPrefixed => self.read_end().map(Some),
+        }
+    }
+
+    /// Read the payload of a variant that the reader doesn't know, and keep it for
+    /// the [`crate::raw::Open`] that is being deserialized. Returns the error that
+    /// unwinds the deserialization of the known variants.
+    fn capture_unknown(&mut self, name: &str, index: u64, end: Option<usize>) -> Error {
+        let Some(end) = end else {
+            return Error::Invalid(format!(
+                "enum {name}: the payload of unknown variant {index} can only be skipped \
+                 with length-prefixed enums"
+            ));
+        };
+        let Ok(index) = u32::try_from(index) else {
+            return Error::Invalid(format!("variant index: {index}"));
+        };
+        let payload = end
+            .checked_sub(self.position)
+            .ok_or_else(|| Error::Invalid(format!("variant length: {end}")))
+            .and_then(|len| self.reader.read_slice(len));
+        match payload {
+            Ok(payload) => {
+                self.advance(&payload);
+                self.unknown = Some((index, payload.into_owned()));
+                Error::Invalid(format!("enum {name}: unknown variant {index}"))
+            }
+            Err(e) => e,
+        }
+    }
+
+    /// Check whether the body of a struct holds the field with the given index.
+    /// Fields that need no bytes are held too, as long as they were written.
+    pub(crate) fn has_field(&self, body: Option<Body>, index: usize) -> bool {
+        match body {
+            Some(body) => index < body.fields,
+            None => true,
+        }
+    }
+
+    /// Finish reading the payload of a variant or the value of a numbered field,
+    /// and skip what was not read. In canonical mode, bytes that were not read are
+    /// an error, since the value would be the same without them.
+    pub(crate) fn end_body(&mut self, end: Option<usize>) -> Result<()> {
+        match end {
+            Some(end) if self.options.canonical.is_some() && self.position < end => {
+                Err(Error::Invalid(format!(
+                    "canonical encoding: {} unread bytes in length-prefixed value",
+                    end - self.position
+                )))
+            }
+            Some(end) => self.skip_to(end),
+            None => Ok(()),
+        }
+    }
+
+    /// Finish reading the body of a struct, after reading `known` fields. Fields that
+    /// the reader doesn't know are skipped, but otherwise the body must be read to its
+    /// end in canonical mode.
+    pub(crate) fn end_struct(&mut self, body: Option<Body>, known: usize) -> Result<()> {
+        match body {
+            Some(body) if body.fields > known => self.skip_to(body.end),
+            body => self.end_body(body.map(|body| body.end)),
+        }
+    }
+
+    /// Read the key of a numbered field, after the field numbered `previous` if any.
+    /// Returns the number of the field and, if its value is length-prefixed, the position
+    /// where its value ends. In canonical mode, field numbers must increase.
+    pub(crate) fn read_field_key(
+        &mut self,
+        previous: &mut Option<u64>,
+    ) -> Result<(u64, Option<usize>)> {
+        let key = self.read_uvarint()?;
+        let number = key >> 3;
+        match previous.replace(number) {
+            Some(previous) if self.options.canonical.is_some() && previous >= number => {
+                return Err(Error::Invalid(format!(
+                    "canonical encoding: field {number} after field {previous}"
+                )));
+            }
+            _ => {}
+        }
+        match key & 0b111 {
+            WIRE_VARINT => Ok((number, None)),
+            WIRE_LEN => Ok((number, Some(self.read_end()?))),
+            wire => Err(Error::Invalid(format!("wire type: {wire}"))),
+        }
+    }
+
+    /// Skip the value of a numbered field, given the end returned by [`Deserializer::read_field_key`].
+    pub(crate) fn skip_field_value(&mut self, end: Option<usize>) -> Result<()> {
+        match end {
+            Some(end) => self.skip_to(end),
+            None => self.read_uvarint().map(drop),
+        }
+    }
+
+    /// Read the body of a struct, or of a struct variant if `variant` is given, with the given visitor.
+    fn deserialize_struct_body<V>(
+        &mut self,
+        name: &'static str,
+        variant: Option<&'static str>,
+        fields: &'static [&'static str],
+        v: V,
+    ) -> Result<V::Value>
+    where
+        V: serde::de::Visitor<'de>,
+    {
+        if self.options.struct_encoding == StructEncoding::Numbered {
+            let len64 = self.read_uvarint()?;
+            let remaining = usize::try_from(len64).map_err(|e| Error::Generic(e.to_string()))?;
+            let numbers = self
+                .options
+                .field_numbers_of(name, variant, fields.iter().copied())?;
+            return v.visit_map(DeserializeFields {
+                numbers,
+                previous: None,
+                remaining,
+                end: None,
+                de: self,
+            });
+        }
+        let body = self.begin_struct()?;
+        let value = v.visit_seq(DeserializeStruct {
+            index: 0,
+            body,
+            de: self,
+        })?;
+        self.end_struct(body, fields.len())?;
+        Ok(value)
     }
 
     /// Read an unsigned 8-bit integer from the stream.
@@ -147,6 +828,11 @@ where
             let b = self.read_u8()? as u64;
             v |= (b & 0x7f) << (i * 7);
             if b < 0x80 {
+                // A canonical varint has no trailing zero bytes, and no bits past 64.
+                let overlong = (i > 0 && b == 0) || (i == Self::MAX_VARINT_BYTES - 1 && b > 1);
+                if overlong && self.options.canonical.is_some() {
+                    return Err(Error::Invalid("canonical encoding: overlong varint".into()));
+                }
                 return Ok(v);
             }
         }
@@ -169,7 +855,15 @@ where
     /// The raw bits are read as an unsigned integer and then converted to a float.
     pub fn read_float(&mut self) -> Result<f64> {
         let unsigned = self.read_uvarint()?;
-        Ok(f64::from_bits(unsigned))
+        let v = f64::from_bits(unsigned);
+        if let Some(canonical) = self.options.canonical {
+            if canonical.normalize(v).to_bits() != unsigned {
+                return Err(Error::Invalid(format!(
+                    "canonical encoding: float {v} is not normalized"
+                )));
+            }
+        }
+        Ok(v)
     }
 
     /// Read a boolean value from the stream.
@@ -188,16 +882,38 @@ where
     /// First, a variable-length integer is read. This is the length of the sequence.
     /// Then, exactly that many bytes are read from the stream.
     pub fn read_bytes(&mut self, min: usize, max: usize) -> Result<Vec<u8>> {
+        self.read_borrowed_bytes(min, max).map(Cow::into_owned)
+    }
+
+    /// Read a sequence of bytes from the stream like [`Deserializer::read_bytes`],
+    /// but borrow the bytes from the input when the reader allows it.
+    pub fn read_borrowed_bytes(&mut self, min: usize, max: usize) -> Result<Cow<'de, [u8]>> {
         let len64 = self.read_uvarint()?;
         let len = usize::try_from(len64).map_err(|e| Error::Generic(e.to_string()))?;
         if len < min || len > max {
             return Err(Error::Invalid(format!("length: {len}")));
         }
-        let mut raw = vec![0u8; len];
-        self.read_exact(&mut raw[..])?;
+        let raw = self.reader.read_slice(len)?;
+        self.advance(&raw);
         Ok(raw)
     }
 
+    /// Read the length of a byte string from the stream, and return a reader over its
+    /// contents instead of reading them into memory.
+    /// The contents must be read to the end, or skipped with [`BytesReader::finish`],
+    /// before anything else is read from the stream.
+    pub fn read_bytes_reader(&mut self, min: usize, max: usize) -> Result<BytesReader<'_, R>> {
+        let len64 = self.read_uvarint()?;
+        let len = usize::try_from(len64).map_err(|e| Error::Generic(e.to_string()))?;
+        if len < min || len > max {
+            return Err(Error::Invalid(format!("length: {len}")));
+        }
+        Ok(BytesReader {
+            remaining: len,
+            de: self,
+        })
+    }
+
     /// Read a sequence of utf8-encoded bytes from the stream.
     /// First, a variable-length integer is read. This is the length of the sequence.
     /// Then, exactly that many bytes are read from the stream.
@@ -209,9 +925,9 @@ where
     }
 }
 
-impl<'de, 'a, R> ::serde::Deserializer<'de> for &'a mut Deserializer<R>
+impl<'de, R> ::serde::Deserializer<'de> for &mut Deserializer<R>
 where
-    R: ::std::io::Read,
+    R: Read<'de>,
 {
     type Error = Error;
 
@@ -310,11 +1026,18 @@ where
         Err(Error::Unsupported("deserialize char".into()))
     }
 
-    fn deserialize_str<V>(self, _: V) -> Result<V::Value>
+    fn deserialize_str<V>(self, v: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        Err(Error::Unsupported("deserialize &str".into()))
+        match self.read_borrowed_bytes(0, usize::MAX)? {
+            Cow::Borrowed(raw) => v.visit_borrowed_str(
+                std::str::from_utf8(raw).map_err(|e| Error::Generic(e.to_string()))?,
+            ),
+            Cow::Owned(raw) => {
+                v.visit_string(String::from_utf8(raw).map_err(|e| Error::Generic(e.to_string()))?)
+            }
+        }
     }
 
     fn deserialize_string<V>(self, v: V) -> Result<V::Value>
@@ -325,11 +1048,14 @@ where
         v.visit_string(x)
     }
 
-    fn deserialize_bytes<V>(self, _: V) -> Result<V::Value>
+    fn deserialize_bytes<V>(self, v: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        Err(Error::Unsupported("deserialize &[u8]".into()))
+        match self.read_borrowed_bytes(0, usize::MAX)? {
+            Cow::Borrowed(raw) => v.visit_borrowed_bytes(raw),
+            Cow::Owned(raw) => v.visit_byte_buf(raw),
+        }
     }
 
     fn deserialize_byte_buf<V>(self, v: V) -> Result<V::Value>
@@ -361,10 +1087,16 @@ where
         v.visit_unit()
     }
 
-    fn deserialize_newtype_struct<V>(self, _: &'static str, v: V) -> Result<V::Value>
+    fn deserialize_newtype_struct<V>(self, name: &'static str, v: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
+        if name == crate::set::SET {
+            self.set = true;
+            let result = v.visit_newtype_struct(&mut *self);
+            self.set = false;
+            return result;
+        }
         v.visit_seq(DeserializeTuple { de: self })
     }
 
@@ -372,10 +1104,13 @@ where
     where
         V: serde::de::Visitor<'de>,
     {
+        self.close_open();
+        let set = ::std::mem::take(&mut self.set);
         let remaining64 = self.read_uvarint()?;
         let remaining = usize::try_from(remaining64).map_err(|e| Error::Generic(e.to_string()))?;
         v.visit_seq(DeserializeCollection {
             remaining,
+            sorted: (set && self.options.canonical.is_some()).then_some(None),
             de: self,
         })
     }
@@ -384,45 +1119,68 @@ where
     where
         V: serde::de::Visitor<'de>,
     {
+        self.close_open();
         v.visit_seq(DeserializeTuple { de: self })
     }
 
-    fn deserialize_tuple_struct<V>(self, _: &'static str, _: usize, v: V) -> Result<V::Value>
+    fn deserialize_tuple_struct<V>(self, name: &'static str, _: usize, v: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
+        if name == crate::raw::OPEN {
+            return v.visit_seq(DeserializeOpen {
+                element: 0,
+                unknown: None,
+                de: self,
+            });
+        }
+        self.close_open();
         v.visit_seq(DeserializeTuple { de: self })
     }
 
-    fn deserialize_map<V>(self, _: V) -> Result<V::Value>
+    fn deserialize_map<V>(self, v: V) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        Err(Error::Unsupported("deserialize map".into()))
+        self.close_open();
+        let remaining64 = self.read_uvarint()?;
+        let remaining = usize::try_from(remaining64).map_err(|e| Error::Generic(e.to_string()))?;
+        v.visit_map(DeserializeMap {
+            remaining,
+            sorted: self.options.canonical.map(|_| None),
+            de: self,
+        })
     }
 
     fn deserialize_struct<V>(
         self,
-        _: &'static str,
-        _: &'static [&'static str],
+        name: &'static str,
+        fields: &'static [&'static str],
         v: V,
     ) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        v.visit_seq(DeserializeTuple { de: self })
+        self.close_open();
+        self.deserialize_struct_body(name, None, fields, v)
     }
 
     fn deserialize_enum<V>(
         self,
-        _: &'static str,
-        _: &'static [&'static str],
+        name: &'static str,
+        variants: &'static [&'static str],
         v: V,
     ) -> Result<V::Value>
     where
         V: serde::de::Visitor<'de>,
     {
-        v.visit_enum(DeserializeEnum { de: self })
+        let open = ::std::mem::take(&mut self.open);
+        v.visit_enum(DeserializeEnum {
+            name,
+            variants,
+            open,
+            de: self,
+        })
     }
 
     fn deserialize_identifier<V>(self, v: V) -> Result<V::Value>
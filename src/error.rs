@@ -16,6 +16,15 @@ pub enum Error {
     /// Errors related to values that are not valid for the given type.
     #[error("invalid {0}")]
     Invalid(String),
+
+    /// Errors related to data whose checksum doesn't match its contents.
+    #[error("checksum mismatch: expected {expected:#010x}, found {actual:#010x}")]
+    Checksum {
+        /// The checksum that was stored with the data.
+        expected: u32,
+        /// The checksum of the data that was read.
+        actual: u32,
+    },
 }
 
 /// Result type for this crate.
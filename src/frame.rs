@@ -0,0 +1,270 @@
+use std::io::Read as _;
+
+use crate::checksum::crc32c;
+use crate::de::Deserializer;
+use crate::error::{Error, Result};
+use crate::options::Options;
+use crate::ser::Serializer;
+
+/// The largest frame payload that a [`FrameReader`] accepts, unless another limit is given.
+pub const DEFAULT_MAX_FRAME_LEN: usize = 1 << 26;
+
+/// The length of the longest variable-length unsigned integer, which a frame starts with.
+pub(crate) const MAX_HEADER_LEN: usize = 10;
+
+/// Encode a value into a writer as a checksummed frame.
+pub fn to_writer<V, W>(v: V, w: W) -> Result<()>
+where
+    V: ::serde::Serialize,
+    W: ::std::io::Write,
+{
+    FrameWriter::new(w).write(v)
+}
+
+/// Decode a value from a checksummed frame in a reader.
+pub fn from_reader<V, R>(r: R) -> Result<V>
+where
+    V: ::serde::de::DeserializeOwned,
+    R: ::std::io::Read,
+{
+    FrameReader::new(r)
+        .read()?
+        .ok_or_else(|| Error::Io(::std::io::ErrorKind::UnexpectedEof.into()))
+}
+
+/// A writer for checksummed frames.
+///
+/// Every frame is the length of the payload as a variable-length unsigned integer,
+/// followed by the payload and the CRC32C checksum of the payload as a 32-bit
+/// little-endian integer.
+pub struct FrameWriter<W> {
+    writer: W,
+    options: Options,
+}
+
+impl<W> FrameWriter<W>
+where
+    W: ::std::io::Write,
+{
+    /// Create a new frame writer.
+    pub fn new(writer: W) -> Self {
+        Self::with_options(writer, Options::default())
+    }
+
+    /// Create a new frame writer whose payloads are encoded with the given options.
+    pub fn with_options(writer: W, options: Options) -> Self {
+        Self { writer, options }
+    }
+
+    /// Write a value as a frame.
+    pub fn write<V>(&mut self, v: V) -> Result<()>
+    where
+        V: ::serde::Serialize,
+    {
+        self.write_raw(&crate::to_bytes_with_options(v, self.options)?)
+    }
+
+    /// Write a payload that has already been encoded as a frame.
+    pub fn write_raw(&mut self, buf: &[u8]) -> Result<()> {
+        let mut ser = Serializer::new(&mut self.writer);
+        ser.write_bytes(buf)?;
+        for b in crc32c(buf).to_le_bytes() {
+            ser.write_u8(b)?;
+        }
+        Ok(())
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush().map_err(Error::Io)
+    }
+
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// A reader for checksummed frames written by [`FrameWriter`].
+///
+/// The checksum of every frame is verified before its payload is decoded, and a
+/// mismatch is reported as [`Error::Checksum`].
+pub struct FrameReader<R> {
+    reader: R,
+    options: Options,
+    max_len: usize,
+}
+
+impl<R> FrameReader<R>
+where
+    R: ::std::io::Read,
+{
+    /// Create a new frame reader.
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, Options::default(), DEFAULT_MAX_FRAME_LEN)
+    }
+
+    /// Create a new frame reader whose payloads are decoded with the given options, and
+    /// which rejects payloads longer than the given length.
+    pub fn with_options(reader: R, options: Options, max_len: usize) -> Self {
+        Self {
+            reader,
+            options,
+            max_len,
+        }
+    }
+
+    /// Read the payload of the next frame and verify its checksum, without decoding it.
+    /// Returns `None` once the end of the stream has been reached.
+    pub fn read_raw(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut first = [0u8];
+        if self.reader.read(&mut first[..]).map_err(Error::Io)? == 0 {
+            return Ok(None);
+        }
+        let mut de = Deserializer::new(first.chain(&mut self.reader));
+        let payload = de.read_bytes(0, self.max_len)?;
+        let mut checksum = [0u8; 4];
+        for b in checksum.iter_mut() {
+            *b = de.read_u8()?;
+        }
+        verify(&payload, u32::from_le_bytes(checksum))?;
+        Ok(Some(payload))
+    }
+
+    /// Read the next frame, verify its checksum and decode it.
+    /// Returns `None` once the end of the stream has been reached.
+    pub fn read<V>(&mut self) -> Result<Option<V>>
+    where
+        V: ::serde::de::DeserializeOwned,
+    {
+        match self.read_raw()? {
+            Some(raw) => crate::from_bytes_with_options(&raw, self.options).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Unwrap the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// The result of a [`FrameDecoder`] that was asked for the next frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decoded<V> {
+    /// The buffered bytes don't hold a whole frame, and at least this many more bytes are needed.
+    NeedMore(usize),
+    /// The next frame was complete, and was decoded into this value.
+    Frame(V),
+}
+
+/// A push decoder for checksummed frames that arrive in chunks of any size, such as
+/// reads from a non-blocking socket.
+///
+/// Chunks are passed to [`FrameDecoder::feed`], and frames are taken out with
+/// [`FrameDecoder::decode`] until it needs more bytes. The length of the next frame is
+/// parsed once, as soon as its header is buffered, so that the decoder knows exactly how
+/// many more bytes the frame needs.
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+    start: usize,
+    options: Options,
+    max_len: usize,
+    frame: Option<(usize, usize)>,
+}
+
+impl FrameDecoder {
+    /// Create a new frame decoder.
+    pub fn new() -> Self {
+        Self::with_options(Options::default(), DEFAULT_MAX_FRAME_LEN)
+    }
+
+    /// Create a new frame decoder whose payloads are decoded with the given options, and
+    /// which rejects payloads longer than the given length.
+    pub fn with_options(options: Options, max_len: usize) -> Self {
+        Self {
+            buf: Vec::new(),
+            start: 0,
+            options,
+            max_len,
+            frame: None,
+        }
+    }
+
+    /// Get the number of bytes that were fed but not yet decoded.
+    pub fn buffered(&self) -> usize {
+        self.buf.len() - self.start
+    }
+
+    /// Buffer the next chunk of bytes.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        if self.start > 0 {
+            self.buf.drain(..self.start);
+            self.start = 0;
+        }
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// Take the payload of the next frame out of the buffer and verify its checksum,
+    /// without decoding it.
+    ///
+    /// A frame whose checksum doesn't match is taken out of the buffer, so that the
+    /// decoder can go on with the next frame. A frame that is too long can't be skipped,
+    /// and the decoder keeps returning an error for it.
+    pub fn decode_raw(&mut self) -> Result<Decoded<Vec<u8>>> {
+        let pending = &self.buf[self.start..];
+        let (header, len) = match self.frame {
+            Some(frame) => frame,
+            None => {
+                if pending.len() < MAX_HEADER_LEN && pending.iter().all(|b| *b >= 0x80) {
+                    return Ok(Decoded::NeedMore(1));
+                }
+                let mut de = Deserializer::from_slice(pending);
+                let len64 = de.read_uvarint()?;
+                let len = usize::try_from(len64).map_err(|e| Error::Generic(e.to_string()))?;
+                if len > self.max_len {
+                    return Err(Error::Invalid(format!("length: {len}")));
+                }
+                *self.frame.insert((de.position(), len))
+            }
+        };
+        let total = header + len + 4;
+        if pending.len() < total {
+            return Ok(Decoded::NeedMore(total - pending.len()));
+        }
+        let payload = pending[header..header + len].to_vec();
+        let checksum =
+            u32::from_le_bytes(pending[header + len..total].try_into().expect("4 bytes"));
+        self.start += total;
+        self.frame = None;
+        verify(&payload, checksum)?;
+        Ok(Decoded::Frame(payload))
+    }
+
+    /// Take the next frame out of the buffer, verify its checksum and decode it.
+    pub fn decode<V>(&mut self) -> Result<Decoded<V>>
+    where
+        V: ::serde::de::DeserializeOwned,
+    {
+        match self.decode_raw()? {
+            Decoded::Frame(raw) => {
+                crate::from_bytes_with_options(&raw, self.options).map(Decoded::Frame)
+            }
+            Decoded::NeedMore(n) => Ok(Decoded::NeedMore(n)),
+        }
+    }
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check that a payload has the expected checksum.
+pub(crate) fn verify(payload: &[u8], expected: u32) -> Result<()> {
+    match crc32c(payload) {
+        actual if actual == expected => Ok(()),
+        actual => Err(Error::Checksum { expected, actual }),
+    }
+}
@@ -0,0 +1,126 @@
+use crate::error::{Error, Result};
+use crate::options::Options;
+use crate::ser::Serializer;
+
+/// The length from which [`GatherWriter::write_bytes`] references byte strings instead of
+/// copying them, unless another threshold is given.
+pub const DEFAULT_THRESHOLD: usize = 4096;
+
+/// A serialization target that copies small encoded values into an inline buffer, but
+/// references large byte strings by slice, for zero-copy sends with
+/// [`std::io::Write::write_vectored`].
+///
+/// Serde passes byte strings to serializers without the lifetime of the value they
+/// belong to, so values serialized with [`GatherWriter::write`] are always copied.
+/// Large byte fields are written with [`GatherWriter::write_bytes`] instead, between
+/// the other fields, and are encoded exactly like byte strings serialized by serde.
+pub struct GatherWriter<'a> {
+    buf: Vec<u8>,
+    blobs: Vec<(usize, &'a [u8])>,
+    options: Options,
+    threshold: usize,
+}
+
+impl<'a> GatherWriter<'a> {
+    /// Create a new gather writer.
+    pub fn new() -> Self {
+        Self::with_options(Options::default(), DEFAULT_THRESHOLD)
+    }
+
+    /// Create a new gather writer that encodes values with the given options, and that
+    /// references byte strings of at least the given length.
+    pub fn with_options(options: Options, threshold: usize) -> Self {
+        Self {
+            buf: Vec::new(),
+            blobs: Vec::new(),
+            options,
+            threshold,
+        }
+    }
+
+    /// Get a serializer that writes to the inline buffer, for the low-level methods of
+    /// [`Serializer`].
+    pub fn serializer(&mut self) -> Serializer<&mut Vec<u8>> {
+        Serializer::with_options(&mut self.buf, self.options)
+    }
+
+    /// Encode a value into the inline buffer.
+    pub fn write<V>(&mut self, v: V) -> Result<()>
+    where
+        V: ::serde::Serialize,
+    {
+        v.serialize(&mut self.serializer())
+    }
+
+    /// Write a byte string. Its length is encoded into the inline buffer, and its contents
+    /// are referenced if it is at least as long as the threshold, or copied otherwise.
+    pub fn write_bytes(&mut self, v: &'a [u8]) -> Result<()> {
+        if v.len() < self.threshold {
+            return self.serializer().write_bytes(v);
+        }
+        let len64 = u64::try_from(v.len()).map_err(|e| Error::Generic(e.to_string()))?;
+        self.serializer().write_uvarint(len64)?;
+        self.blobs.push((self.buf.len(), v));
+        Ok(())
+    }
+
+    /// Get the total length of the encoded bytes.
+    pub fn len(&self) -> usize {
+        self.buf.len() + self.blobs.iter().map(|(_, blob)| blob.len()).sum::<usize>()
+    }
+
+    /// Check whether nothing has been written.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the encoded bytes as slices of the inline buffer and of the referenced byte
+    /// strings, in order.
+    pub fn io_slices(&self) -> Vec<::std::io::IoSlice<'_>> {
+        let mut slices = Vec::with_capacity(self.blobs.len() * 2 + 1);
+        let mut start = 0;
+        for (end, blob) in &self.blobs {
+            if *end > start {
+                slices.push(::std::io::IoSlice::new(&self.buf[start..*end]));
+            }
+            slices.push(::std::io::IoSlice::new(blob));
+            start = *end;
+        }
+        if self.buf.len() > start {
+            slices.push(::std::io::IoSlice::new(&self.buf[start..]));
+        }
+        slices
+    }
+
+    /// Write the encoded bytes to a writer with vectored writes.
+    pub fn write_to<W>(&self, mut w: W) -> Result<()>
+    where
+        W: ::std::io::Write,
+    {
+        let mut slices = self.io_slices();
+        let mut slices = &mut slices[..];
+        while !slices.is_empty() {
+            match w.write_vectored(slices) {
+                Ok(0) => return Err(Error::Io(::std::io::ErrorKind::WriteZero.into())),
+                Ok(n) => ::std::io::IoSlice::advance_slices(&mut slices, n),
+                Err(e) if e.kind() == ::std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(Error::Io(e)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the encoded bytes into a byte vector.
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.io_slices()
+            .iter()
+            .flat_map(|s| s.iter().copied())
+            .collect()
+    }
+}
+
+impl Default for GatherWriter<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,72 @@
+/// The number of bytes that a [`Sink`] collects before feeding them on.
+const BLOCK: usize = 256;
+
+/// A writer that feeds the bytes written to it into a hasher or an incremental digest.
+///
+/// The serializer writes varints one byte at a time, so the bytes are collected into
+/// blocks of a fixed size before they are fed on. Every block is full except the last,
+/// so the input is split the same way for equal encodings, even for hashers whose
+/// result depends on how their input is split. Call [`Sink::finish`] to feed the last
+/// block; flushing the sink does not feed a partial block.
+pub struct Sink<F>
+where
+    F: FnMut(&[u8]),
+{
+    update: F,
+    block: [u8; BLOCK],
+    len: usize,
+}
+
+impl<F> Sink<F>
+where
+    F: FnMut(&[u8]),
+{
+    /// Create a sink that feeds blocks of bytes to the given function, such as the
+    /// `update` method of a digest.
+    pub fn new(update: F) -> Self {
+        Self {
+            update,
+            block: [0u8; BLOCK],
+            len: 0,
+        }
+    }
+
+    /// Feed the last block.
+    pub fn finish(mut self) {
+        if self.len > 0 {
+            (self.update)(&self.block[..self.len]);
+        }
+    }
+}
+
+/// Create a sink that feeds blocks of bytes to a [`std::hash::Hasher`].
+pub fn hasher<H>(hasher: &mut H) -> Sink<impl FnMut(&[u8]) + '_>
+where
+    H: ::std::hash::Hasher,
+{
+    Sink::new(|bytes: &[u8]| hasher.write(bytes))
+}
+
+impl<F> ::std::io::Write for Sink<F>
+where
+    F: FnMut(&[u8]),
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let n = rest.len().min(BLOCK - self.len);
+            self.block[self.len..self.len + n].copy_from_slice(&rest[..n]);
+            self.len += n;
+            rest = &rest[n..];
+            if self.len == BLOCK {
+                (self.update)(&self.block);
+                self.len = 0;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
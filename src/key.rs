@@ -0,0 +1,880 @@
+use std::borrow::Cow;
+
+use crate::error::{Error, Result};
+
+/// The byte that escapes a zero byte inside a string or a byte string.
+const ESCAPE: u8 = 0xff;
+
+/// The byte that terminates a string or a byte string, after a zero byte.
+const TERMINATOR: u8 = 0x01;
+
+/// The byte that precedes each element of a sequence or entry of a map.
+const MORE: u8 = 0x01;
+
+/// The byte that ends a sequence or map.
+const END: u8 = 0x00;
+
+/// Encode a value as an order-preserving key.
+pub fn to_bytes<V>(v: V) -> Result<Vec<u8>>
+where
+    V: ::serde::Serialize,
+{
+    let mut buf = Vec::new();
+    v.serialize(&mut Serializer::new(&mut buf))?;
+    Ok(buf)
+}
+
+/// Decode a value from an order-preserving key. The key must contain exactly one value.
+pub fn from_bytes<'de, V>(buf: &'de [u8]) -> Result<V>
+where
+    V: ::serde::Deserialize<'de>,
+{
+    let mut de = Deserializer::new(buf);
+    let v = V::deserialize(&mut de)?;
+    match de.remaining().len() {
+        0 => Ok(v),
+        n => Err(Error::Invalid(format!("key: {n} trailing bytes"))),
+    }
+}
+
+/// A serializer for order-preserving keys.
+///
+/// The bytewise order of two keys matches the [`Ord`] of the values they encode, as long
+/// as the values have the same type and its `Ord` is derived from its fields:
+///
+/// - Integers are big-endian at the width of their type, with the sign bit flipped.
+/// - Floats have their bits flipped so that they sort like [`f64::total_cmp`].
+/// - Strings and byte strings escape zero bytes as `00 ff`, and end with `00 01`.
+/// - Each element of a sequence or entry of a map is preceded by `01`, and they end with `00`.
+///   Map entries are sorted by their encoded keys, so maps of equal entries have equal keys.
+/// - Options are `00` for `None`, and `01` followed by the value for `Some`.
+/// - Tuples and structs concatenate their fields, and enums are a big-endian variant index
+///   followed by the payload.
+pub struct Serializer<W> {
+    writer: W,
+}
+
+impl<W> Serializer<W> {
+    /// Create a new serializer that writes to the given writer.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W> Serializer<W>
+where
+    W: ::std::io::Write,
+{
+    fn write_exact(&mut self, buf: &[u8]) -> Result<()> {
+        self.writer.write_all(buf).map_err(Error::Io)
+    }
+
+    fn write_escaped(&mut self, v: &[u8]) -> Result<()> {
+        for chunk in v.split_inclusive(|b| *b == 0) {
+            self.write_exact(chunk)?;
+            if chunk.last() == Some(&0) {
+                self.write_exact(&[ESCAPE])?;
+            }
+        }
+        self.write_exact(&[0, TERMINATOR])
+    }
+
+    fn write_signed(&mut self, v: i64, width: usize) -> Result<()> {
+        let flipped = (v as u64) ^ (1 << (width * 8 - 1));
+        self.write_exact(&flipped.to_be_bytes()[8 - width..])
+    }
+
+    fn write_unsigned(&mut self, v: u64, width: usize) -> Result<()> {
+        self.write_exact(&v.to_be_bytes()[8 - width..])
+    }
+
+    fn write_float(&mut self, bits: u64, width: usize) -> Result<()> {
+        let sign = 1 << (width * 8 - 1);
+        let flipped = match bits & sign {
+            0 => bits ^ sign,
+            _ => !bits,
+        };
+        self.write_unsigned(flipped, width)
+    }
+}
+
+/// A helper for serializing the elements of sequences.
+pub struct SeqSerializer<'a, W> {
+    ser: &'a mut Serializer<W>,
+}
+
+impl<'a, W> ::serde::ser::SerializeSeq for SeqSerializer<'a, W>
+where
+    W: ::std::io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + ::serde::Serialize,
+    {
+        self.ser.write_exact(&[MORE])?;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<()> {
+        self.ser.write_exact(&[END])
+    }
+}
+
+/// A helper for serializing the entries of maps.
+///
+/// The entries are serialized into buffers, which are sorted by their encoded keys and
+/// written once the map is complete, so that equal maps produce the same key regardless
+/// of their iteration order.
+pub struct MapSerializer<'a, W> {
+    entries: Vec<Vec<u8>>,
+    ser: &'a mut Serializer<W>,
+}
+
+impl<'a, W> ::serde::ser::SerializeMap for MapSerializer<'a, W>
+where
+    W: ::std::io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: ?Sized + ::serde::Serialize,
+    {
+        let mut entry = vec![MORE];
+        key.serialize(&mut Serializer::new(&mut entry))?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + ::serde::Serialize,
+    {
+        let entry = self.entries.last_mut().ok_or_else(|| {
+            Error::Generic("tried to serialize a map value before its key".into())
+        })?;
+        value.serialize(&mut Serializer::new(entry))
+    }
+
+    fn end(mut self) -> Result<()> {
+        // Encoded keys are never a prefix of one another, so sorting whole entries
+        // sorts them by their keys.
+        self.entries.sort_unstable();
+        for entry in &self.entries {
+            self.ser.write_exact(entry)?;
+        }
+        self.ser.write_exact(&[END])
+    }
+}
+
+/// A helper for serializing tuples, structs, and the payloads of variants.
+pub struct TupleSerializer<'a, W> {
+    ser: &'a mut Serializer<W>,
+}
+
+impl<'a, W> ::serde::ser::SerializeTuple for TupleSerializer<'a, W>
+where
+    W: ::std::io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + ::serde::Serialize,
+    {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, W> ::serde::ser::SerializeTupleStruct for TupleSerializer<'a, W>
+where
+    W: ::std::io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + ::serde::Serialize,
+    {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, W> ::serde::ser::SerializeTupleVariant for TupleSerializer<'a, W>
+where
+    W: ::std::io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + ::serde::Serialize,
+    {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, W> ::serde::ser::SerializeStruct for TupleSerializer<'a, W>
+where
+    W: ::std::io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, _: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + ::serde::Serialize,
+    {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, W> ::serde::ser::SerializeStructVariant for TupleSerializer<'a, W>
+where
+    W: ::std::io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, _: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + ::serde::Serialize,
+    {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, W> ::serde::ser::Serializer for &'a mut Serializer<W>
+where
+    W: ::std::io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = SeqSerializer<'a, W>;
+    type SerializeTuple = TupleSerializer<'a, W>;
+    type SerializeTupleStruct = TupleSerializer<'a, W>;
+    type SerializeTupleVariant = TupleSerializer<'a, W>;
+    type SerializeMap = MapSerializer<'a, W>;
+    type SerializeStruct = TupleSerializer<'a, W>;
+    type SerializeStructVariant = TupleSerializer<'a, W>;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.write_exact(&[v as u8])
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.write_signed(v as i64, 1)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.write_signed(v as i64, 2)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.write_signed(v as i64, 4)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.write_signed(v, 8)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.write_unsigned(v as u64, 1)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.write_unsigned(v as u64, 2)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.write_unsigned(v as u64, 4)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.write_unsigned(v, 8)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.write_float(v.to_bits() as u64, 4)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.write_float(v.to_bits(), 8)
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.write_unsigned(v as u64, 4)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.write_escaped(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.write_escaped(v)
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.write_exact(&[0])
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<()>
+    where
+        T: ?Sized + ::serde::Serialize,
+    {
+        self.write_exact(&[1])?;
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(self, _: &'static str, index: u32, _: &'static str) -> Result<()> {
+        self.write_unsigned(index as u64, 4)
+    }
+
+    fn serialize_newtype_struct<T>(self, _: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + ::serde::Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _: &'static str,
+        index: u32,
+        _: &'static str,
+        value: &T,
+    ) -> Result<()>
+    where
+        T: ?Sized + ::serde::Serialize,
+    {
+        self.write_unsigned(index as u64, 4)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(SeqSerializer { ser: self })
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple> {
+        Ok(TupleSerializer { ser: self })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Ok(TupleSerializer { ser: self })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        index: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        self.write_unsigned(index as u64, 4)?;
+        Ok(TupleSerializer { ser: self })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
+        Ok(MapSerializer {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            ser: self,
+        })
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct> {
+        Ok(TupleSerializer { ser: self })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        index: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        self.write_unsigned(index as u64, 4)?;
+        Ok(TupleSerializer { ser: self })
+    }
+}
+
+/// A deserializer for order-preserving keys, which borrows strings and byte strings
+/// from the key when they contain no escaped zero bytes.
+pub struct Deserializer<'de> {
+    buf: &'de [u8],
+}
+
+impl<'de> Deserializer<'de> {
+    /// Create a new deserializer over the given key.
+    pub fn new(buf: &'de [u8]) -> Self {
+        Self { buf }
+    }
+
+    /// Get the bytes that have not been read yet.
+    pub fn remaining(&self) -> &'de [u8] {
+        self.buf
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'de [u8]> {
+        if len > self.buf.len() {
+            return Err(Error::Invalid("key: unexpected end".into()));
+        }
+        let (taken, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(taken)
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn read_unsigned(&mut self, width: usize) -> Result<u64> {
+        let mut bytes = [0u8; 8];
+        bytes[8 - width..].copy_from_slice(self.take(width)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    fn read_signed(&mut self, width: usize) -> Result<i64> {
+        let flipped = self.read_unsigned(width)? ^ (1 << (width * 8 - 1));
+        // Sign-extend from the width of the type.
+        let shift = 64 - width * 8;
+        Ok(((flipped << shift) as i64) >> shift)
+    }
+
+    fn read_float(&mut self, width: usize) -> Result<u64> {
+        let sign = 1 << (width * 8 - 1);
+        let flipped = self.read_unsigned(width)?;
+        let mask = match width {
+            8 => u64::MAX,
+            _ => (1 << (width * 8)) - 1,
+        };
+        Ok(match flipped & sign {
+            0 => !flipped & mask,
+            _ => flipped ^ sign,
+        })
+    }
+
+    fn read_escaped(&mut self) -> Result<Cow<'de, [u8]>> {
+        let mut owned: Option<Vec<u8>> = None;
+        let mut start = 0;
+        loop {
+            let zero = self.buf[start..]
+                .iter()
+                .position(|b| *b == 0)
+                .map(|i| start + i)
+                .ok_or_else(|| Error::Invalid("key: unterminated string".into()))?;
+            match self.buf.get(zero + 1) {
+                Some(&TERMINATOR) => {
+                    let (content, rest) = self.buf.split_at(zero);
+                    self.buf = &rest[2..];
+                    return Ok(match owned {
+                        Some(mut owned) => {
+                            owned.extend_from_slice(&content[start..]);
+                            Cow::Owned(owned)
+                        }
+                        None => Cow::Borrowed(content),
+                    });
+                }
+                Some(&ESCAPE) => {
+                    owned
+                        .get_or_insert_with(Vec::new)
+                        .extend_from_slice(&self.buf[start..=zero]);
+                    start = zero + 2;
+                }
+                _ => return Err(Error::Invalid("key: escape sequence".into())),
+            }
+        }
+    }
+
+    fn read_marker(&mut self) -> Result<bool> {
+        match self.read_u8()? {
+            MORE => Ok(true),
+            END => Ok(false),
+            b => Err(Error::Invalid(format!("key: sequence marker {b:#04x}"))),
+        }
+    }
+}
+
+/// A helper for deserializing the elements of sequences and the entries of maps.
+struct DeserializeSeq<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'de, 'a> ::serde::de::SeqAccess<'de> for DeserializeSeq<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: ::serde::de::DeserializeSeed<'de>,
+    {
+        match self.de.read_marker()? {
+            true => seed.deserialize(&mut *self.de).map(Some),
+            false => Ok(None),
+        }
+    }
+}
+
+impl<'de, 'a> ::serde::de::MapAccess<'de> for DeserializeSeq<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: ::serde::de::DeserializeSeed<'de>,
+    {
+        match self.de.read_marker()? {
+            true => seed.deserialize(&mut *self.de).map(Some),
+            false => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: ::serde::de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+/// A helper for deserializing tuples, structs, and the payloads of variants.
+struct DeserializeTuple<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'de, 'a> ::serde::de::SeqAccess<'de> for DeserializeTuple<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: ::serde::de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+}
+
+impl<'de> ::serde::de::EnumAccess<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+    where
+        V: ::serde::de::DeserializeSeed<'de>,
+    {
+        use ::serde::de::IntoDeserializer as _;
+
+        let index = self.read_unsigned(4)? as u32;
+        let value = seed.deserialize(index.into_deserializer())?;
+        Ok((value, self))
+    }
+}
+
+impl<'de> ::serde::de::VariantAccess<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: ::serde::de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, _: usize, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_seq(DeserializeTuple { de: self })
+    }
+
+    fn struct_variant<V>(self, _: &'static [&'static str], v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_seq(DeserializeTuple { de: self })
+    }
+}
+
+impl<'de> ::serde::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, _: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        Err(Error::Unsupported("deserialize any".into()))
+    }
+
+    fn deserialize_bool<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        match self.read_u8()? {
+            0 => v.visit_bool(false),
+            1 => v.visit_bool(true),
+            _ => Err(Error::Invalid("boolean encoding".into())),
+        }
+    }
+
+    fn deserialize_i8<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_i8(self.read_signed(1)? as i8)
+    }
+
+    fn deserialize_i16<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_i16(self.read_signed(2)? as i16)
+    }
+
+    fn deserialize_i32<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_i32(self.read_signed(4)? as i32)
+    }
+
+    fn deserialize_i64<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_i64(self.read_signed(8)?)
+    }
+
+    fn deserialize_u8<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_u8(self.read_unsigned(1)? as u8)
+    }
+
+    fn deserialize_u16<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_u16(self.read_unsigned(2)? as u16)
+    }
+
+    fn deserialize_u32<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_u32(self.read_unsigned(4)? as u32)
+    }
+
+    fn deserialize_u64<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_u64(self.read_unsigned(8)?)
+    }
+
+    fn deserialize_f32<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_f32(f32::from_bits(self.read_float(4)? as u32))
+    }
+
+    fn deserialize_f64<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_f64(f64::from_bits(self.read_float(8)?))
+    }
+
+    fn deserialize_char<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        let c = self.read_unsigned(4)?;
+        let c = u32::try_from(c)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| Error::Invalid(format!("char: {c:#x}")))?;
+        v.visit_char(c)
+    }
+
+    fn deserialize_str<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        let utf8 = |e: ::std::str::Utf8Error| Error::Generic(e.to_string());
+        match self.read_escaped()? {
+            Cow::Borrowed(raw) => v.visit_borrowed_str(::std::str::from_utf8(raw).map_err(utf8)?),
+            Cow::Owned(raw) => {
+                v.visit_string(String::from_utf8(raw).map_err(|e| utf8(e.utf8_error()))?)
+            }
+        }
+    }
+
+    fn deserialize_string<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        self.deserialize_str(v)
+    }
+
+    fn deserialize_bytes<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        match self.read_escaped()? {
+            Cow::Borrowed(raw) => v.visit_borrowed_bytes(raw),
+            Cow::Owned(raw) => v.visit_byte_buf(raw),
+        }
+    }
+
+    fn deserialize_byte_buf<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        self.deserialize_bytes(v)
+    }
+
+    fn deserialize_option<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        match self.read_u8()? {
+            0 => v.visit_none(),
+            1 => v.visit_some(self),
+            b => Err(Error::Invalid(format!("key: option marker {b:#04x}"))),
+        }
+    }
+
+    fn deserialize_unit<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(self, _: &'static str, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V>(self, _: &'static str, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_seq(DeserializeSeq { de: self })
+    }
+
+    fn deserialize_tuple<V>(self, _: usize, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_seq(DeserializeTuple { de: self })
+    }
+
+    fn deserialize_tuple_struct<V>(self, _: &'static str, _: usize, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_seq(DeserializeTuple { de: self })
+    }
+
+    fn deserialize_map<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_map(DeserializeSeq { de: self })
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        v: V,
+    ) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_seq(DeserializeTuple { de: self })
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        v: V,
+    ) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V>(self, v: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        v.visit_u32(self.read_unsigned(4)? as u32)
+    }
+
+    fn deserialize_ignored_any<V>(self, _: V) -> Result<V::Value>
+    where
+        V: ::serde::de::Visitor<'de>,
+    {
+        Err(Error::Unsupported("deserialize any (ignored)".into()))
+    }
+}
@@ -7,21 +7,72 @@
 //!
 //! - SiBOR is not self-describing. The schema must be known in advance.
 //! - SiBOR does not have a concept of "optional" fields. All fields must have a value.
-//! - SiBOR does not support maps. All maps must be encoded as sequences of key-value pairs.
+//! - SiBOR does not have a concept of maps. All maps are encoded as sequences of key-value pairs.
 //! - SiBOR treats all signed integers, unsigned integers, and floats as 64-bit values.
 //! - SiBOR encodes all unsigned integers using a variable-length encoding.
 //! - SiBOR encodes all signed integers using a variable-length zigzag encoding.
 //! - SiBOR encodes all floats using a 64-bit IEEE 754 encoding. The bits are treated as a u64 and encoded using the variable-length encoding.
 //!
 //! SiBOR is meant to be used when you want a quick and dirty way to serialize and deserialize binary data of a known schema.
-//! It does not have any built-in support for schema evolution, so such support must be implemented by the user.
+//! By default it does not support schema evolution, but [`options::Options`] can opt into encodings
+//! that allow structs to add and remove fields, and enums to add variants, and into a canonical
+//! encoding where equal values always produce the same bytes. The [`key`] module has a separate
+//! encoding for keys of sorted key-value stores, whose bytewise order matches the order of the values.
 
+/// Asynchronous reading and writing of checksummed frames, with the `futures-io` or `tokio` feature.
+#[cfg(any(feature = "futures-io", feature = "tokio"))]
+pub mod async_io;
+/// Checksums that detect corrupted data.
+pub mod checksum;
+/// Self-describing containers of SiBOR payloads.
+pub mod container;
 /// Deserialization types and functions.
 pub mod de;
 /// Error types and functions.
 pub mod error;
+/// Checksummed frames that detect corrupted payloads before they are decoded.
+pub mod frame;
+/// Scatter-gather serialization that references large byte strings instead of copying them.
+pub mod gather;
+/// Hashing of encoded values without buffering them.
+pub mod hash;
+/// Order-preserving encoding of values as keys for sorted key-value stores.
+pub mod key;
+/// Append-only log files of records, with an index for random access.
+pub mod log;
+/// Options that change how values are encoded.
+pub mod options;
+/// Paths that locate values nested inside other values.
+pub mod path;
+/// Profiling of encoded sizes by field path and primitive kind.
+pub mod profile;
+/// Embedded payloads that are kept encoded until they are needed.
+pub mod raw;
+/// Request and response messages for remote procedure calls.
+pub mod rpc;
+/// Runtime schemas and schema compatibility checks.
+pub mod schema;
 /// Serialization types and functions.
 pub mod ser;
+/// Sets, marked with `#[serde(with = "serde_sibor::set")]` so that canonical encoding
+/// sorts their elements.
+pub mod set;
+/// Refcounted zero-copy decoding from, and encoding into, the buffers of the `bytes` crate,
+/// with the `bytes` feature.
+#[cfg(feature = "bytes")]
+pub mod shared;
+/// Streams of records with periodic sync points, which readers use to skip corrupted data.
+pub mod sync;
+/// Tracing of the bytes produced by each value, and annotated hex dumps.
+pub mod trace;
+/// Schema-driven transcoding between SiBOR and other serde formats.
+pub mod transcode;
+/// Dynamically typed values.
+pub mod value;
+/// Versioned values and migrations between versions.
+pub mod versioned;
+/// Lazy, schema-driven views of encoded values.
+pub mod view;
 
 /// Tests for the crate.
 #[cfg(test)]
@@ -29,7 +80,13 @@ mod tests;
 
 pub use crate::de::Deserializer;
 pub use crate::error::Error;
+pub use crate::options::Options;
+pub use crate::raw::{Open, Raw, RawSibor};
+pub use crate::schema::Schema;
 pub use crate::ser::Serializer;
+pub use crate::value::Value;
+pub use crate::versioned::Versioned;
+pub use crate::view::View;
 
 /// Get the number of bytes required to encode a value.
 pub fn encoded_size<V>(v: V) -> crate::error::Result<usize>
@@ -77,8 +134,51 @@ where
     Ok(buf)
 }
 
+/// Encode a value into a byte vector with the given options.
+pub fn to_bytes_with_options<V>(
+    v: V,
+    options: crate::options::Options,
+) -> crate::error::Result<Vec<u8>>
+where
+    V: ::serde::Serialize,
+{
+    let mut buf = Vec::<u8>::new();
+    v.serialize(&mut crate::ser::Serializer::with_options(&mut buf, options))?;
+    Ok(buf)
+}
+
+/// Feed the encoding of a value into a hasher, without buffering it.
+///
+/// Values with equal encodings have equal hashes. Use [`hash::Sink`] to feed the
+/// encoding into an incremental digest instead.
+pub fn hash_value<V, H>(v: V, hasher: &mut H) -> crate::error::Result<()>
+where
+    V: ::serde::Serialize,
+    H: ::std::hash::Hasher,
+{
+    hash_value_with_options(v, hasher, crate::options::Options::default())
+}
+
+/// Feed the encoding of a value with the given options into a hasher, without buffering it.
+pub fn hash_value_with_options<V, H>(
+    v: V,
+    hasher: &mut H,
+    options: crate::options::Options,
+) -> crate::error::Result<()>
+where
+    V: ::serde::Serialize,
+    H: ::std::hash::Hasher,
+{
+    let mut sink = crate::hash::hasher(hasher);
+    v.serialize(&mut crate::ser::Serializer::with_options(
+        &mut sink, options,
+    ))?;
+    sink.finish();
+    Ok(())
+}
+
 /// Decode a value from a reader.
-pub fn from_reader<'de, V, R>(r: R) -> crate::error::Result<V>
+pub fn from_reader<V, R>(r: R) -> crate::error::Result<V>
 where
     V: ::serde::de::DeserializeOwned,
     R: ::std::io::Read,
@@ -91,5 +191,18 @@ pub fn from_bytes<'de, V>(buf: &'de [u8]) -> crate::error::Result<V>
 where
     V: ::serde::Deserialize<'de>,
 {
-    V::deserialize(&mut crate::de::Deserializer::new(buf))
+    V::deserialize(&mut crate::de::Deserializer::from_slice(buf))
+}
+
+/// Decode a value from a byte slice that was encoded with the given options.
+pub fn from_bytes_with_options<'de, V>(
+    buf: &'de [u8],
+    options: crate::options::Options,
+) -> crate::error::Result<V>
+where
+    V: ::serde::Deserialize<'de>,
+{
+    V::deserialize(&mut crate::de::Deserializer::from_slice_with_options(
+        buf, options,
+    ))
 }
@@ -0,0 +1,629 @@
+use std::io::{Seek as _, Write as _};
+
+use crate::checksum::Crc32c;
+use crate::error::{Error, Result};
+use crate::options::Options;
+
+/// The magic bytes at the start of every log file.
+pub const MAGIC: [u8; 4] = *b"SLOG";
+
+/// The version of the log format written by [`LogWriter`].
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The number of records between index blocks, unless another interval is given.
+pub const DEFAULT_INDEX_INTERVAL: u32 = 1024;
+
+/// The length of the header: the magic bytes, the version, the options and the index interval.
+const HEADER_LEN: u64 = 4 + 4 + 8 + 4;
+
+/// The length of the tag, length and checksum before the body of every entry.
+const ENTRY_HEADER_LEN: u64 = 1 + 4 + 4;
+
+/// The length of the body of the footer: the offset of the last index block and the number of records.
+const FOOTER_BODY_LEN: u64 = 8 + 8;
+
+/// The length of the body of an index block before the record offsets: the number of the
+/// first record and the offset of the previous index block.
+const INDEX_HEADER_LEN: usize = 8 + 8;
+
+/// The offset of a missing index block.
+const NONE: u64 = u64::MAX;
+
+/// The tag of an entry that holds a record.
+const TAG_RECORD: u8 = 0;
+
+/// The tag of an entry that holds an index block.
+const TAG_INDEX: u8 = 1;
+
+/// The tag of the entry that ends a finished log file.
+const TAG_FOOTER: u8 = 2;
+
+/// A writer for append-only log files of SiBOR records.
+///
+/// A log file starts with a header, followed by entries. Every entry is a tag, a 32-bit
+/// little-endian length and a CRC32C checksum of the tag, the length and the body,
+/// followed by the body. Records are entries whose body is an encoded value. After every
+/// index interval of records, and when the writer is finished, an index block with the
+/// offsets of the records since the previous index block is written. [`LogWriter::finish`]
+/// then writes a footer with the offset of the last index block and the number of records.
+///
+/// A log file that was not finished, for example after a crash, has no footer and may end
+/// with a torn entry. [`LogWriter::open`] truncates the torn entry and keeps appending, and
+/// [`LogReader`] reads the entries up to it.
+pub struct LogWriter {
+    file: ::std::io::BufWriter<::std::fs::File>,
+    options: Options,
+    interval: u32,
+    position: u64,
+    records: u64,
+    pending: Vec<u64>,
+    last_index: u64,
+    truncated: u64,
+}
+
+impl LogWriter {
+    /// Create a new log file, replacing any existing file at the given path.
+    pub fn create<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<::std::path::Path>,
+    {
+        Self::create_with_options(path, Options::default(), DEFAULT_INDEX_INTERVAL)
+    }
+
+    /// Create a new log file whose records are encoded with the given options, and which
+    /// writes an index block after the given number of records.
+    pub fn create_with_options<P>(path: P, options: Options, interval: u32) -> Result<Self>
+    where
+        P: AsRef<::std::path::Path>,
+    {
+        if interval == 0 {
+            return Err(Error::Invalid("log: index interval of 0".into()));
+        }
+        let file = ::std::fs::File::create(path).map_err(Error::Io)?;
+        let mut file = ::std::io::BufWriter::new(file);
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        header.extend_from_slice(&options.to_bits().to_le_bytes());
+        header.extend_from_slice(&interval.to_le_bytes());
+        file.write_all(&header).map_err(Error::Io)?;
+        Ok(Self {
+            file,
+            options,
+            interval,
+            position: HEADER_LEN,
+            records: 0,
+            pending: Vec::new(),
+            last_index: NONE,
+            truncated: 0,
+        })
+    }
+
+    /// Open an existing log file to append records to it.
+    ///
+    /// The footer of a finished log file is removed, and is written again by
+    /// [`LogWriter::finish`]. If the log file was not finished, it is scanned, and a torn
+    /// entry at its end is truncated. Corrupted entries before that are an error, and the
+    /// file is left as it is.
+    ///
+    /// Records are encoded with the options stored in the header, which don't include
+    /// [`Options::field_numbers`] or [`Options::canonical`]. Logs that use them must be
+    /// opened with [`LogWriter::open_with_options`].
+    pub fn open<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<::std::path::Path>,
+    {
+        Self::open_inner(path, None)
+    }
+
+    /// Open an existing log file to append records encoded with the given options.
+    /// Returns an error if they don't match the options stored in the header.
+    pub fn open_with_options<P>(path: P, options: Options) -> Result<Self>
+    where
+        P: AsRef<::std::path::Path>,
+    {
+        Self::open_inner(path, Some(options))
+    }
+
+    fn open_inner<P>(path: P, options: Option<Options>) -> Result<Self>
+    where
+        P: AsRef<::std::path::Path>,
+    {
+        let mut file = ::std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(Error::Io)?;
+        let len = file.metadata().map_err(Error::Io)?.len();
+        let header = read_header(&mut file, options)?;
+        let (end, records, pending, last_index) = match read_footer(&mut file, len)? {
+            Some((last_index, records)) => (
+                len - ENTRY_HEADER_LEN - FOOTER_BODY_LEN,
+                records,
+                Vec::new(),
+                last_index,
+            ),
+            None => {
+                let scan = scan(&mut file, len)?;
+                let last_index = scan.directory.last().map_or(NONE, |(_, offset)| *offset);
+                (scan.end, scan.records, scan.tail, last_index)
+            }
+        };
+        file.set_len(end).map_err(Error::Io)?;
+        file.seek(::std::io::SeekFrom::Start(end))
+            .map_err(Error::Io)?;
+        Ok(Self {
+            file: ::std::io::BufWriter::new(file),
+            options: header.options,
+            interval: header.interval,
+            position: end,
+            records,
+            pending,
+            last_index,
+            truncated: len - end,
+        })
+    }
+
+    /// Get the options that records are encoded with.
+    pub fn options(&self) -> Options {
+        self.options
+    }
+
+    /// Get the number of records in the log.
+    pub fn len(&self) -> u64 {
+        self.records
+    }
+
+    /// Check whether the log has no records.
+    pub fn is_empty(&self) -> bool {
+        self.records == 0
+    }
+
+    /// Get the number of bytes that were removed from the end of the file when it was
+    /// opened, including the footer of a finished log file.
+    pub fn truncated(&self) -> u64 {
+        self.truncated
+    }
+
+    /// Append a record, and return its number.
+    pub fn append<V>(&mut self, v: V) -> Result<u64>
+    where
+        V: ::serde::Serialize,
+    {
+        let buf = crate::to_bytes_with_options(v, self.options)?;
+        self.append_raw(&buf)
+    }
+
+    /// Append a record that has already been encoded, and return its number.
+    pub fn append_raw(&mut self, buf: &[u8]) -> Result<u64> {
+        let offset = self.write_entry(TAG_RECORD, buf)?;
+        self.pending.push(offset);
+        self.records += 1;
+        if self.pending.len() == self.interval as usize {
+            self.write_index()?;
+        }
+        Ok(self.records - 1)
+    }
+
+    /// Flush the buffered entries to the file.
+    pub fn flush(&mut self) -> Result<()> {
+        self.file.flush().map_err(Error::Io)
+    }
+
+    /// Write the index block of the remaining records and the footer, and sync the file.
+    pub fn finish(mut self) -> Result<()> {
+        if !self.pending.is_empty() {
+            self.write_index()?;
+        }
+        let mut footer = Vec::with_capacity(FOOTER_BODY_LEN as usize);
+        footer.extend_from_slice(&self.last_index.to_le_bytes());
+        footer.extend_from_slice(&self.records.to_le_bytes());
+        self.write_entry(TAG_FOOTER, &footer)?;
+        self.flush()?;
+        self.file.get_ref().sync_all().map_err(Error::Io)
+    }
+
+    fn write_index(&mut self) -> Result<()> {
+        let mut body = Vec::with_capacity(INDEX_HEADER_LEN + 8 * self.pending.len());
+        body.extend_from_slice(&(self.records - self.pending.len() as u64).to_le_bytes());
+        body.extend_from_slice(&self.last_index.to_le_bytes());
+        for offset in self.pending.drain(..) {
+            body.extend_from_slice(&offset.to_le_bytes());
+        }
+        self.last_index = self.write_entry(TAG_INDEX, &body)?;
+        Ok(())
+    }
+
+    fn write_entry(&mut self, tag: u8, body: &[u8]) -> Result<u64> {
+        let len = u32::try_from(body.len())
+            .map_err(|_| Error::Invalid(format!("log: entry of {} bytes", body.len())))?;
+        let mut crc = Crc32c::new();
+        crc.update(&[tag]);
+        crc.update(&len.to_le_bytes());
+        crc.update(body);
+        let mut header = [0u8; ENTRY_HEADER_LEN as usize];
+        header[0] = tag;
+        header[1..5].copy_from_slice(&len.to_le_bytes());
+        header[5..9].copy_from_slice(&crc.finish().to_le_bytes());
+        self.file.write_all(&header).map_err(Error::Io)?;
+        self.file.write_all(body).map_err(Error::Io)?;
+        let offset = self.position;
+        self.position += ENTRY_HEADER_LEN + body.len() as u64;
+        Ok(offset)
+    }
+}
+
+/// A reader for log files written by [`LogWriter`], with sequential iteration and
+/// random access by record number.
+pub struct LogReader<R> {
+    reader: R,
+    options: Options,
+    directory: Vec<(u64, u64)>,
+    tail: Vec<u64>,
+    records: u64,
+    end: u64,
+    torn: u64,
+}
+
+impl<R> LogReader<R>
+where
+    R: ::std::io::Read + ::std::io::Seek,
+{
+    /// Create a new log reader, and read the header and the index of the log.
+    ///
+    /// If the log file was not finished, it is scanned instead, up to a torn entry at its
+    /// end. Corrupted entries before that are an error. Records are decoded with the options stored in the header, so
+    /// logs that use [`Options::field_numbers`] or [`Options::canonical`] must be read
+    /// with [`LogReader::with_options`].
+    pub fn new(reader: R) -> Result<Self> {
+        Self::new_inner(reader, None)
+    }
+
+    /// Create a new log reader whose records are decoded with the given options.
+    /// Returns an error if they don't match the options stored in the header.
+    pub fn with_options(reader: R, options: Options) -> Result<Self> {
+        Self::new_inner(reader, Some(options))
+    }
+
+    fn new_inner(mut reader: R, options: Option<Options>) -> Result<Self> {
+        let len = reader
+            .seek(::std::io::SeekFrom::End(0))
+            .map_err(Error::Io)?;
+        reader
+            .seek(::std::io::SeekFrom::Start(0))
+            .map_err(Error::Io)?;
+        let header = read_header(&mut reader, options)?;
+        let (directory, tail, records, end) = match read_footer(&mut reader, len)? {
+            Some((last_index, records)) => {
+                let end = len - ENTRY_HEADER_LEN - FOOTER_BODY_LEN;
+                let directory = read_directory(&mut reader, last_index, records, end)?;
+                (directory, Vec::new(), records, end)
+            }
+            None => {
+                let scan = scan(&mut reader, len)?;
+                (scan.directory, scan.tail, scan.records, scan.end)
+            }
+        };
+        Ok(Self {
+            reader,
+            options: header.options,
+            directory,
+            tail,
+            records,
+            end,
+            torn: len - end,
+        })
+    }
+
+    /// Get the options that records are encoded with.
+    pub fn options(&self) -> Options {
+        self.options
+    }
+
+    /// Get the number of records in the log.
+    pub fn len(&self) -> u64 {
+        self.records
+    }
+
+    /// Check whether the log has no records.
+    pub fn is_empty(&self) -> bool {
+        self.records == 0
+    }
+
+    /// Get the number of bytes at the end of the file that are not part of the log,
+    /// including the footer of a finished log file.
+    pub fn torn(&self) -> u64 {
+        self.torn
+    }
+
+    /// Read the record with the given number without decoding it.
+    /// Returns `None` if the log has no such record.
+    pub fn get_raw(&mut self, n: u64) -> Result<Option<Vec<u8>>> {
+        if n >= self.records {
+            return Ok(None);
+        }
+        let indexed = self.records - self.tail.len() as u64;
+        let offset = match n.checked_sub(indexed) {
+            Some(i) => self.tail[i as usize],
+            None => {
+                let block = self
+                    .directory
+                    .partition_point(|(first, _)| *first <= n)
+                    .checked_sub(1)
+                    .ok_or_else(|| Error::Invalid(format!("log: no index block for record {n}")))?;
+                let (first, offset) = self.directory[block];
+                let (tag, body) = read_entry(&mut self.reader, offset, self.end)?;
+                let at = INDEX_HEADER_LEN + 8 * (n - first) as usize;
+                match (tag, body.get(at..at + 8)) {
+                    (TAG_INDEX, Some(offset)) => le_u64(offset),
+                    _ => return Err(Error::Invalid(format!("log: index block at {offset}"))),
+                }
+            }
+        };
+        match read_entry(&mut self.reader, offset, self.end)? {
+            (TAG_RECORD, body) => Ok(Some(body)),
+            _ => Err(Error::Invalid(format!("log: record at {offset}"))),
+        }
+    }
+
+    /// Read and decode the record with the given number.
+    /// Returns `None` if the log has no such record.
+    pub fn get<V>(&mut self, n: u64) -> Result<Option<V>>
+    where
+        V: ::serde::de::DeserializeOwned,
+    {
+        match self.get_raw(n)? {
+            Some(raw) => crate::from_bytes_with_options(&raw, self.options).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Iterate over the records in order, without decoding them.
+    pub fn records(&mut self) -> Records<'_, R> {
+        Records {
+            position: HEADER_LEN,
+            log: self,
+        }
+    }
+
+    /// Iterate over the records in order, and decode them.
+    pub fn iter<V>(&mut self) -> impl Iterator<Item = Result<V>> + '_
+    where
+        V: ::serde::de::DeserializeOwned,
+    {
+        let options = self.options;
+        self.records()
+            .map(move |raw| crate::from_bytes_with_options(&raw?, options))
+    }
+}
+
+/// An iterator over the records of a log, in order.
+pub struct Records<'a, R> {
+    log: &'a mut LogReader<R>,
+    position: u64,
+}
+
+impl<'a, R> Iterator for Records<'a, R>
+where
+    R: ::std::io::Read + ::std::io::Seek,
+{
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.position < self.log.end {
+            let (tag, body) = match read_entry(&mut self.log.reader, self.position, self.log.end) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.position = self.log.end;
+                    return Some(Err(e));
+                }
+            };
+            self.position += ENTRY_HEADER_LEN + body.len() as u64;
+            if tag == TAG_RECORD {
+                return Some(Ok(body));
+            }
+        }
+        None
+    }
+}
+
+/// The header of a log file.
+struct Header {
+    options: Options,
+    interval: u32,
+}
+
+/// The entries of a log file found by scanning it.
+struct Scan {
+    directory: Vec<(u64, u64)>,
+    tail: Vec<u64>,
+    records: u64,
+    end: u64,
+}
+
+fn le_u64(buf: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&buf[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn le_u32(buf: &[u8]) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&buf[..4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_at<R>(reader: &mut R, offset: u64, len: usize) -> Result<Vec<u8>>
+where
+    R: ::std::io::Read + ::std::io::Seek,
+{
+    reader
+        .seek(::std::io::SeekFrom::Start(offset))
+        .map_err(Error::Io)?;
+    let mut buf = vec![0u8; len];
+    reader.read_exact(&mut buf).map_err(Error::Io)?;
+    Ok(buf)
+}
+
+/// Read the header of a log file, and check the given options against it if any.
+fn read_header<R>(reader: &mut R, options: Option<Options>) -> Result<Header>
+where
+    R: ::std::io::Read + ::std::io::Seek,
+{
+    let buf = read_at(reader, 0, HEADER_LEN as usize)?;
+    if buf[..4] != MAGIC {
+        return Err(Error::Invalid("log magic".into()));
+    }
+    let version = le_u32(&buf[4..]);
+    if version != FORMAT_VERSION {
+        return Err(Error::Unsupported(format!("log version {version}")));
+    }
+    let interval = le_u32(&buf[16..]);
+    if interval == 0 {
+        return Err(Error::Invalid("log: index interval of 0".into()));
+    }
+    let bits = le_u64(&buf[8..]);
+    let options = match options {
+        Some(options) => options.check_bits(bits)?,
+        None => Options::from_bits(bits)?,
+    };
+    Ok(Header { options, interval })
+}
+
+/// Read the index blocks of a finished log file, from the last one back to the first.
+/// Returns the number of the first record and the offset of every index block, in order.
+///
+/// Every index block must be intact and precede the one that points to it. Every index
+/// block holds at least one record, so a log has at most as many index blocks as records.
+/// The index interval doesn't bound them, since every time a log is finished, the records
+/// since the last index block get an index block of their own.
+fn read_directory<R>(
+    reader: &mut R,
+    last_index: u64,
+    records: u64,
+    end: u64,
+) -> Result<Vec<(u64, u64)>>
+where
+    R: ::std::io::Read + ::std::io::Seek,
+{
+    let max_blocks = records;
+    let mut directory = Vec::new();
+    let mut offset = last_index;
+    while offset != NONE {
+        if directory.len() as u64 == max_blocks {
+            return Err(Error::Invalid(format!(
+                "log: more than {max_blocks} index blocks"
+            )));
+        }
+        let (first, previous) = match read_entry(reader, offset, end)? {
+            (TAG_INDEX, body) if body.len() >= INDEX_HEADER_LEN => {
+                (le_u64(&body[..8]), le_u64(&body[8..]))
+            }
+            _ => return Err(Error::Invalid(format!("log: index block at {offset}"))),
+        };
+        if previous != NONE && previous >= offset {
+            return Err(Error::Invalid(format!("log: index block at {offset}")));
+        }
+        directory.push((first, offset));
+        offset = previous;
+    }
+    directory.reverse();
+    Ok(directory)
+}
+
+/// Read the entry at the given offset, which must end before the given end of the log.
+/// Returns an error if the entry is torn or its checksum doesn't match.
+fn read_entry<R>(reader: &mut R, offset: u64, end: u64) -> Result<(u8, Vec<u8>)>
+where
+    R: ::std::io::Read + ::std::io::Seek,
+{
+    if offset.saturating_add(ENTRY_HEADER_LEN) > end {
+        return Err(Error::Invalid(format!("log: torn entry at {offset}")));
+    }
+    let header = read_at(reader, offset, ENTRY_HEADER_LEN as usize)?;
+    let len = le_u32(&header[1..]);
+    if offset + ENTRY_HEADER_LEN + len as u64 > end {
+        return Err(Error::Invalid(format!("log: torn entry at {offset}")));
+    }
+    let mut body = vec![0u8; len as usize];
+    reader.read_exact(&mut body).map_err(Error::Io)?;
+    let mut crc = Crc32c::new();
+    crc.update(&header[..5]);
+    crc.update(&body);
+    let expected = le_u32(&header[5..]);
+    if crc.finish() != expected {
+        return Err(Error::Checksum {
+            expected,
+            actual: crc.finish(),
+        });
+    }
+    Ok((header[0], body))
+}
+
+/// Check whether the entry at the given offset runs past the given end of the log, as
+/// the last entry does when writing it was interrupted.
+fn is_torn<R>(reader: &mut R, offset: u64, end: u64) -> Result<bool>
+where
+    R: ::std::io::Read + ::std::io::Seek,
+{
+    if offset.saturating_add(ENTRY_HEADER_LEN) > end {
+        return Ok(true);
+    }
+    let header = read_at(reader, offset, ENTRY_HEADER_LEN as usize)?;
+    Ok(offset + ENTRY_HEADER_LEN + le_u32(&header[1..]) as u64 > end)
+}
+
+/// Read the footer of a finished log file of the given length.
+/// Returns the offset of the last index block and the number of records.
+fn read_footer<R>(reader: &mut R, len: u64) -> Result<Option<(u64, u64)>>
+where
+    R: ::std::io::Read + ::std::io::Seek,
+{
+    let Some(offset) = len.checked_sub(ENTRY_HEADER_LEN + FOOTER_BODY_LEN) else {
+        return Ok(None);
+    };
+    if offset < HEADER_LEN {
+        return Ok(None);
+    }
+    match read_entry(reader, offset, len) {
+        Ok((TAG_FOOTER, body)) if body.len() == FOOTER_BODY_LEN as usize => {
+            Ok(Some((le_u64(&body[..8]), le_u64(&body[8..]))))
+        }
+        Ok(_) | Err(Error::Invalid(_) | Error::Checksum { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Scan the entries of a log file of the given length, up to a torn entry at its end.
+/// Returns an error if any other entry is corrupted.
+fn scan<R>(reader: &mut R, len: u64) -> Result<Scan>
+where
+    R: ::std::io::Read + ::std::io::Seek,
+{
+    let mut scan = Scan {
+        directory: Vec::new(),
+        tail: Vec::new(),
+        records: 0,
+        end: HEADER_LEN,
+    };
+    while scan.end < len {
+        let (tag, body) = match read_entry(reader, scan.end, len) {
+            Ok(entry) => entry,
+            Err(Error::Invalid(_)) if is_torn(reader, scan.end, len)? => break,
+            Err(e) => return Err(e),
+        };
+        match tag {
+            TAG_RECORD => {
+                scan.tail.push(scan.end);
+                scan.records += 1;
+            }
+            TAG_INDEX if body.len() >= INDEX_HEADER_LEN => {
+                scan.directory.push((le_u64(&body[..8]), scan.end));
+                scan.tail.clear();
+            }
+            _ => return Err(Error::Invalid(format!("log: entry at {}", scan.end))),
+        }
+        scan.end += ENTRY_HEADER_LEN + body.len() as u64;
+    }
+    Ok(scan)
+}
@@ -0,0 +1,242 @@
+use std::borrow::Cow;
+
+use crate::error::{Error, Result};
+
+/// How the fields of structs are encoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum StructEncoding {
+    /// Fields are concatenated by position, like a tuple.
+    #[default]
+    Tuple,
+    /// Fields are concatenated by position, after the number of fields, and the whole
+    /// body is prefixed with its length in bytes.
+    ///
+    /// Readers fill fields that are missing at the end of the body from their
+    /// `#[serde(default)]`, and skip unknown fields at the end of the body, so fields
+    /// can be appended to a struct without breaking old data or old readers.
+    LengthPrefixed,
+    /// Fields are written in order, each prefixed with a key made from the field's
+    /// number and wire type, and the body is prefixed with the number of fields.
+    ///
+    /// This is similar to protobuf: the wire type says whether the value is a single
+    /// varint or length-prefixed, so readers can skip fields with unknown numbers, and
+    /// fill fields that are missing from their `#[serde(default)]`. Fields are numbered
+    /// by their index in the struct, unless [`Options::field_numbers`] says otherwise,
+    /// so fields can only be reordered or removed safely if they have fixed numbers.
+    Numbered,
+}
+
+/// How the payloads of enum variants are encoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum EnumEncoding {
+    /// The payload follows the variant index.
+    #[default]
+    Plain,
+    /// The payload follows the variant index, prefixed with its length in bytes.
+    ///
+    /// Readers can skip the payloads of variants they don't know, so variants can be
+    /// appended to an enum without breaking old readers that have a `#[serde(other)]`
+    /// variant, or that decode the enum as an [`crate::raw::Open`].
+    LengthPrefixed,
+}
+
+/// The rules of canonical encoding, in which equal values always produce the same bytes.
+///
+/// In canonical mode, the entries of maps and of sets marked with [`crate::set`] are
+/// sorted by the bytes of their encoded keys, the fields of numbered structs are sorted
+/// by their numbers, and deserializers reject input that is not canonical: overlong
+/// varints, unsorted or duplicate keys and field numbers, unread bytes at the end of
+/// length-prefixed values, and floats that should have been normalized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Canonical {
+    /// Encode every NaN as the same quiet NaN, dropping its sign and payload.
+    pub normalize_nan: bool,
+    /// Encode negative zero as positive zero.
+    pub normalize_negative_zero: bool,
+}
+
+impl Default for Canonical {
+    fn default() -> Self {
+        Self {
+            normalize_nan: true,
+            normalize_negative_zero: true,
+        }
+    }
+}
+
+impl Canonical {
+    /// Normalize a float according to these rules.
+    pub(crate) fn normalize(&self, v: f64) -> f64 {
+        match v {
+            v if v.is_nan() && self.normalize_nan => f64::NAN,
+            v if v == 0.0 && self.normalize_negative_zero => 0.0,
+            v => v,
+        }
+    }
+}
+
+/// A function that assigns fixed numbers to the fields of structs for
+/// [`StructEncoding::Numbered`].
+///
+/// It is called with the name of the struct and the serialized name of the field,
+/// and returns `None` for fields that are numbered by their index. The fields of
+/// struct variants are looked up with the name `Enum::Variant`, so schemas of struct
+/// variants should be named the same way. Values of [`crate::Value`]
+/// are encoded without names, so their fields are always numbered by index.
+/// Encoding or decoding a struct with two fields of the same number is an error.
+pub type FieldNumbers = fn(container: &str, field: &str) -> Option<u64>;
+
+/// The wire type of a numbered field whose value is encoded as a single varint.
+pub(crate) const WIRE_VARINT: u64 = 0;
+
+/// The wire type of a numbered field whose value is prefixed with its length.
+pub(crate) const WIRE_LEN: u64 = 2;
+
+/// The largest field number that fits into a field key.
+pub(crate) const MAX_FIELD_NUMBER: u64 = u64::MAX >> 3;
+
+/// Options that change how values are encoded.
+///
+/// Data must be decoded with the same options it was encoded with. The default
+/// options produce the plain SiBOR encoding.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    /// How the fields of structs are encoded.
+    pub struct_encoding: StructEncoding,
+    /// The numbers of fields with [`StructEncoding::Numbered`]. Field numbers are not
+    /// stored in container headers, so readers must be given the same function, for
+    /// example with [`crate::container::ContainerReader::with_options`].
+    pub field_numbers: Option<FieldNumbers>,
+    /// How the payloads of enum variants are encoded.
+    pub enum_encoding: EnumEncoding,
+    /// The rules of canonical encoding, if values must be encoded canonically. Canonical
+    /// mode only restricts the encoding, so it is not stored in container headers.
+    pub canonical: Option<Canonical>,
+}
+
+impl PartialEq for Options {
+    fn eq(&self, other: &Self) -> bool {
+        let field_numbers = match (self.field_numbers, other.field_numbers) {
+            (Some(a), Some(b)) => a as usize == b as usize,
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        self.struct_encoding == other.struct_encoding
+            && field_numbers
+            && self.enum_encoding == other.enum_encoding
+            && self.canonical == other.canonical
+    }
+}
+
+impl Eq for Options {}
+
+impl ::std::hash::Hash for Options {
+    fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
+        // Function pointers are only compared by address, which is not stable
+        // enough to hash, so equal options always hash the same without them.
+        self.struct_encoding.hash(state);
+        self.enum_encoding.hash(state);
+        self.canonical.hash(state);
+    }
+}
+
+impl Options {
+    const STRUCT_ENCODING_MASK: u64 = 0b11;
+    const ENUM_ENCODING_MASK: u64 = 0b100;
+
+    /// Get the options as the bit flags stored in container headers.
+    pub fn to_bits(&self) -> u64 {
+        let struct_encoding = match self.struct_encoding {
+            StructEncoding::Tuple => 0,
+            StructEncoding::LengthPrefixed => 1,
+            StructEncoding::Numbered => 2,
+        };
+        let enum_encoding = match self.enum_encoding {
+            EnumEncoding::Plain => 0,
+            EnumEncoding::LengthPrefixed => Self::ENUM_ENCODING_MASK,
+        };
+        struct_encoding | enum_encoding
+    }
+
+    /// Get the options from the bit flags stored in container headers.
+    pub fn from_bits(bits: u64) -> Result<Self> {
+        let struct_encoding = match bits & Self::STRUCT_ENCODING_MASK {
+            0 => StructEncoding::Tuple,
+            1 => StructEncoding::LengthPrefixed,
+            2 => StructEncoding::Numbered,
+            _ => return Err(Error::Unsupported(format!("options {bits:#x}"))),
+        };
+        let enum_encoding = match bits & Self::ENUM_ENCODING_MASK {
+            0 => EnumEncoding::Plain,
+            _ => EnumEncoding::LengthPrefixed,
+        };
+        if bits & !(Self::STRUCT_ENCODING_MASK | Self::ENUM_ENCODING_MASK) != 0 {
+            return Err(Error::Unsupported(format!("options {bits:#x}")));
+        }
+        Ok(Self {
+            struct_encoding,
+            enum_encoding,
+            ..Self::default()
+        })
+    }
+
+    /// Check that these options are stored as the given bit flags, as read from a
+    /// container header. Returns these options, including the parts that are not stored.
+    pub(crate) fn check_bits(self, bits: u64) -> Result<Self> {
+        if self.to_bits() != bits {
+            return Err(Error::Invalid(format!(
+                "options {:#x} don't match the header's options {bits:#x}",
+                self.to_bits()
+            )));
+        }
+        Ok(self)
+    }
+
+    /// Get the number of a field, given the name from [`field_container`] of the struct
+    /// it belongs to.
+    pub(crate) fn field_number(&self, container: &str, index: usize, field: &str) -> Result<u64> {
+        let number = self
+            .field_numbers
+            .and_then(|numbers| numbers(container, field))
+            .unwrap_or(index as u64);
+        if number > MAX_FIELD_NUMBER {
+            return Err(Error::Invalid(format!("field number: {number}")));
+        }
+        Ok(number)
+    }
+
+    /// Get the numbers of the fields of a struct, or of a struct variant if `variant` is
+    /// given, in order. Returns an error if two fields have the same number.
+    pub(crate) fn field_numbers_of<'f>(
+        &self,
+        container: &str,
+        variant: Option<&str>,
+        fields: impl IntoIterator<Item = &'f str>,
+    ) -> Result<Vec<u64>> {
+        let container = field_container(container, variant);
+        let mut numbers = Vec::new();
+        for (index, field) in fields.into_iter().enumerate() {
+            let number = self.field_number(&container, index, field)?;
+            if numbers.contains(&number) {
+                return Err(duplicate_field_number(&container, number));
+            }
+            numbers.push(number);
+        }
+        Ok(numbers)
+    }
+}
+
+/// Get the name that the fields of a struct, or of a struct variant if `variant` is given,
+/// are numbered under.
+pub(crate) fn field_container<'a>(container: &'a str, variant: Option<&str>) -> Cow<'a, str> {
+    match variant {
+        Some(variant) => Cow::Owned(format!("{container}::{variant}")),
+        None => Cow::Borrowed(container),
+    }
+}
+
+/// The error for two fields of a struct that have the same number.
+pub(crate) fn duplicate_field_number(container: &str, number: u64) -> Error {
+    Error::Invalid(format!(
+        "struct {container}: more than one field numbered {number}"
+    ))
+}
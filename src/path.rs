@@ -0,0 +1,137 @@
+/// A single step in a [`Path`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Segment {
+    /// A named struct field.
+    Field(String),
+    /// A positional element of a tuple.
+    Element(usize),
+    /// An element of a sequence. `None` stands for "any element".
+    Index(Option<usize>),
+    /// A named enum variant.
+    Variant(String),
+}
+
+/// A location inside a value, such as `header.id` or `items[42].name`.
+///
+/// Struct fields, tuple elements and enum variants are separated by dots,
+/// and sequence elements are written in brackets. The root path is empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Path {
+    segments: Vec<Segment>,
+}
+
+impl Path {
+    /// Create a new, empty path that refers to the root of a value.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the segments of this path.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments[..]
+    }
+
+    /// Check whether this path refers to the root of a value.
+    pub fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Append a segment to the end of this path.
+    pub fn push(&mut self, segment: Segment) {
+        self.segments.push(segment);
+    }
+
+    /// Remove the last segment of this path, if any.
+    pub fn pop(&mut self) -> Option<Segment> {
+        self.segments.pop()
+    }
+
+    /// Get a copy of this path where every sequence index is replaced by "any element",
+    /// so that `items[3].name` becomes `items[].name`.
+    pub fn generalized(&self) -> Self {
+        Self {
+            segments: self
+                .segments
+                .iter()
+                .map(|segment| match segment {
+                    Segment::Index(_) => Segment::Index(None),
+                    segment => segment.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Set the sequence index of the segment at the given depth.
+    pub(crate) fn set_index(&mut self, depth: usize, index: usize) {
+        self.segments[depth] = Segment::Index(Some(index));
+    }
+
+    /// Get a copy of this path with one more segment at the end.
+    pub fn join(&self, segment: Segment) -> Self {
+        let mut joined = self.clone();
+        joined.push(segment);
+        joined
+    }
+}
+
+impl ::std::fmt::Display for Path {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            let sep = if i == 0 { "" } else { "." };
+            match segment {
+                Segment::Field(name) | Segment::Variant(name) => write!(f, "{sep}{name}")?,
+                Segment::Element(index) => write!(f, "{sep}{index}")?,
+                Segment::Index(Some(index)) => write!(f, "[{index}]")?,
+                Segment::Index(None) => write!(f, "[]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ::std::str::FromStr for Path {
+    type Err = crate::error::Error;
+
+    /// Parse a path such as `header.id` or `items[42].name`.
+    ///
+    /// Names are parsed as struct fields, numbers as tuple elements, and `[]` as any
+    /// element of a sequence. An empty string or `<root>` is the root path.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || crate::error::Error::Invalid(format!("path: {s:?}"));
+
+        let mut path = Self::new();
+        if s.is_empty() || s == "<root>" {
+            return Ok(path);
+        }
+        let mut rest = s;
+        let mut first = true;
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('[') {
+                let (index, after) = after.split_once(']').ok_or_else(invalid)?;
+                let index = match index {
+                    "" => None,
+                    index => Some(index.parse().map_err(|_| invalid())?),
+                };
+                path.push(Segment::Index(index));
+                rest = after;
+            } else {
+                let name = match first {
+                    true => rest,
+                    false => rest.strip_prefix('.').ok_or_else(invalid)?,
+                };
+                let end = name.find(['.', '[']).unwrap_or(name.len());
+                let (name, after) = name.split_at(end);
+                if name.is_empty() {
+                    return Err(invalid());
+                }
+                path.push(match name.parse() {
+                    Ok(index) => Segment::Element(index),
+                    Err(_) => Segment::Field(name.to_string()),
+                });
+                rest = after;
+            }
+            first = false;
+        }
+        Ok(path)
+    }
+}
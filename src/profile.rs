@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+
+use crate::de::Deserializer;
+use crate::error::Result;
+use crate::options::Options;
+use crate::path::Path;
+use crate::schema::Schema;
+use crate::trace::{Kind, Span, Trace};
+
+/// Aggregate statistics about the bytes spent on one kind of thing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// The total number of encoded bytes.
+    pub bytes: usize,
+    /// The number of primitives that contributed to the total.
+    pub count: usize,
+}
+
+impl Usage {
+    fn add(&mut self, bytes: usize) {
+        self.bytes += bytes;
+        self.count += 1;
+    }
+}
+
+/// A profiler that measures where the encoded bytes of many values go.
+///
+/// Bytes are aggregated by field path, with sequence indices collapsed so that
+/// every element of `points` is counted under `points[]`, and by primitive kind.
+/// The profiler also keeps a histogram of varint lengths for each kind, where the
+/// varint of strings and byte strings is their length prefix.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    options: Options,
+    values: usize,
+    total: usize,
+    paths: BTreeMap<(Path, Kind), Usage>,
+    kinds: BTreeMap<Kind, Usage>,
+    varints: BTreeMap<Kind, BTreeMap<usize, usize>>,
+}
+
+impl Profiler {
+    /// Create a new, empty profiler.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new, empty profiler for values encoded with the given options.
+    pub fn with_options(options: Options) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Encode a value and add its bytes to the profile.
+    pub fn add<V>(&mut self, v: V) -> Result<()>
+    where
+        V: ::serde::Serialize,
+    {
+        let mut ser = crate::ser::Serializer::with_options(Vec::<u8>::new(), self.options);
+        ser.enable_trace();
+        v.serialize(&mut ser)?;
+        let spans = ser.take_trace().map(Trace::into_spans).unwrap_or_default();
+        self.add_spans(&ser.into_inner(), &spans);
+        Ok(())
+    }
+
+    /// Decode an encoded value with the given schema and add its bytes to the profile.
+    pub fn add_bytes(&mut self, schema: &Schema, buf: &[u8]) -> Result<()> {
+        let spans = crate::trace::trace_bytes_with_options(schema, buf, self.options)?;
+        self.add_spans(buf, &spans);
+        Ok(())
+    }
+
+    /// Add traced spans of an encoded value to the profile.
+    pub fn add_spans(&mut self, buf: &[u8], spans: &[Span]) {
+        self.values += 1;
+        for span in spans {
+            let len = span.len();
+            self.total += len;
+            self.paths
+                .entry((span.path.generalized(), span.kind))
+                .or_default()
+                .add(len);
+            self.kinds.entry(span.kind).or_default().add(len);
+
+            let varint_len = match span.kind {
+                Kind::Bool | Kind::Byte => continue,
+                Kind::String | Kind::Bytes => {
+                    let mut rest = buf.get(span.start..span.end).unwrap_or_default();
+                    let before = rest.len();
+                    match Deserializer::new(&mut rest).read_uvarint() {
+                        Ok(_) => before - rest.len(),
+                        Err(_) => continue,
+                    }
+                }
+                _ => len,
+            };
+            *self
+                .varints
+                .entry(span.kind)
+                .or_default()
+                .entry(varint_len)
+                .or_default() += 1;
+        }
+    }
+
+    /// Summarize the profile so far.
+    pub fn report(&self) -> Report {
+        let mut report = Report {
+            values: self.values,
+            total_bytes: self.total,
+            paths: self
+                .paths
+                .iter()
+                .map(|((path, kind), usage)| PathUsage {
+                    path: path.clone(),
+                    kind: *kind,
+                    usage: *usage,
+                })
+                .collect(),
+            kinds: self.kinds.iter().map(|(k, u)| (*k, *u)).collect(),
+            varint_lengths: self
+                .varints
+                .iter()
+                .map(|(kind, histogram)| (*kind, histogram.iter().map(|(l, c)| (*l, *c)).collect()))
+                .collect(),
+        };
+        report.sort_by(SortBy::Bytes);
+        report
+    }
+}
+
+/// The bytes spent on a single field path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathUsage {
+    /// The path, with sequence indices collapsed.
+    pub path: Path,
+    /// The kind of primitive found at the path.
+    pub kind: Kind,
+    /// The bytes spent on the path.
+    pub usage: Usage,
+}
+
+/// The order of the entries in a [`Report`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    /// Largest total bytes first.
+    Bytes,
+    /// Largest number of primitives first.
+    Count,
+    /// Largest average bytes per primitive first.
+    Average,
+    /// Path order.
+    Path,
+}
+
+/// A summary of where the encoded bytes of the profiled values went.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// The number of values profiled.
+    pub values: usize,
+    /// The total number of encoded bytes.
+    pub total_bytes: usize,
+    /// The bytes spent on each field path.
+    pub paths: Vec<PathUsage>,
+    /// The bytes spent on each kind of primitive.
+    pub kinds: Vec<(Kind, Usage)>,
+    /// For each kind of primitive, the number of varints of each length in bytes.
+    pub varint_lengths: Vec<(Kind, Vec<(usize, usize)>)>,
+}
+
+impl Report {
+    /// Sort the path and kind entries of the report.
+    pub fn sort_by(&mut self, by: SortBy) {
+        fn key(usage: &Usage, by: SortBy) -> ::std::cmp::Reverse<(usize, usize)> {
+            let average = usage.bytes.checked_div(usage.count).unwrap_or(0);
+            ::std::cmp::Reverse(match by {
+                SortBy::Bytes | SortBy::Path => (usage.bytes, usage.count),
+                SortBy::Count => (usage.count, usage.bytes),
+                SortBy::Average => (average, usage.bytes),
+            })
+        }
+
+        match by {
+            SortBy::Path => self
+                .paths
+                .sort_by(|a, b| (&a.path, a.kind).cmp(&(&b.path, b.kind))),
+            _ => self.paths.sort_by_key(|entry| key(&entry.usage, by)),
+        }
+        match by {
+            SortBy::Path => self.kinds.sort_by_key(|(kind, _)| *kind),
+            _ => self.kinds.sort_by_key(|(_, usage)| key(usage, by)),
+        }
+    }
+
+    /// Get the share of the total bytes spent on the given usage, as a percentage.
+    pub fn percent(&self, usage: &Usage) -> f64 {
+        match self.total_bytes {
+            0 => 0.0,
+            total => usage.bytes as f64 * 100.0 / total as f64,
+        }
+    }
+}
+
+impl ::std::fmt::Display for Report {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        writeln!(f, "{} values, {} bytes", self.values, self.total_bytes)?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:>10} {:>7} {:>10} {:>8}  {:<8}  path",
+            "bytes", "share", "count", "avg", "kind"
+        )?;
+        for entry in &self.paths {
+            let path = match entry.path.is_root() {
+                true => "<root>".to_string(),
+                false => entry.path.to_string(),
+            };
+            writeln!(
+                f,
+                "{:>10} {:>6.1}% {:>10} {:>8.1}  {:<8}  {path}",
+                entry.usage.bytes,
+                self.percent(&entry.usage),
+                entry.usage.count,
+                entry.usage.bytes as f64 / entry.usage.count.max(1) as f64,
+                entry.kind,
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{:>10} {:>7} {:>10}  kind", "bytes", "share", "count")?;
+        for (kind, usage) in &self.kinds {
+            writeln!(
+                f,
+                "{:>10} {:>6.1}% {:>10}  {kind}",
+                usage.bytes,
+                self.percent(usage),
+                usage.count
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "varint lengths:")?;
+        for (kind, histogram) in &self.varint_lengths {
+            let buckets = Vec::from_iter(histogram.iter().map(|(len, n)| format!("{len}B x{n}")));
+            writeln!(f, "  {kind:<8}  {}", buckets.join(", "))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+use std::borrow::Cow;
+use std::marker::PhantomData;
+
+use crate::de::Deserializer;
+use crate::error::{Error, Result};
+
+/// An embedded SiBOR payload that is kept encoded.
+///
+/// A raw payload is encoded as a length-prefixed byte string that contains the encoded
+/// value. When it is decoded from a byte slice, the payload borrows from the slice, so
+/// passing it through to another message copies nothing but the bytes themselves.
+///
+/// Since a borrowed payload is not [`serde::de::DeserializeOwned`], it cannot be decoded
+/// with [`crate::from_reader`]. Decode with a [`Deserializer`] over the reader instead,
+/// which copies the payload, or read the message into a buffer first.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RawSibor<'a> {
+    bytes: Cow<'a, [u8]>,
+}
+
+impl<'a> RawSibor<'a> {
+    /// Wrap bytes that already contain an encoded value.
+    pub fn new(bytes: impl Into<Cow<'a, [u8]>>) -> Self {
+        Self {
+            bytes: bytes.into(),
+        }
+    }
+
+    /// Encode a value into a new raw payload.
+    pub fn from_value<V>(v: V) -> Result<RawSibor<'static>>
+    where
+        V: ::serde::Serialize,
+    {
+        crate::to_bytes(v).map(RawSibor::new)
+    }
+
+    /// Get the encoded bytes of the payload.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Check whether the payload borrows its bytes.
+    pub fn is_borrowed(&self) -> bool {
+        matches!(self.bytes, Cow::Borrowed(_))
+    }
+
+    /// Copy the bytes of the payload if they are borrowed.
+    pub fn into_owned(self) -> RawSibor<'static> {
+        RawSibor::new(self.bytes.into_owned())
+    }
+
+    /// Unwrap the encoded bytes of the payload.
+    pub fn into_bytes(self) -> Cow<'a, [u8]> {
+        self.bytes
+    }
+
+    /// Decode the payload. The payload must contain exactly one value.
+    pub fn decode<'de, V>(&'de self) -> Result<V>
+    where
+        V: ::serde::Deserialize<'de>,
+    {
+        let mut de = Deserializer::from_slice(&self.bytes);
+        let v = V::deserialize(&mut de)?;
+        match de.into_inner().remaining().len() {
+            0 => Ok(v),
+            n => Err(Error::Invalid(format!("raw payload: {n} trailing bytes"))),
+        }
+    }
+}
+
+impl ::serde::Serialize for RawSibor<'_> {
+    fn serialize<S>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: ::serde::Serializer,
+    {
+        s.serialize_bytes(&self.bytes)
+    }
+}
+
+impl<'de: 'a, 'a> ::serde::Deserialize<'de> for RawSibor<'a> {
+    fn deserialize<D>(d: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        struct RawVisitor;
+
+        impl<'de> ::serde::de::Visitor<'de> for RawVisitor {
+            type Value = RawSibor<'de>;
+
+            fn expecting(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                write!(f, "an embedded SiBOR payload")
+            }
+
+            fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> ::std::result::Result<Self::Value, E>
+            where
+                E: ::serde::de::Error,
+            {
+                Ok(RawSibor::new(v))
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> ::std::result::Result<Self::Value, E>
+            where
+                E: ::serde::de::Error,
+            {
+                Ok(RawSibor::new(v.to_vec()))
+            }
+
+            fn visit_byte_buf<E>(self, v: Vec<u8>) -> ::std::result::Result<Self::Value, E>
+            where
+                E: ::serde::de::Error,
+            {
+                Ok(RawSibor::new(v))
+            }
+        }
+
+        d.deserialize_bytes(RawVisitor)
+    }
+}
+
+/// An embedded SiBOR payload that holds an encoded value of type `T`.
+///
+/// This is a [`RawSibor`] that remembers the type of its value, so that it can be
+/// decoded on demand without naming the type again.
+pub struct Raw<'a, T> {
+    raw: RawSibor<'a>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, T> Raw<'a, T> {
+    /// Encode a value into a new raw payload.
+    pub fn new(v: &T) -> Result<Raw<'static, T>>
+    where
+        T: ::serde::Serialize,
+    {
+        RawSibor::from_value(v).map(Raw::from_raw)
+    }
+
+    /// Wrap an untyped payload that is known to hold a value of type `T`.
+    pub fn from_raw(raw: RawSibor<'a>) -> Self {
+        Self {
+            raw,
+            marker: PhantomData,
+        }
+    }
+
+    /// Get the untyped payload.
+    pub fn as_raw(&self) -> &RawSibor<'a> {
+        &self.raw
+    }
+
+    /// Unwrap the untyped payload.
+    pub fn into_raw(self) -> RawSibor<'a> {
+        self.raw
+    }
+
+    /// Get the encoded bytes of the payload.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.raw.as_bytes()
+    }
+
+    /// Copy the bytes of the payload if they are borrowed.
+    pub fn into_owned(self) -> Raw<'static, T> {
+        Raw::from_raw(self.raw.into_owned())
+    }
+
+    /// Decode the payload.
+    pub fn decode<'de>(&'de self) -> Result<T>
+    where
+        T: ::serde::Deserialize<'de>,
+    {
+        self.raw.decode()
+    }
+}
+
+impl<T> ::std::fmt::Debug for Raw<'_, T> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        f.debug_tuple("Raw").field(&self.raw).finish()
+    }
+}
+
+impl<T> Clone for Raw<'_, T> {
+    fn clone(&self) -> Self {
+        Self::from_raw(self.raw.clone())
+    }
+}
+
+impl<T> PartialEq for Raw<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw == other.raw
+    }
+}
+
+impl<T> Eq for Raw<'_, T> {}
+
+impl<T> ::serde::Serialize for Raw<'_, T> {
+    fn serialize<S>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: ::serde::Serializer,
+    {
+        self.raw.serialize(s)
+    }
+}
+
+impl<'de: 'a, 'a, T> ::serde::Deserialize<'de> for Raw<'a, T> {
+    fn deserialize<D>(d: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        RawSibor::deserialize(d).map(Self::from_raw)
+    }
+}
+
+/// The name that [`Open`] uses to ask the SiBOR serializer and deserializer for
+/// unknown variants.
+pub(crate) const OPEN: &str = "$serde_sibor::Open";
+
+/// An enum that keeps variants unknown to the reader instead of failing on them.
+///
+/// With [`crate::options::EnumEncoding::LengthPrefixed`], a variant whose index is not a
+/// variant of `E` is decoded as [`Open::Unknown`], with its payload kept encoded. Encoding
+/// it again writes the same bytes, so services can forward variants they don't understand.
+/// Open enums are specific to SiBOR, and can't be used with other serde formats.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Open<E> {
+    /// A variant that the reader knows.
+    Known(E),
+    /// A variant that the reader doesn't know.
+    Unknown {
+        /// The index of the variant.
+        index: u32,
+        /// The encoded payload of the variant.
+        payload: Vec<u8>,
+    },
+}
+
+impl<E> Open<E> {
+    /// Get the enum if its variant is known.
+    pub fn known(&self) -> Option<&E> {
+        match self {
+            Open::Known(e) => Some(e),
+            Open::Unknown { .. } => None,
+        }
+    }
+
+    /// Unwrap the enum if its variant is known.
+    pub fn into_known(self) -> Option<E> {
+        match self {
+            Open::Known(e) => Some(e),
+            Open::Unknown { .. } => None,
+        }
+    }
+}
+
+impl<E> From<E> for Open<E> {
+    fn from(e: E) -> Self {
+        Open::Known(e)
+    }
+}
+
+impl<E> ::serde::Serialize for Open<E>
+where
+    E: ::serde::Serialize,
+{
+    fn serialize<S>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: ::serde::Serializer,
+    {
+        match self {
+            Open::Known(e) => e.serialize(s),
+            Open::Unknown { index, payload } => {
+                s.serialize_newtype_variant(OPEN, *index, "", &RawSibor::new(&payload[..]))
+            }
+        }
+    }
+}
+
+impl<'de, E> ::serde::Deserialize<'de> for Open<E>
+where
+    E: ::serde::Deserialize<'de>,
+{
+    fn deserialize<D>(d: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        struct OpenVisitor<E>(PhantomData<fn() -> E>);
+
+        impl<'de, E> ::serde::de::Visitor<'de> for OpenVisitor<E>
+        where
+            E: ::serde::Deserialize<'de>,
+        {
+            type Value = Open<E>;
+
+            fn expecting(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                write!(f, "an enum with a known or unknown variant")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> ::std::result::Result<Self::Value, A::Error>
+            where
+                A: ::serde::de::SeqAccess<'de>,
+            {
+                use ::serde::de::Error as _;
+
+                if let Some(e) = seq.next_element()? {
+                    return Ok(Open::Known(e));
+                }
+                let index = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(1, &self))?;
+                let payload = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(2, &self))?;
+                Ok(Open::Unknown { index, payload })
+            }
+        }
+
+        d.deserialize_tuple_struct(OPEN, 3, OpenVisitor(PhantomData))
+    }
+}
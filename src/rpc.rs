@@ -0,0 +1,227 @@
+use crate::de::Deserializer;
+use crate::error::{Error, Result};
+use crate::frame::{FrameReader, FrameWriter, DEFAULT_MAX_FRAME_LEN};
+use crate::options::Options;
+use crate::ser::Serializer;
+
+/// The kind of an RPC message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    /// A request to call a method.
+    Request,
+    /// The successful response to a request.
+    Response,
+    /// The error response to a request.
+    Error,
+    /// The cancellation of a request.
+    Cancel,
+}
+
+impl Kind {
+    fn to_u8(self) -> u8 {
+        match self {
+            Kind::Request => 0,
+            Kind::Response => 1,
+            Kind::Error => 2,
+            Kind::Cancel => 3,
+        }
+    }
+
+    fn from_u8(b: u8) -> Result<Self> {
+        match b {
+            0 => Ok(Kind::Request),
+            1 => Ok(Kind::Response),
+            2 => Ok(Kind::Error),
+            3 => Ok(Kind::Cancel),
+            _ => Err(Error::Invalid(format!("rpc message kind: {b}"))),
+        }
+    }
+}
+
+/// The header of an RPC message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Header {
+    /// The kind of the message.
+    pub kind: Kind,
+    /// The correlation id, which responses, errors and cancellations share with their request.
+    pub id: u64,
+    /// The id of the method that was called.
+    pub method: u64,
+}
+
+/// An RPC message whose body has not been decoded yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// The header of the message.
+    pub header: Header,
+    /// The encoded body of the message.
+    pub body: Vec<u8>,
+    options: Options,
+}
+
+impl Message {
+    /// Decode the body of the message.
+    pub fn decode<V>(&self) -> Result<V>
+    where
+        V: ::serde::de::DeserializeOwned,
+    {
+        crate::from_bytes_with_options(&self.body, self.options)
+    }
+}
+
+/// One end of an RPC connection over a reader and a writer, such as the two halves of
+/// a socket.
+///
+/// Every message is a checksummed frame of [`crate::frame`], whose payload is the kind of
+/// the message as a byte, the correlation id and the method id as variable-length
+/// unsigned integers, and the encoded body.
+pub struct Channel<R, W> {
+    reader: FrameReader<R>,
+    writer: FrameWriter<W>,
+    options: Options,
+    next_id: u64,
+}
+
+impl<R, W> Channel<R, W>
+where
+    R: ::std::io::Read,
+    W: ::std::io::Write,
+{
+    /// Create a new channel.
+    pub fn new(reader: R, writer: W) -> Self {
+        Self::with_options(reader, writer, Options::default())
+    }
+
+    /// Create a new channel whose message bodies are encoded with the given options.
+    pub fn with_options(reader: R, writer: W, options: Options) -> Self {
+        Self {
+            reader: FrameReader::with_options(reader, options, DEFAULT_MAX_FRAME_LEN),
+            writer: FrameWriter::with_options(writer, options),
+            options,
+            next_id: 0,
+        }
+    }
+
+    /// Write a message with the given header and body.
+    pub fn send<V>(&mut self, header: Header, body: V) -> Result<()>
+    where
+        V: ::serde::Serialize,
+    {
+        let mut ser = Serializer::with_options(Vec::new(), self.options);
+        ser.write_u8(header.kind.to_u8())?;
+        ser.write_uvarint(header.id)?;
+        ser.write_uvarint(header.method)?;
+        body.serialize(&mut ser)?;
+        self.writer.write_raw(&ser.into_inner())
+    }
+
+    /// Write a request to call the given method, and return its correlation id.
+    pub fn request<V>(&mut self, method: u64, body: V) -> Result<u64>
+    where
+        V: ::serde::Serialize,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+        let header = Header {
+            kind: Kind::Request,
+            id,
+            method,
+        };
+        self.send(header, body)?;
+        Ok(id)
+    }
+
+    /// Write the successful response to a request.
+    pub fn respond<V>(&mut self, request: &Header, body: V) -> Result<()>
+    where
+        V: ::serde::Serialize,
+    {
+        self.reply(request, Kind::Response, body)
+    }
+
+    /// Write the error response to a request.
+    pub fn respond_error<V>(&mut self, request: &Header, body: V) -> Result<()>
+    where
+        V: ::serde::Serialize,
+    {
+        self.reply(request, Kind::Error, body)
+    }
+
+    /// Write the cancellation of a request.
+    pub fn cancel(&mut self, request: &Header) -> Result<()> {
+        self.reply(request, Kind::Cancel, ())
+    }
+
+    /// Read the next message.
+    /// Returns `None` once the other end has closed the connection.
+    pub fn receive(&mut self) -> Result<Option<Message>> {
+        let Some(payload) = self.reader.read_raw()? else {
+            return Ok(None);
+        };
+        let mut de = Deserializer::from_slice_with_options(&payload, self.options);
+        let header = Header {
+            kind: Kind::from_u8(de.read_u8()?)?,
+            id: de.read_uvarint()?,
+            method: de.read_uvarint()?,
+        };
+        let body = payload[de.position()..].to_vec();
+        Ok(Some(Message {
+            header,
+            body,
+            options: self.options,
+        }))
+    }
+
+    /// Call a method, and wait for its response.
+    ///
+    /// Returns `Ok(Err(_))` with the decoded body of an error response, and an error if the
+    /// request was cancelled, the connection was closed, or a message for another request
+    /// arrived first.
+    pub fn call<Req, Resp, E>(
+        &mut self,
+        method: u64,
+        body: Req,
+    ) -> Result<::std::result::Result<Resp, E>>
+    where
+        Req: ::serde::Serialize,
+        Resp: ::serde::de::DeserializeOwned,
+        E: ::serde::de::DeserializeOwned,
+    {
+        let id = self.request(method, body)?;
+        self.flush()?;
+        let message = self
+            .receive()?
+            .ok_or_else(|| Error::Io(::std::io::ErrorKind::UnexpectedEof.into()))?;
+        if message.header.id != id {
+            return Err(Error::Invalid(format!(
+                "rpc: message for request {}",
+                message.header.id
+            )));
+        }
+        match message.header.kind {
+            Kind::Response => message.decode().map(Ok),
+            Kind::Error => message.decode().map(Err),
+            kind => Err(Error::Generic(format!(
+                "rpc: request {id} got a {kind:?} message"
+            ))),
+        }
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+
+    /// Unwrap the underlying reader and writer.
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader.into_inner(), self.writer.into_inner())
+    }
+
+    fn reply<V>(&mut self, request: &Header, kind: Kind, body: V) -> Result<()>
+    where
+        V: ::serde::Serialize,
+    {
+        let header = Header { kind, ..*request };
+        self.send(header, body)
+    }
+}
@@ -0,0 +1,623 @@
+use crate::options::{Options, StructEncoding};
+use crate::path::{Path, Segment};
+
+/// A runtime description of the shape of a SiBOR-encoded value.
+///
+/// Because SiBOR is not self-describing, a schema is the only way to make
+/// sense of encoded bytes without access to the Rust types that produced them.
+/// Newtype structs are transparent on the wire and are described by their inner
+/// type, and unit structs are described as [`Schema::Unit`].
+#[derive(Debug, Clone, PartialEq, Eq, ::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Schema {
+    /// A value that occupies no bytes, such as `()` or a unit struct.
+    Unit,
+    /// A boolean, encoded as a single `0` or `1` byte.
+    Bool,
+    /// An unsigned 8-bit integer, encoded as a varint.
+    U8,
+    /// An unsigned 16-bit integer, encoded as a varint.
+    U16,
+    /// An unsigned 32-bit integer, encoded as a varint.
+    U32,
+    /// An unsigned 64-bit integer, encoded as a varint.
+    U64,
+    /// A signed 8-bit integer, encoded as a zigzag varint.
+    I8,
+    /// A signed 16-bit integer, encoded as a zigzag varint.
+    I16,
+    /// A signed 32-bit integer, encoded as a zigzag varint.
+    I32,
+    /// A signed 64-bit integer, encoded as a zigzag varint.
+    I64,
+    /// A 32-bit float, widened to 64 bits and encoded as a varint.
+    F32,
+    /// A 64-bit float, encoded as a varint.
+    F64,
+    /// A length-prefixed utf8 string.
+    String,
+    /// A length-prefixed byte string.
+    Bytes,
+    /// A count-prefixed sequence of values of the same type.
+    Seq(Box<Schema>),
+    /// A fixed number of values of possibly different types.
+    Tuple(Vec<Schema>),
+    /// A struct with named fields.
+    Struct(StructSchema),
+    /// A tagged union, encoded as a variant index followed by the variant payload.
+    Enum(EnumSchema),
+}
+
+/// The description of a struct with named fields.
+#[derive(Debug, Clone, PartialEq, Eq, ::serde::Serialize, ::serde::Deserialize)]
+pub struct StructSchema {
+    /// The name of the struct.
+    pub name: String,
+    /// The fields of the struct, in declaration order.
+    pub fields: Vec<Field>,
+}
+
+/// A named field of a struct.
+#[derive(Debug, Clone, PartialEq, Eq, ::serde::Serialize, ::serde::Deserialize)]
+pub struct Field {
+    /// The name of the field.
+    pub name: String,
+    /// The schema of the field's value.
+    pub schema: Schema,
+}
+
+/// The description of an enum.
+#[derive(Debug, Clone, PartialEq, Eq, ::serde::Serialize, ::serde::Deserialize)]
+pub struct EnumSchema {
+    /// The name of the enum.
+    pub name: String,
+    /// The variants of the enum, in declaration order.
+    pub variants: Vec<Variant>,
+}
+
+/// A variant of an enum.
+///
+/// Unit variants have a [`Schema::Unit`] payload, newtype variants have the schema
+/// of their inner value, tuple variants have a [`Schema::Tuple`] payload, and struct
+/// variants have a [`Schema::Struct`] payload.
+#[derive(Debug, Clone, PartialEq, Eq, ::serde::Serialize, ::serde::Deserialize)]
+pub struct Variant {
+    /// The name of the variant.
+    pub name: String,
+    /// The schema of the variant's payload.
+    pub schema: Schema,
+}
+
+impl Schema {
+    /// Create a sequence schema with the given element schema.
+    pub fn seq(element: Schema) -> Self {
+        Self::Seq(Box::new(element))
+    }
+
+    /// Create a struct schema from a name and a list of `(name, schema)` fields.
+    pub fn structure<'a>(name: &str, fields: impl IntoIterator<Item = (&'a str, Schema)>) -> Self {
+        Self::Struct(StructSchema {
+            name: name.into(),
+            fields: fields
+                .into_iter()
+                .map(|(name, schema)| Field {
+                    name: name.into(),
+                    schema,
+                })
+                .collect(),
+        })
+    }
+
+    /// Create an enum schema from a name and a list of `(name, payload)` variants.
+    pub fn enumeration<'a>(
+        name: &str,
+        variants: impl IntoIterator<Item = (&'a str, Schema)>,
+    ) -> Self {
+        Self::Enum(EnumSchema {
+            name: name.into(),
+            variants: variants
+                .into_iter()
+                .map(|(name, schema)| Variant {
+                    name: name.into(),
+                    schema,
+                })
+                .collect(),
+        })
+    }
+
+    /// The bit width of an unsigned integer schema.
+    fn unsigned_width(&self) -> Option<u32> {
+        match self {
+            Self::U8 => Some(8),
+            Self::U16 => Some(16),
+            Self::U32 => Some(32),
+            Self::U64 => Some(64),
+            _ => None,
+        }
+    }
+
+    /// The bit width of a signed integer schema.
+    fn signed_width(&self) -> Option<u32> {
+        match self {
+            Self::I8 => Some(8),
+            Self::I16 => Some(16),
+            Self::I32 => Some(32),
+            Self::I64 => Some(64),
+            _ => None,
+        }
+    }
+
+    /// The bit width of a floating point schema.
+    fn float_width(&self) -> Option<u32> {
+        match self {
+            Self::F32 => Some(32),
+            Self::F64 => Some(64),
+            _ => None,
+        }
+    }
+}
+
+impl StructSchema {
+    fn members(&self) -> Vec<(&str, &Schema)> {
+        self.fields
+            .iter()
+            .map(|f| (f.name.as_str(), &f.schema))
+            .collect()
+    }
+}
+
+impl EnumSchema {
+    fn members(&self) -> Vec<(&str, &Schema)> {
+        self.variants
+            .iter()
+            .map(|v| (v.name.as_str(), &v.schema))
+            .collect()
+    }
+}
+
+impl ::std::fmt::Display for Schema {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        match self {
+            Self::Unit => write!(f, "()"),
+            Self::Bool => write!(f, "bool"),
+            Self::U8 => write!(f, "u8"),
+            Self::U16 => write!(f, "u16"),
+            Self::U32 => write!(f, "u32"),
+            Self::U64 => write!(f, "u64"),
+            Self::I8 => write!(f, "i8"),
+            Self::I16 => write!(f, "i16"),
+            Self::I32 => write!(f, "i32"),
+            Self::I64 => write!(f, "i64"),
+            Self::F32 => write!(f, "f32"),
+            Self::F64 => write!(f, "f64"),
+            Self::String => write!(f, "string"),
+            Self::Bytes => write!(f, "bytes"),
+            Self::Seq(element) => write!(f, "[{element}]"),
+            Self::Tuple(elements) => {
+                write!(f, "(")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{element}")?;
+                }
+                write!(f, ")")
+            }
+            Self::Struct(s) => write!(f, "struct {}", s.name),
+            Self::Enum(e) => write!(f, "enum {}", e.name),
+        }
+    }
+}
+
+/// Whether a schema change can be read by the new schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Compatibility {
+    /// Data written with the old schema decodes correctly with the new schema.
+    Compatible,
+    /// Data written with the old schema may fail to decode, or may decode into the wrong value.
+    Breaking,
+}
+
+/// A single difference between a writer schema and a reader schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// The location of the difference.
+    pub path: Path,
+    /// Whether the difference is safe on the wire.
+    pub compatibility: Compatibility,
+    /// A human readable description of the difference.
+    pub message: String,
+}
+
+impl ::std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        let severity = match self.compatibility {
+            Compatibility::Compatible => "compatible",
+            Compatibility::Breaking => "breaking",
+        };
+        if self.path.is_root() {
+            write!(f, "{severity}: <root>: {}", self.message)
+        } else {
+            write!(f, "{severity}: {}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// The result of comparing a writer schema against a reader schema.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Every difference that was found, in schema order.
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    /// Check whether every difference in this report is wire-safe.
+    pub fn is_compatible(&self) -> bool {
+        self.breaking().next().is_none()
+    }
+
+    /// Iterate over the differences that are wire-safe.
+    pub fn compatible(&self) -> impl Iterator<Item = &Finding> {
+        self.findings
+            .iter()
+            .filter(|f| f.compatibility == Compatibility::Compatible)
+    }
+
+    /// Iterate over the differences that break decoding of old data.
+    pub fn breaking(&self) -> impl Iterator<Item = &Finding> {
+        self.findings
+            .iter()
+            .filter(|f| f.compatibility == Compatibility::Breaking)
+    }
+
+    fn record(&mut self, path: &Path, compatibility: Compatibility, message: String) {
+        self.findings.push(Finding {
+            path: path.clone(),
+            compatibility,
+            message,
+        });
+    }
+}
+
+impl ::std::fmt::Display for Report {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        for finding in &self.findings {
+            writeln!(f, "{finding}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Check whether data written with the `writer` schema can be decoded with the `reader` schema.
+///
+/// Every difference between the two schemas is reported along with its path,
+/// and classified as either wire-safe or breaking.
+pub fn check_compatibility(writer: &Schema, reader: &Schema) -> Report {
+    check_compatibility_with_options(writer, reader, &Options::default())
+}
+
+/// Check whether data written with the `writer` schema and the given options can be
+/// decoded with the `reader` schema and the same options.
+///
+/// With length-prefixed structs, fields that are appended to or removed from the end
+/// of a struct are wire-safe, as long as appended fields have a `#[serde(default)]`.
+pub fn check_compatibility_with_options(
+    writer: &Schema,
+    reader: &Schema,
+    options: &Options,
+) -> Report {
+    let mut report = Report::default();
+    compare(&mut Path::new(), writer, reader, options, &mut report);
+    report
+}
+
+fn compare(
+    path: &mut Path,
+    writer: &Schema,
+    reader: &Schema,
+    options: &Options,
+    report: &mut Report,
+) {
+    use Compatibility::{Breaking, Compatible};
+
+    if writer == reader {
+        return;
+    }
+
+    match (writer, reader) {
+        (Schema::Seq(w), Schema::Seq(r)) => {
+            path.push(Segment::Index(None));
+            compare(path, w, r, options, report);
+            path.pop();
+        }
+        (Schema::Tuple(w), Schema::Tuple(r)) => {
+            if w.len() != r.len() {
+                report.record(
+                    path,
+                    Breaking,
+                    format!("tuple length changed from {} to {}", w.len(), r.len()),
+                );
+                return;
+            }
+            for (i, (w, r)) in w.iter().zip(r.iter()).enumerate() {
+                path.push(Segment::Element(i));
+                compare(path, w, r, options, report);
+                path.pop();
+            }
+        }
+        (Schema::Struct(w), Schema::Struct(r)) => compare_structs(path, w, r, options, report),
+        (Schema::Enum(w), Schema::Enum(r)) => compare_enums(path, w, r, options, report),
+        (Schema::Bool, r) if r.unsigned_width().is_some() => report.record(
+            path,
+            Compatible,
+            format!("bool read as {r}; values decode as 0 or 1"),
+        ),
+        (Schema::String, Schema::Bytes) => {
+            report.record(path, Compatible, "string read as bytes".into())
+        }
+        (Schema::Bytes, Schema::String) => report.record(
+            path,
+            Breaking,
+            "bytes read as string; non-utf8 values fail to decode".into(),
+        ),
+        (w, r) => match (
+            (w.unsigned_width(), r.unsigned_width()),
+            (w.signed_width(), r.signed_width()),
+            (w.float_width(), r.float_width()),
+        ) {
+            ((Some(ww), Some(rw)), _, _) | (_, (Some(ww), Some(rw)), _) => {
+                if ww < rw {
+                    report.record(path, Compatible, format!("widened {w} to {r}"));
+                } else {
+                    report.record(
+                        path,
+                        Breaking,
+                        format!("narrowed {w} to {r}; values outside of {r} fail to decode"),
+                    );
+                }
+            }
+            (_, _, (Some(ww), Some(rw))) => {
+                if ww < rw {
+                    report.record(path, Compatible, format!("widened {w} to {r}"));
+                } else {
+                    report.record(
+                        path,
+                        Breaking,
+                        format!("narrowed {w} to {r}; values lose precision"),
+                    );
+                }
+            }
+            ((Some(_), _), (_, Some(_)), _) | ((_, Some(_)), (Some(_), _), _) => report.record(
+                path,
+                Breaking,
+                format!("{w} read as {r}; zigzag encoding mismatch"),
+            ),
+            _ => report.record(path, Breaking, format!("type changed from {w} to {r}")),
+        },
+    }
+}
+
+fn compare_structs(
+    path: &mut Path,
+    writer: &StructSchema,
+    reader: &StructSchema,
+    options: &Options,
+    report: &mut Report,
+) {
+    if options.struct_encoding == StructEncoding::Numbered {
+        return compare_numbered_fields(path, writer, reader, options, report);
+    }
+    compare_members(
+        path,
+        &writer.members(),
+        &reader.members(),
+        MemberKind::Field,
+        options,
+        report,
+    );
+}
+
+fn compare_enums(
+    path: &mut Path,
+    writer: &EnumSchema,
+    reader: &EnumSchema,
+    options: &Options,
+    report: &mut Report,
+) {
+    compare_members(
+        path,
+        &writer.members(),
+        &reader.members(),
+        MemberKind::Variant,
+        options,
+        report,
+    );
+}
+
+/// Compare the fields of numbered structs, which are matched by number instead of
+/// by position.
+fn compare_numbered_fields(
+    path: &mut Path,
+    writer: &StructSchema,
+    reader: &StructSchema,
+    options: &Options,
+    report: &mut Report,
+) {
+    use Compatibility::{Breaking, Compatible};
+
+    let numbers = |s: &StructSchema| {
+        let names = s.fields.iter().map(|field| field.name.as_str());
+        options.field_numbers_of(&s.name, None, names)
+    };
+    let (writer_numbers, reader_numbers) = match (numbers(writer), numbers(reader)) {
+        (Ok(writer_numbers), Ok(reader_numbers)) => (writer_numbers, reader_numbers),
+        (Err(e), _) | (_, Err(e)) => {
+            report.record(path, Breaking, e.to_string());
+            return;
+        }
+    };
+
+    for (w, number) in writer.fields.iter().zip(&writer_numbers) {
+        match reader_numbers.iter().position(|n| n == number) {
+            Some(j) => {
+                let r = &reader.fields[j];
+                path.push(Segment::Field(r.name.clone()));
+                if r.name != w.name {
+                    report.record(
+                        path,
+                        Compatible,
+                        format!("field {number} renamed from `{}` to `{}`", w.name, r.name),
+                    );
+                }
+                compare(path, &w.schema, &r.schema, options, report);
+                path.pop();
+            }
+            None => {
+                path.push(Segment::Field(w.name.clone()));
+                report.record(
+                    path,
+                    Compatible,
+                    format!("field {number} removed; new readers skip it"),
+                );
+                path.pop();
+            }
+        }
+        if let Some(j) = reader.fields.iter().position(|r| r.name == w.name) {
+            if reader_numbers[j] != *number {
+                path.push(Segment::Field(w.name.clone()));
+                report.record(
+                    path,
+                    Breaking,
+                    format!("field renumbered from {number} to {}", reader_numbers[j]),
+                );
+                path.pop();
+            }
+        }
+    }
+
+    for (r, number) in reader.fields.iter().zip(&reader_numbers) {
+        if !writer_numbers.contains(number) {
+            path.push(Segment::Field(r.name.clone()));
+            report.record(
+                path,
+                Compatible,
+                format!("field {number} added; old data fills it from its default"),
+            );
+            path.pop();
+        }
+    }
+}
+
+/// The kind of named, positionally encoded members being compared.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MemberKind {
+    Field,
+    Variant,
+}
+
+impl MemberKind {
+    fn noun(self) -> &'static str {
+        match self {
+            Self::Field => "field",
+            Self::Variant => "variant",
+        }
+    }
+
+    fn segment(self, name: &str) -> Segment {
+        match self {
+            Self::Field => Segment::Field(name.into()),
+            Self::Variant => Segment::Variant(name.into()),
+        }
+    }
+}
+
+/// Compare struct fields or enum variants.
+///
+/// Both are encoded by position, so members are matched by name to detect
+/// reordering, and by position to detect renames.
+fn compare_members(
+    path: &mut Path,
+    writer: &[(&str, &Schema)],
+    reader: &[(&str, &Schema)],
+    kind: MemberKind,
+    options: &Options,
+    report: &mut Report,
+) {
+    use Compatibility::{Breaking, Compatible};
+
+    let noun = kind.noun();
+    let length_prefixed =
+        kind == MemberKind::Field && options.struct_encoding == StructEncoding::LengthPrefixed;
+    let position =
+        |members: &[(&str, &Schema)], name: &str| members.iter().position(|(n, _)| *n == name);
+    let renamed = |i: usize| {
+        i < writer.len()
+            && i < reader.len()
+            && position(reader, writer[i].0).is_none()
+            && position(writer, reader[i].0).is_none()
+    };
+
+    for (i, (name, schema)) in writer.iter().enumerate() {
+        match position(reader, name) {
+            Some(j) if j == i => {
+                path.push(kind.segment(name));
+                compare(path, schema, reader[j].1, options, report);
+                path.pop();
+            }
+            Some(j) => {
+                path.push(kind.segment(name));
+                report.record(path, Breaking, format!("{noun} moved from {i} to {j}"));
+                path.pop();
+            }
+            None if renamed(i) => {
+                let (new_name, new_schema) = reader[i];
+                path.push(kind.segment(new_name));
+                report.record(
+                    path,
+                    Compatible,
+                    format!("{noun} renamed from `{name}` to `{new_name}`"),
+                );
+                compare(path, schema, new_schema, options, report);
+                path.pop();
+            }
+            None if length_prefixed && i >= reader.len() => {
+                path.push(kind.segment(name));
+                report.record(
+                    path,
+                    Compatible,
+                    format!("trailing {noun} removed; new readers skip it"),
+                );
+                path.pop();
+            }
+            None => {
+                path.push(kind.segment(name));
+                report.record(path, Breaking, format!("{noun} removed"));
+                path.pop();
+            }
+        }
+    }
+
+    for (j, (name, _)) in reader.iter().enumerate() {
+        if position(writer, name).is_some() || renamed(j) {
+            continue;
+        }
+        path.push(kind.segment(name));
+        match kind {
+            MemberKind::Variant if j >= writer.len() => {
+                report.record(path, Compatible, format!("{noun} appended"))
+            }
+            MemberKind::Variant => report.record(path, Breaking, format!("{noun} inserted at {j}")),
+            MemberKind::Field if length_prefixed && j >= writer.len() => report.record(
+                path,
+                Compatible,
+                format!("{noun} appended; old data fills it from its default"),
+            ),
+            MemberKind::Field => report.record(
+                path,
+                Breaking,
+                format!("{noun} added; old data does not contain it"),
+            ),
+        }
+        path.pop();
+    }
+}
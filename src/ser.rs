@@ -1,8 +1,21 @@
+use std::borrow::Cow;
+
 use crate::error::{Error, Result};
+use crate::options::{
+    duplicate_field_number, field_container, EnumEncoding, Options, StructEncoding, WIRE_LEN,
+    WIRE_VARINT,
+};
+use crate::path::Segment;
+use crate::trace::{Kind, Trace};
 
 /// A helper for serializing elements of a dynamically sized collection.
+///
+/// In canonical mode, the elements of sets are serialized into buffers, which are
+/// sorted and written once the set is complete.
 pub struct CollectionSerializer<'a, W> {
+    index: usize,
     remaining: usize,
+    sorted: Option<Vec<(usize, Serializer<Vec<u8>>)>>,
     ser: &'a mut Serializer<W>,
 }
 impl<'a, W> ::serde::ser::SerializeSeq for CollectionSerializer<'a, W>
@@ -12,9 +25,9 @@ where
     type Ok = ();
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<Self::Ok>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<Self::Ok>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
         if self.remaining < 1 {
             return Err(Error::Generic(
@@ -22,17 +35,110 @@ where
             ));
         }
         self.remaining -= 1;
-        value.serialize(&mut *self.ser)
+        self.index += 1;
+        if let Some(sorted) = self.sorted.as_mut() {
+            let mut element = self.ser.buffer();
+            element.nested(Segment::Index(None), |ser| value.serialize(ser))?;
+            sorted.push((element.writer.len(), element));
+            return Ok(());
+        }
+        self.ser
+            .nested(Segment::Index(Some(self.index - 1)), |ser| {
+                value.serialize(ser)
+            })
     }
 
     fn end(self) -> Result<Self::Ok> {
-        Ok(())
+        match self.sorted {
+            Some(sorted) => self.ser.write_sorted(sorted),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A helper for serializing the entries of maps, as a sequence of key-value pairs.
+///
+/// In canonical mode, the entries are serialized into buffers, which are sorted by
+/// their keys and written once the map is complete.
+pub struct MapSerializer<'a, W> {
+    index: usize,
+    remaining: Option<usize>,
+    sorted: Option<Vec<(usize, Serializer<Vec<u8>>)>>,
+    ser: &'a mut Serializer<W>,
+}
+
+impl<'a, W> ::serde::ser::SerializeMap for MapSerializer<'a, W>
+where
+    W: ::std::io::Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<Self::Ok>
+    where
+        T: ?Sized + serde::Serialize,
+    {
+        if let Some(remaining) = self.remaining.as_mut() {
+            if *remaining < 1 {
+                return Err(Error::Generic(
+                    "tried to serialize too many entries in map".into(),
+                ));
+            }
+            *remaining -= 1;
+        }
+        self.index += 1;
+        match self.sorted.as_mut() {
+            Some(sorted) => {
+                let mut entry = self.ser.buffer();
+                entry.enter(Segment::Index(None));
+                entry.nested(Segment::Element(0), |ser| key.serialize(ser))?;
+                sorted.push((entry.writer.len(), entry));
+                Ok(())
+            }
+            None => {
+                self.ser.enter(Segment::Index(Some(self.index - 1)));
+                self.ser
+                    .nested(Segment::Element(0), |ser| key.serialize(ser))
+            }
+        }
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<Self::Ok>
+    where
+        T: ?Sized + serde::Serialize,
+    {
+        match self.sorted.as_mut().and_then(|sorted| sorted.last_mut()) {
+            Some((_, entry)) => {
+                let result = entry.nested(Segment::Element(1), |ser| value.serialize(ser));
+                entry.leave();
+                result
+            }
+            None => {
+                let result = self
+                    .ser
+                    .nested(Segment::Element(1), |ser| value.serialize(ser));
+                self.ser.leave();
+                result
+            }
+        }
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        match self.sorted {
+            Some(sorted) => self.ser.write_sorted(sorted),
+            None => Ok(()),
+        }
     }
 }
 
 /// A helper for serializing statically structured data such as
 /// tuples, structs, and fixed-length arrays.
+///
+/// With [`EnumEncoding::LengthPrefixed`], the fields of tuple variants are serialized
+/// into a buffer, which is written with its length once the variant is complete.
 pub struct TupleSerializer<'a, W> {
+    index: usize,
+    payload: Option<Serializer<Vec<u8>>>,
     ser: &'a mut Serializer<W>,
 }
 
@@ -43,11 +149,13 @@ where
     type Ok = ();
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<Self::Ok>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<Self::Ok>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        value.serialize(&mut *self.ser)
+        self.index += 1;
+        self.ser
+            .nested(Segment::Element(self.index - 1), |ser| value.serialize(ser))
     }
 
     fn end(self) -> Result<Self::Ok> {
@@ -62,11 +170,13 @@ where
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<Self::Ok>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<Self::Ok>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        value.serialize(&mut *self.ser)
+        self.index += 1;
+        self.ser
+            .nested(Segment::Element(self.index - 1), |ser| value.serialize(ser))
     }
 
     fn end(self) -> Result<Self::Ok> {
@@ -81,65 +191,359 @@ where
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<Self::Ok>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<Self::Ok>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        value.serialize(&mut *self.ser)
+        self.index += 1;
+        let segment = Segment::Element(self.index - 1);
+        match self.payload.as_mut() {
+            Some(payload) => payload.nested(segment, |ser| value.serialize(ser)),
+            None => self.ser.nested(segment, |ser| value.serialize(ser)),
+        }
     }
 
-    fn end(self) -> Result<Self::Ok> {
-        Ok(())
+    fn end(mut self) -> Result<Self::Ok> {
+        let result = match self.payload.take() {
+            Some(payload) => self.ser.write_prefixed(payload),
+            None => Ok(()),
+        };
+        self.ser.leave();
+        result
+    }
+}
+
+/// A buffered numbered field, with its number and the segment of its path.
+type NumberedField = (u64, Segment, Serializer<Vec<u8>>);
+
+/// A helper for serializing the fields of structs and struct variants.
+///
+/// With [`StructEncoding::LengthPrefixed`], the fields are serialized into a buffer,
+/// which is written with its length and the number of fields once the struct is complete.
+/// Fields are read back by position, so only trailing fields can be skipped. With
+/// [`StructEncoding::Numbered`], each field is serialized into a buffer, which is
+/// written after the field's key. In canonical mode, numbered fields are written in
+/// the order of their numbers once the struct is complete. With
+/// [`EnumEncoding::LengthPrefixed`], the whole body of a struct variant is buffered too,
+/// and written with its length.
+pub struct StructSerializer<'a, W> {
+    container: Cow<'static, str>,
+    numbers: Vec<u64>,
+    sorted: Option<Vec<NumberedField>>,
+    index: usize,
+    written: usize,
+    remaining: usize,
+    body: Option<Serializer<Vec<u8>>>,
+    payload: Option<Serializer<Vec<u8>>>,
+    ser: &'a mut Serializer<W>,
+}
+
+impl<'a, W> StructSerializer<'a, W>
+where
+    W: ::std::io::Write,
+{
+    fn new(
+        ser: &'a mut Serializer<W>,
+        name: &'static str,
+        variant: Option<&'static str>,
+        len: usize,
+    ) -> Result<Self> {
+        let mut payload = variant.and_then(|_| ser.payload());
+        let mut container = Cow::Borrowed(name);
+        let mut sorted = None;
+        let body = match ser.options.struct_encoding {
+            StructEncoding::Tuple => None,
+            StructEncoding::LengthPrefixed => Some(ser.buffer()),
+            StructEncoding::Numbered => {
+                container = field_container(name, variant);
+                sorted = ser.options.canonical.map(|_| Vec::new());
+                match payload.as_mut() {
+                    Some(payload) => payload.write_length(len)?,
+                    None => ser.write_length(len)?,
+                }
+                None
+            }
+        };
+        Ok(Self {
+            container,
+            numbers: Vec::new(),
+            sorted,
+            index: 0,
+            written: 0,
+            remaining: len,
+            body,
+            payload,
+            ser,
+        })
+    }
+
+    fn field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + serde::Serialize,
+    {
+        let segment = Segment::Field(key.into());
+        self.index += 1;
+        if self.ser.options.struct_encoding == StructEncoding::Numbered {
+            return self.numbered_field(segment, value);
+        }
+        if self.body.is_some() && self.written + 1 < self.index {
+            return Err(Error::Invalid(format!(
+                "struct {}: field {key} follows a skipped field, which length-prefixed \
+                 structs can't encode",
+                self.container
+            )));
+        }
+        self.written += 1;
+        match (self.body.as_mut(), self.payload.as_mut()) {
+            (Some(body), _) => body.nested(segment, |ser| value.serialize(ser)),
+            (None, Some(payload)) => payload.nested(segment, |ser| value.serialize(ser)),
+            (None, None) => self.ser.nested(segment, |ser| value.serialize(ser)),
+        }
+    }
+
+    fn numbered_field<T>(&mut self, segment: Segment, value: &T) -> Result<()>
+    where
+        T: ?Sized + serde::Serialize,
+    {
+        let Segment::Field(key) = &segment else {
+            unreachable!("struct fields have field segments");
+        };
+        let number = self
+            .ser
+            .options
+            .field_number(&self.container, self.index - 1, key)?;
+        if self.numbers.contains(&number) {
+            return Err(duplicate_field_number(&self.container, number));
+        }
+        self.numbers.push(number);
+        if self.remaining < 1 {
+            return Err(Error::Generic(
+                "tried to serialize too many fields in struct".into(),
+            ));
+        }
+        self.remaining -= 1;
+
+        let mut body = self.ser.buffer();
+        body.nested(segment.clone(), |ser| value.serialize(ser))?;
+        if let Some(sorted) = self.sorted.as_mut() {
+            sorted.push((number, segment, body));
+            return Ok(());
+        }
+        self.write_numbered_field(segment, number, body)
+    }
+
+    fn write_numbered_field(
+        &mut self,
+        segment: Segment,
+        number: u64,
+        body: Serializer<Vec<u8>>,
+    ) -> Result<()> {
+        match self.payload.as_mut() {
+            Some(payload) => payload.write_numbered_field(segment, number, body),
+            None => self.ser.write_numbered_field(segment, number, body),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.ser.options.struct_encoding == StructEncoding::Numbered && self.remaining > 0 {
+            return Err(Error::Generic(
+                "tried to serialize too few fields in struct".into(),
+            ));
+        }
+        if let Some(mut sorted) = self.sorted.take() {
+            sorted.sort_by_key(|(number, _, _)| *number);
+            for (number, segment, body) in sorted {
+                self.write_numbered_field(segment, number, body)?;
+            }
+        }
+        if let Some(fields) = self.body.take() {
+            // The body starts with the number of fields, so that readers can tell a
+            // trailing field that takes no bytes from a missing one.
+            let mut body = self.ser.buffer();
+            body.write_length(self.written)?;
+            body.write_buffered(fields)?;
+            match self.payload.as_mut() {
+                Some(payload) => payload.write_prefixed(body)?,
+                None => self.ser.write_prefixed(body)?,
+            }
+        }
+        match self.payload.take() {
+            Some(payload) => self.ser.write_prefixed(payload),
+            None => Ok(()),
+        }
     }
 }
 
-impl<'a, W> ::serde::ser::SerializeStruct for TupleSerializer<'a, W>
+/// Check whether an encoded value is exactly one varint.
+fn is_single_varint(buf: &[u8]) -> bool {
+    match buf.split_last() {
+        Some((last, init)) => buf.len() <= 10 && *last < 0x80 && init.iter().all(|b| *b >= 0x80),
+        None => false,
+    }
+}
+
+impl<'a, W> ::serde::ser::SerializeStruct for StructSerializer<'a, W>
 where
     W: ::std::io::Write,
 {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, _: &'static str, value: &T) -> Result<Self::Ok>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<Self::Ok>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        value.serialize(&mut *self.ser)
+        self.field(key, value)
     }
 
-    fn end(self) -> Result<Self::Ok> {
+    fn skip_field(&mut self, _: &'static str) -> Result<Self::Ok> {
+        self.index += 1;
         Ok(())
     }
+
+    fn end(mut self) -> Result<Self::Ok> {
+        self.finish()
+    }
 }
 
-impl<'a, W> ::serde::ser::SerializeStructVariant for TupleSerializer<'a, W>
+impl<'a, W> ::serde::ser::SerializeStructVariant for StructSerializer<'a, W>
 where
     W: ::std::io::Write,
 {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, _: &'static str, value: &T) -> Result<Self::Ok>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<Self::Ok>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        value.serialize(&mut *self.ser)
+        self.field(key, value)
     }
 
-    fn end(self) -> Result<Self::Ok> {
+    fn skip_field(&mut self, _: &'static str) -> Result<Self::Ok> {
+        self.index += 1;
         Ok(())
     }
+
+    fn end(mut self) -> Result<Self::Ok> {
+        let result = self.finish();
+        self.ser.leave();
+        result
+    }
+}
+
+/// A writer for sequences whose elements are serialized across separate calls, so that
+/// the whole sequence never has to be held in memory.
+///
+/// The element count is written up front, so the sequence is encoded exactly like one
+/// serialized with `serialize_seq`, and [`SeqWriter::finish`] checks that exactly that
+/// many elements were written.
+pub struct SeqWriter<'a, W> {
+    index: usize,
+    len: usize,
+    ser: &'a mut Serializer<W>,
+}
+
+impl<'a, W> SeqWriter<'a, W>
+where
+    W: ::std::io::Write,
+{
+    /// Start a sequence with the given number of elements, and write its count.
+    pub fn new(ser: &'a mut Serializer<W>, len: usize) -> Result<Self> {
+        ser.write_length(len)?;
+        Ok(Self { index: 0, len, ser })
+    }
+
+    /// Get the number of elements that are still to be written.
+    pub fn remaining(&self) -> usize {
+        self.len - self.index
+    }
+
+    /// Write the next element.
+    pub fn write<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + ::serde::Serialize,
+    {
+        if self.index == self.len {
+            return Err(Error::Generic(
+                "tried to serialize too many elements in collection".into(),
+            ));
+        }
+        self.index += 1;
+        self.ser
+            .nested(Segment::Index(Some(self.index - 1)), |ser| {
+                value.serialize(ser)
+            })
+    }
+
+    /// Finish the sequence, and check that every element was written.
+    pub fn finish(self) -> Result<()> {
+        match self.remaining() {
+            0 => Ok(()),
+            n => Err(Error::Invalid(format!("sequence: {n} elements missing"))),
+        }
+    }
 }
 
 /// A serializer that can serialize values to a writer.
 pub struct Serializer<W> {
     writer: W,
+    options: Options,
+    trace: Option<Box<Trace>>,
+    set: bool,
 }
 
 impl<W> Serializer<W> {
     /// Create a new serializer that writes to the given writer.
     pub fn new(writer: W) -> Self {
-        Self { writer }
+        Self::with_options(writer, Options::default())
+    }
+
+    /// Create a new serializer that writes to the given writer with the given options.
+    pub fn with_options(writer: W, options: Options) -> Self {
+        Self {
+            writer,
+            options,
+            trace: None,
+            set: false,
+        }
+    }
+
+    /// Create a new serializer that writes to the given writer, and records
+    /// the byte span, path and kind of every primitive that it writes.
+    pub fn new_traced(writer: W) -> Self {
+        Self {
+            writer,
+            options: Options::default(),
+            trace: Some(Box::default()),
+            set: false,
+        }
+    }
+
+    /// Get the options of this serializer.
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Enable tracing, and start over with an empty trace.
+    pub fn enable_trace(&mut self) {
+        self.trace = Some(Box::default());
+    }
+
+    /// Get the trace recorded so far, if tracing is enabled.
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_deref()
+    }
+
+    /// Take the trace recorded so far, if tracing is enabled.
+    /// Tracing stays enabled, and starts over with an empty trace.
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        self.trace.as_mut().map(|trace| *::std::mem::take(trace))
+    }
+
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
     }
 }
 
@@ -175,7 +579,7 @@ where
 
     /// Get the number of bytes required to encode a 64-bit floating point number.
     pub fn sizeof_float(&self, v: &f64) -> Result<usize> {
-        return self.sizeof_uvarint(&v.to_bits());
+        self.sizeof_uvarint(&v.to_bits())
     }
 
     /// Get the number of bytes required to encode a boolean.
@@ -197,40 +601,158 @@ where
     /// A helper method for writing the full and exact contents of a buffer
     /// to the underlying writer.
     fn write_exact(&mut self, buf: &[u8]) -> Result<()> {
-        self.writer.write_all(buf).map_err(Error::Io)
+        self.writer.write_all(buf).map_err(Error::Io)?;
+        if let Some(trace) = self.trace.as_mut() {
+            trace.advance(buf.len());
+        }
+        Ok(())
+    }
+
+    /// Create a serializer with the same options that buffers a nested body,
+    /// and traces it at the current path if tracing is enabled.
+    fn buffer(&self) -> Serializer<Vec<u8>> {
+        Serializer {
+            writer: Vec::new(),
+            options: self.options,
+            trace: self.trace.as_ref().map(|trace| Box::new(trace.nested())),
+            set: false,
+        }
+    }
+
+    /// Write a buffered body, along with its trace.
+    fn write_buffered(&mut self, mut body: Serializer<Vec<u8>>) -> Result<()> {
+        if let (Some(parent), Some(trace)) = (self.trace.as_mut(), body.take_trace()) {
+            parent.splice(trace);
+        }
+        self.write_exact(&body.writer)
+    }
+
+    /// Write the buffered elements of a set or entries of a map, sorted by the bytes of their
+    /// keys, which are the first `n` bytes of each buffer.
+    fn write_sorted(&mut self, mut sorted: Vec<(usize, Serializer<Vec<u8>>)>) -> Result<()> {
+        fn key((n, buf): &(usize, Serializer<Vec<u8>>)) -> &[u8] {
+            &buf.writer[..*n]
+        }
+        sorted.sort_by(|a, b| key(a).cmp(key(b)));
+        if sorted.windows(2).any(|pair| key(&pair[0]) == key(&pair[1])) {
+            return Err(Error::Invalid("canonical encoding: duplicate keys".into()));
+        }
+        self.write_length(sorted.len())?;
+        for (index, (_, mut element)) in sorted.into_iter().enumerate() {
+            if let (Some(parent), Some(trace)) = (self.trace.as_mut(), element.take_trace()) {
+                parent.splice_element(trace, index);
+            }
+            self.write_exact(&element.writer)?;
+        }
+        Ok(())
+    }
+
+    /// Write a buffered body prefixed with its length.
+    fn write_prefixed(&mut self, body: Serializer<Vec<u8>>) -> Result<()> {
+        self.write_length(body.writer.len())?;
+        self.write_buffered(body)
+    }
+
+    /// Write the key of a numbered field and its buffered value. Values that are
+    /// exactly one varint are written as is, and other values with their length.
+    fn write_numbered_field(
+        &mut self,
+        segment: Segment,
+        number: u64,
+        body: Serializer<Vec<u8>>,
+    ) -> Result<()> {
+        let wire = match is_single_varint(&body.writer) {
+            true => WIRE_VARINT,
+            false => WIRE_LEN,
+        };
+        self.nested(segment, |ser| {
+            ser.traced(Kind::Key, |ser| ser.write_uvarint(number << 3 | wire))?;
+            match wire {
+                WIRE_LEN => ser.write_prefixed(body),
+                _ => ser.write_buffered(body),
+            }
+        })
+    }
+
+    /// Create a buffer for the payload of an enum variant, if payloads are length-prefixed.
+    fn payload(&self) -> Option<Serializer<Vec<u8>>> {
+        match self.options.enum_encoding {
+            EnumEncoding::Plain => None,
+            EnumEncoding::LengthPrefixed => Some(self.buffer()),
+        }
+    }
+
+    /// A helper method for recording the span of a primitive while tracing.
+    fn traced(&mut self, kind: Kind, f: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        let Some(start) = self.trace.as_mut().map(|trace| trace.enter()) else {
+            return f(self);
+        };
+        let result = f(self);
+        if let Some(trace) = self.trace.as_mut() {
+            trace.exit(start, kind);
+        }
+        result
+    }
+
+    /// A helper method for serializing a nested value at the given path segment.
+    fn nested(&mut self, segment: Segment, f: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        self.enter(segment);
+        let result = f(self);
+        self.leave();
+        result
+    }
+
+    /// Start tracing a nested value at the given path segment.
+    fn enter(&mut self, segment: Segment) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(segment);
+        }
+    }
+
+    /// Finish tracing a nested value.
+    fn leave(&mut self) {
+        if let Some(trace) = self.trace.as_mut() {
+            trace.pop();
+        }
     }
 
     /// Write a single byte to the byte stream.
     /// This is a special case that does not use variable-length encoding.
     pub fn write_u8(&mut self, v: u8) -> Result<()> {
-        self.write_exact(&[v])
+        self.traced(Kind::Byte, |ser| ser.write_exact(&[v]))
     }
 
     /// Write an unsigned 64-bit integer to the byte stream using variable-length encoding.
-    pub fn write_uvarint(&mut self, mut v: u64) -> Result<()> {
-        while v >= 0x80 {
-            self.write_u8((v & 0x7f) as u8 | 0x80)?;
-            v >>= 7;
-        }
-        self.write_u8((v & 0x7f) as u8)?;
-        Ok(())
+    pub fn write_uvarint(&mut self, v: u64) -> Result<()> {
+        self.traced(Kind::Unsigned, |ser| {
+            let mut v = v;
+            while v >= 0x80 {
+                ser.write_u8((v & 0x7f) as u8 | 0x80)?;
+                v >>= 7;
+            }
+            ser.write_u8((v & 0x7f) as u8)
+        })
     }
 
     /// Write a signed 64-bit integer to the byte stream using variable-length zigzag encoding.
     pub fn write_ivarint(&mut self, v: i64) -> Result<()> {
         let unsigned = self.zigzag(v);
-        self.write_uvarint(unsigned)
+        self.traced(Kind::Signed, |ser| ser.write_uvarint(unsigned))
     }
 
     /// Write a 64-bit floating point number to the byte stream.
     /// The bits of the floating point number are written as an unsigned integer.
     pub fn write_float(&mut self, v: f64) -> Result<()> {
-        self.write_uvarint(v.to_bits())
+        let v = match self.options.canonical {
+            Some(canonical) => canonical.normalize(v),
+            None => v,
+        };
+        self.traced(Kind::Float, |ser| ser.write_uvarint(v.to_bits()))
     }
 
     /// Write a boolean to the byte stream.
     pub fn write_bool(&mut self, v: bool) -> Result<()> {
-        self.write_u8(if v { 1 } else { 0 })
+        self.traced(Kind::Bool, |ser| ser.write_u8(if v { 1 } else { 0 }))
     }
 
     /// Write a byte string to the byte stream.
@@ -238,22 +760,71 @@ where
     /// Then, the contents of the byte string are written exactly as-is.
     pub fn write_bytes(&mut self, v: &[u8]) -> Result<()> {
         let len64 = u64::try_from(v.len()).map_err(|e| Error::Generic(e.to_string()))?;
-        self.write_uvarint(len64)?;
-        self.write_exact(v)?;
-        Ok(())
+        self.traced(Kind::Bytes, |ser| {
+            ser.write_uvarint(len64)?;
+            ser.write_exact(v)
+        })
+    }
+
+    /// Write a byte string of the given length to the byte stream, copying its contents
+    /// from a reader instead of holding them in memory.
+    /// Returns an error if the reader ends before `len` bytes were copied.
+    pub fn write_bytes_from<Rd>(&mut self, len: u64, reader: Rd) -> Result<()>
+    where
+        Rd: ::std::io::Read,
+    {
+        self.traced(Kind::Bytes, |ser| {
+            ser.write_uvarint(len)?;
+            let mut reader = ::std::io::Read::take(reader, len);
+            let mut buf = [0u8; 8192];
+            let mut copied = 0u64;
+            loop {
+                let n = match ::std::io::Read::read(&mut reader, &mut buf) {
+                    Ok(n) => n,
+                    Err(e) if e.kind() == ::std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(Error::Io(e)),
+                };
+                if n == 0 {
+                    break;
+                }
+                ser.write_exact(&buf[..n])?;
+                copied += n as u64;
+            }
+            match copied {
+                copied if copied == len => Ok(()),
+                copied => Err(Error::Io(::std::io::Error::new(
+                    ::std::io::ErrorKind::UnexpectedEof,
+                    format!("byte string source ended after {copied} of {len} bytes"),
+                ))),
+            }
+        })
     }
 
     /// Write a utf8-encoded string to the byte stream.
     /// First, the length us written as a variable-length unsigned integer.
     /// Then, the contents of the string are written exactly as-is.
     pub fn write_string(&mut self, v: &str) -> Result<()> {
-        self.write_bytes(v.as_bytes())
+        self.traced(Kind::String, |ser| ser.write_bytes(v.as_bytes()))
+    }
+
+    /// Write the element count of a sequence to the byte stream.
+    fn write_length(&mut self, len: usize) -> Result<()> {
+        let len64 = u64::try_from(len).map_err(|e| Error::Generic(e.to_string()))?;
+        self.traced(Kind::Length, |ser| ser.write_uvarint(len64))
+    }
+
+    /// Write the index of an enum variant to the byte stream,
+    /// and start tracing the variant's payload.
+    fn write_variant(&mut self, variant_index: u32, variant: &'static str) -> Result<()> {
+        self.traced(Kind::Variant, |ser| ser.write_uvarint(variant_index as u64))?;
+        self.enter(Segment::Variant(variant.into()));
+        Ok(())
     }
 
     fn combine_sizes(sizes: impl IntoIterator<Item = usize>) -> Result<usize> {
         sizes
             .into_iter()
-            .fold(Some(0), |agg, v| Some(agg? + v))
+            .try_fold(0usize, |agg, v| agg.checked_add(v))
             .ok_or_else(|| Error::Generic("size too large".into()))
     }
 }
@@ -273,11 +844,11 @@ where
 
     type SerializeTupleVariant = TupleSerializer<'a, W>;
 
-    type SerializeMap = ::serde::ser::Impossible<(), Error>;
+    type SerializeMap = MapSerializer<'a, W>;
 
-    type SerializeStruct = TupleSerializer<'a, W>;
+    type SerializeStruct = StructSerializer<'a, W>;
 
-    type SerializeStructVariant = TupleSerializer<'a, W>;
+    type SerializeStructVariant = StructSerializer<'a, W>;
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok> {
         self.write_bool(v)
@@ -339,9 +910,9 @@ where
         Err(Error::Unsupported("serialize option".into()))
     }
 
-    fn serialize_some<T: ?Sized>(self, _: &T) -> Result<Self::Ok>
+    fn serialize_some<T>(self, _: &T) -> Result<Self::Ok>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
         Err(Error::Unsupported("serialize option".into()))
     }
@@ -358,39 +929,72 @@ where
         self,
         _: &'static str,
         variant_index: u32,
-        _: &'static str,
+        variant: &'static str,
     ) -> Result<Self::Ok> {
-        self.write_uvarint(variant_index as u64)
+        self.write_variant(variant_index, variant)?;
+        let result = match self.options.enum_encoding {
+            EnumEncoding::Plain => Ok(()),
+            EnumEncoding::LengthPrefixed => self.write_length(0),
+        };
+        self.leave();
+        result
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(self, _: &'static str, value: &T) -> Result<Self::Ok>
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Self::Ok>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        value.serialize(self)
+        self.set = name == crate::set::SET;
+        let result = value.serialize(&mut *self);
+        self.set = false;
+        result
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
-        _: &'static str,
+        name: &'static str,
         variant_index: u32,
-        _: &'static str,
+        variant: &'static str,
         value: &T,
     ) -> Result<Self::Ok>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        self.write_uvarint(variant_index as u64)?;
-        value.serialize(self)
+        // The payload of an unknown variant is serialized as a byte string, which is
+        // exactly its length and its bytes.
+        if name == crate::raw::OPEN && self.options.enum_encoding == EnumEncoding::Plain {
+            return Err(Error::Unsupported(
+                "unknown variants without length-prefixed enums".into(),
+            ));
+        }
+        self.write_variant(variant_index, variant)?;
+        let result = match self.payload().filter(|_| name != crate::raw::OPEN) {
+            Some(mut payload) => value
+                .serialize(&mut payload)
+                .and_then(|()| self.write_prefixed(payload)),
+            None => value.serialize(&mut *self),
+        };
+        self.leave();
+        result
     }
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        let set = ::std::mem::take(&mut self.set);
+        if set && self.options.canonical.is_some() {
+            return Ok(CollectionSerializer {
+                index: 0,
+                remaining: len.unwrap_or(usize::MAX),
+                sorted: Some(Vec::new()),
+                ser: self,
+            });
+        }
         match len {
             Some(remaining) => {
-                let len64 = u64::try_from(remaining).map_err(|e| Error::Generic(e.to_string()))?;
-                self.write_uvarint(len64)?;
+                self.write_length(remaining)?;
                 Ok(CollectionSerializer {
+                    index: 0,
                     remaining,
+                    sorted: None,
                     ser: self,
                 })
             }
@@ -399,7 +1003,11 @@ where
     }
 
     fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple> {
-        Ok(TupleSerializer { ser: self })
+        Ok(TupleSerializer {
+            index: 0,
+            payload: None,
+            ser: self,
+        })
     }
 
     fn serialize_tuple_struct(
@@ -407,36 +1015,63 @@ where
         _: &'static str,
         _: usize,
     ) -> Result<Self::SerializeTupleStruct> {
-        Ok(TupleSerializer { ser: self })
+        Ok(TupleSerializer {
+            index: 0,
+            payload: None,
+            ser: self,
+        })
     }
 
     fn serialize_tuple_variant(
         self,
         _: &'static str,
         variant_index: u32,
-        _: &'static str,
+        variant: &'static str,
         _: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        self.write_uvarint(variant_index as u64)?;
-        Ok(TupleSerializer { ser: self })
+        self.write_variant(variant_index, variant)?;
+        Ok(TupleSerializer {
+            index: 0,
+            payload: self.payload(),
+            ser: self,
+        })
     }
 
-    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap> {
-        Err(Error::Unsupported("serialize map".into()))
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
+        if self.options.canonical.is_some() {
+            return Ok(MapSerializer {
+                index: 0,
+                remaining: len,
+                sorted: Some(Vec::new()),
+                ser: self,
+            });
+        }
+        match len {
+            Some(remaining) => {
+                self.write_length(remaining)?;
+                Ok(MapSerializer {
+                    index: 0,
+                    remaining: Some(remaining),
+                    sorted: None,
+                    ser: self,
+                })
+            }
+            None => Err(Error::Unsupported("serialize map (unsized)".into())),
+        }
     }
 
-    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeStruct> {
-        Ok(TupleSerializer { ser: self })
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
+        StructSerializer::new(self, name, None, len)
     }
 
     fn serialize_struct_variant(
         self,
-        _: &'static str,
+        name: &'static str,
         variant_index: u32,
-        _: &'static str,
-        _: usize,
+        variant: &'static str,
+        len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        self.write_uvarint(variant_index as u64)?;
-        Ok(TupleSerializer { ser: self })
+        self.write_variant(variant_index, variant)?;
+        StructSerializer::new(self, name, Some(variant), len)
     }
 }
@@ -0,0 +1,48 @@
+use std::marker::PhantomData;
+
+/// The name that marks a set for the SiBOR serializer and deserializer.
+pub(crate) const SET: &str = "$serde_sibor::Set";
+
+/// Serialize a set, such as a [`std::collections::HashSet`].
+///
+/// Sets are serialized as sequences, which SiBOR can't tell apart from other sequences,
+/// so canonical serializers only sort the elements of sequences marked as sets. Other
+/// serde formats see the set itself.
+pub fn serialize<T, S>(set: &T, s: S) -> ::std::result::Result<S::Ok, S::Error>
+where
+    T: ::serde::Serialize,
+    S: ::serde::Serializer,
+{
+    s.serialize_newtype_struct(SET, set)
+}
+
+/// Deserialize a set, such as a [`std::collections::HashSet`].
+///
+/// Canonical deserializers check that the elements are sorted and unique.
+pub fn deserialize<'de, T, D>(d: D) -> ::std::result::Result<T, D::Error>
+where
+    T: ::serde::Deserialize<'de>,
+    D: ::serde::Deserializer<'de>,
+{
+    struct SetVisitor<T>(PhantomData<fn() -> T>);
+
+    impl<'de, T> ::serde::de::Visitor<'de> for SetVisitor<T>
+    where
+        T: ::serde::Deserialize<'de>,
+    {
+        type Value = T;
+
+        fn expecting(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            write!(f, "a set")
+        }
+
+        fn visit_newtype_struct<D>(self, d: D) -> ::std::result::Result<Self::Value, D::Error>
+        where
+            D: ::serde::Deserializer<'de>,
+        {
+            T::deserialize(d)
+        }
+    }
+
+    d.deserialize_newtype_struct(SET, SetVisitor(PhantomData))
+}
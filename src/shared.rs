@@ -0,0 +1,97 @@
+use ::bytes::{BufMut as _, Bytes, BytesMut};
+
+use crate::error::Result;
+
+::std::thread_local! {
+    /// The input of the innermost [`from_bytes_shared`] call on this thread.
+    static INPUT: ::std::cell::RefCell<Option<Bytes>> = const { ::std::cell::RefCell::new(None) };
+}
+
+/// Restores the input of the enclosing [`from_bytes_shared`] call when dropped.
+struct Restore(Option<Bytes>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        INPUT.with(|input| *input.borrow_mut() = self.0.take());
+    }
+}
+
+/// Decode a value from a shared byte buffer.
+///
+/// Fields that are [`Bytes`] marked with `#[serde(with = "serde_sibor::shared")]` are
+/// decoded as refcounted slices of the buffer instead of being copied.
+pub fn from_bytes_shared<V>(buf: Bytes) -> Result<V>
+where
+    V: ::serde::de::DeserializeOwned,
+{
+    let previous = INPUT.with(|input| input.borrow_mut().replace(buf.clone()));
+    let _restore = Restore(previous);
+    crate::from_bytes(&buf)
+}
+
+/// Encode a value at the end of a byte buffer, such as one taken from a pool.
+pub fn to_bytes_mut<V>(v: V, buf: &mut BytesMut) -> Result<()>
+where
+    V: ::serde::Serialize,
+{
+    crate::to_writer(v, buf.writer())
+}
+
+/// Encode a [`Bytes`] field as a byte string, for `#[serde(with = "serde_sibor::shared")]`.
+pub fn serialize<S>(v: &Bytes, s: S) -> ::std::result::Result<S::Ok, S::Error>
+where
+    S: ::serde::Serializer,
+{
+    s.serialize_bytes(v)
+}
+
+/// Decode a [`Bytes`] field from a byte string, for `#[serde(with = "serde_sibor::shared")]`.
+///
+/// Within [`from_bytes_shared`], the field is a refcounted slice of the input. Anywhere
+/// else, its contents are copied.
+pub fn deserialize<'de, D>(d: D) -> ::std::result::Result<Bytes, D::Error>
+where
+    D: ::serde::Deserializer<'de>,
+{
+    struct SharedVisitor;
+
+    impl<'de> ::serde::de::Visitor<'de> for SharedVisitor {
+        type Value = Bytes;
+
+        fn expecting(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            write!(f, "a byte string")
+        }
+
+        fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> ::std::result::Result<Self::Value, E>
+        where
+            E: ::serde::de::Error,
+        {
+            let range = v.as_ptr_range();
+            INPUT.with(|input| match &*input.borrow() {
+                Some(buf)
+                    if buf.as_ptr_range().start <= range.start
+                        && range.end <= buf.as_ptr_range().end =>
+                {
+                    Ok(buf.slice_ref(v))
+                }
+                _ => Ok(Bytes::copy_from_slice(v)),
+            })
+        }
+
+        fn visit_bytes<E>(self, v: &[u8]) -> ::std::result::Result<Self::Value, E>
+        where
+            E: ::serde::de::Error,
+        {
+            Ok(Bytes::copy_from_slice(v))
+        }
+
+        fn visit_byte_buf<E>(self, v: Vec<u8>) -> ::std::result::Result<Self::Value, E>
+        where
+            E: ::serde::de::Error,
+        {
+            Ok(Bytes::from(v))
+        }
+    }
+
+    d.deserialize_bytes(SharedVisitor)
+}
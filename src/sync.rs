@@ -0,0 +1,302 @@
+use crate::checksum::{crc32c, Crc32c};
+use crate::error::{Error, Result};
+use crate::options::Options;
+
+/// The bytes that start every sync point. The first byte tells sync points apart from records.
+pub const MARKER: [u8; 16] = [
+    0xff, b'S', b'I', b'B', b'O', b'R', b'-', b'S', b'Y', b'N', b'C', 0x5a, 0xa5, 0xc3, 0x3c, 0x96,
+];
+
+/// The number of records between sync points, unless another interval is given.
+pub const DEFAULT_SYNC_INTERVAL: u32 = 64;
+
+/// The largest record that can be written or read. Longer lengths are treated as corruption.
+pub const MAX_RECORD_LEN: u32 = 1 << 26;
+
+/// The tag that starts every record.
+const TAG_RECORD: u8 = 0;
+
+/// The length of the tag, length and checksum before the payload of every record.
+const RECORD_HEADER_LEN: usize = 1 + 4 + 4;
+
+/// The length of a sync point: the marker, the number of the next record and its checksum.
+const SYNC_LEN: usize = MARKER.len() + 8 + 4;
+
+/// A writer for streams of records with periodic sync points.
+///
+/// Every record is a zero tag, a 32-bit little-endian length and a CRC32C checksum of the
+/// length and the payload, followed by the payload. Before the first record, and after
+/// every sync interval of records, a sync point is written: the [`MARKER`] bytes, followed
+/// by the 64-bit little-endian number of the next record and its CRC32C checksum. Readers
+/// that find a corrupted record skip to the next sync point.
+pub struct SyncWriter<W> {
+    writer: W,
+    options: Options,
+    interval: u32,
+    records: u64,
+}
+
+impl<W> SyncWriter<W>
+where
+    W: ::std::io::Write,
+{
+    /// Create a new sync writer.
+    pub fn new(writer: W) -> Self {
+        Self::with_options(writer, Options::default(), DEFAULT_SYNC_INTERVAL)
+    }
+
+    /// Create a new sync writer whose records are encoded with the given options, and which
+    /// writes a sync point after the given number of records.
+    pub fn with_options(writer: W, options: Options, interval: u32) -> Self {
+        Self {
+            writer,
+            options,
+            interval: interval.max(1),
+            records: 0,
+        }
+    }
+
+    /// Get the number of records written so far.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Write a record.
+    pub fn write<V>(&mut self, v: V) -> Result<()>
+    where
+        V: ::serde::Serialize,
+    {
+        let buf = crate::to_bytes_with_options(v, self.options)?;
+        self.write_raw(&buf)
+    }
+
+    /// Write a record that has already been encoded.
+    pub fn write_raw(&mut self, buf: &[u8]) -> Result<()> {
+        let len = u32::try_from(buf.len())
+            .ok()
+            .filter(|len| *len <= MAX_RECORD_LEN)
+            .ok_or_else(|| Error::Invalid(format!("sync: record of {} bytes", buf.len())))?;
+        if self.records % self.interval as u64 == 0 {
+            self.write_sync()?;
+        }
+        let mut crc = Crc32c::new();
+        crc.update(&len.to_le_bytes());
+        crc.update(buf);
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        header[0] = TAG_RECORD;
+        header[1..5].copy_from_slice(&len.to_le_bytes());
+        header[5..9].copy_from_slice(&crc.finish().to_le_bytes());
+        self.writer.write_all(&header).map_err(Error::Io)?;
+        self.writer.write_all(buf).map_err(Error::Io)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush().map_err(Error::Io)
+    }
+
+    /// Get a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_sync(&mut self) -> Result<()> {
+        let mut sync = [0u8; SYNC_LEN];
+        let next = self.records.to_le_bytes();
+        sync[..MARKER.len()].copy_from_slice(&MARKER);
+        sync[MARKER.len()..MARKER.len() + 8].copy_from_slice(&next);
+        sync[MARKER.len() + 8..].copy_from_slice(&crc32c(&next).to_le_bytes());
+        self.writer.write_all(&sync).map_err(Error::Io)
+    }
+}
+
+/// The data that a [`SyncReader`] skipped because it was corrupted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Loss {
+    /// The number of times the reader skipped to the next sync point.
+    pub resyncs: u64,
+    /// The number of bytes that were skipped.
+    pub bytes: u64,
+    /// The number of records that were skipped. Records that were lost at the end of the
+    /// stream, after the last sync point, are counted as one.
+    pub records: u64,
+}
+
+/// A reader for streams written by [`SyncWriter`], which skips corrupted records.
+pub struct SyncReader<R> {
+    reader: R,
+    options: Options,
+    buf: Vec<u8>,
+    start: usize,
+    eof: bool,
+    next: u64,
+    loss: Loss,
+}
+
+impl<R> SyncReader<R>
+where
+    R: ::std::io::Read,
+{
+    /// Create a new sync reader.
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, Options::default())
+    }
+
+    /// Create a new sync reader whose records are decoded with the given options.
+    pub fn with_options(reader: R, options: Options) -> Self {
+        Self {
+            reader,
+            options,
+            buf: Vec::new(),
+            start: 0,
+            eof: false,
+            next: 0,
+            loss: Loss::default(),
+        }
+    }
+
+    /// Get the data that was skipped so far because it was corrupted.
+    pub fn loss(&self) -> Loss {
+        self.loss
+    }
+
+    /// Read the next valid record without decoding it.
+    /// Returns `None` once the end of the stream has been reached.
+    pub fn read_raw(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            if !self.fill(1)? {
+                return Ok(None);
+            }
+            let valid = match self.buf[self.start] {
+                TAG_RECORD => match self.try_record()? {
+                    Some(record) => return Ok(Some(record)),
+                    None => false,
+                },
+                _ => self.try_sync()?,
+            };
+            if !valid {
+                self.resync()?;
+            }
+        }
+    }
+
+    /// Read and decode the next valid record.
+    /// Returns `None` once the end of the stream has been reached.
+    pub fn read<V>(&mut self) -> Result<Option<V>>
+    where
+        V: ::serde::de::DeserializeOwned,
+    {
+        match self.read_raw()? {
+            Some(raw) => crate::from_bytes_with_options(&raw, self.options).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Read the record at the start of the buffer, if it is valid.
+    fn try_record(&mut self) -> Result<Option<Vec<u8>>> {
+        if !self.fill(RECORD_HEADER_LEN)? {
+            return Ok(None);
+        }
+        let header = &self.buf[self.start..self.start + RECORD_HEADER_LEN];
+        let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]);
+        let expected = u32::from_le_bytes([header[5], header[6], header[7], header[8]]);
+        if len > MAX_RECORD_LEN || !self.fill(RECORD_HEADER_LEN + len as usize)? {
+            return Ok(None);
+        }
+        let body = self.start + RECORD_HEADER_LEN;
+        let mut crc = Crc32c::new();
+        crc.update(&len.to_le_bytes());
+        crc.update(&self.buf[body..body + len as usize]);
+        if crc.finish() != expected {
+            return Ok(None);
+        }
+        let record = self.buf[body..body + len as usize].to_vec();
+        self.consume(RECORD_HEADER_LEN + len as usize);
+        self.next += 1;
+        Ok(Some(record))
+    }
+
+    /// Read the sync point at the start of the buffer, if it is valid.
+    fn try_sync(&mut self) -> Result<bool> {
+        if !self.fill(SYNC_LEN)? {
+            return Ok(false);
+        }
+        let sync = &self.buf[self.start..self.start + SYNC_LEN];
+        let (marker, rest) = sync.split_at(MARKER.len());
+        let (next, crc) = rest.split_at(8);
+        if marker != MARKER || crc32c(next).to_le_bytes() != crc {
+            return Ok(false);
+        }
+        let next = u64::from_le_bytes(next.try_into().expect("8 bytes"));
+        self.loss.records += next.saturating_sub(self.next);
+        self.next = next;
+        self.consume(SYNC_LEN);
+        Ok(true)
+    }
+
+    /// Skip to the next valid sync point, or to the end of the stream.
+    fn resync(&mut self) -> Result<()> {
+        self.loss.resyncs += 1;
+        self.skip(1);
+        loop {
+            if !self.fill(1)? {
+                self.loss.records += 1;
+                return Ok(());
+            }
+            let rest = &self.buf[self.start..];
+            match rest.iter().position(|b| *b == MARKER[0]) {
+                Some(i) => {
+                    self.skip(i);
+                    if self.try_sync()? {
+                        return Ok(());
+                    }
+                    self.skip(1);
+                }
+                None => self.skip(rest.len()),
+            }
+        }
+    }
+
+    /// Make sure that the buffer holds at least the given number of bytes.
+    /// Returns `false` if the stream ends before that.
+    fn fill(&mut self, len: usize) -> Result<bool> {
+        if self.buf.len() - self.start >= len {
+            return Ok(true);
+        }
+        self.buf.drain(..self.start);
+        self.start = 0;
+        while self.buf.len() < len && !self.eof {
+            let filled = self.buf.len();
+            self.buf.resize(len.max(filled + 8192), 0);
+            let read = self.reader.read(&mut self.buf[filled..]);
+            self.buf.truncate(filled + *read.as_ref().unwrap_or(&0));
+            match read {
+                Ok(0) => self.eof = true,
+                Ok(_) => {}
+                Err(e) if e.kind() == ::std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(Error::Io(e)),
+            }
+        }
+        Ok(self.buf.len() >= len)
+    }
+
+    fn skip(&mut self, len: usize) {
+        self.consume(len);
+        self.loss.bytes += len as u64;
+    }
+
+    fn consume(&mut self, len: usize) {
+        self.start += len;
+        if self.start == self.buf.len() {
+            self.buf.clear();
+            self.start = 0;
+        }
+    }
+}
@@ -5,10 +5,12 @@ where
     <T as ::arbitrary::Arbitrary>::arbitrary_take_rest(::arbitrary::Unstructured::new(
         &Vec::from_iter(std::iter::repeat_with(::rand::random::<u8>).take(1024)),
     ))
-    .expect(&format!(
-        "failed to generate arbitrary valueof type {}",
-        std::any::type_name::<T>()
-    ))
+    .unwrap_or_else(|_| {
+        panic!(
+            "failed to generate arbitrary valueof type {}",
+            std::any::type_name::<T>()
+        )
+    })
 }
 
 macro_rules! assert_round_trip {
@@ -263,3 +265,2422 @@ fn test_deeply_nested_round_trips() {
         assert_round_trip!(TestEnum);
     }
 }
+
+#[test]
+fn test_schema_compatibility() {
+    use crate::schema::{check_compatibility, Compatibility, Schema};
+
+    let old = Schema::structure(
+        "Message",
+        [
+            ("id", Schema::U8),
+            ("delta", Schema::I32),
+            ("tags", Schema::seq(Schema::String)),
+            (
+                "kind",
+                Schema::enumeration("Kind", [("A", Schema::Unit), ("B", Schema::U32)]),
+            ),
+        ],
+    );
+    assert!(check_compatibility(&old, &old).findings.is_empty());
+
+    let widened = Schema::structure(
+        "Message",
+        [
+            ("id", Schema::U64),
+            ("delta", Schema::I64),
+            ("tags", Schema::seq(Schema::Bytes)),
+            (
+                "kind",
+                Schema::enumeration(
+                    "Kind",
+                    [("A", Schema::Unit), ("B", Schema::U32), ("C", Schema::Bool)],
+                ),
+            ),
+        ],
+    );
+    let report = check_compatibility(&old, &widened);
+    assert!(report.is_compatible(), "{report}");
+    let paths = Vec::from_iter(report.compatible().map(|f| f.path.to_string()));
+    assert_eq!(paths, ["id", "delta", "tags[]", "kind.C"]);
+
+    let broken = Schema::structure(
+        "Message",
+        [
+            ("delta", Schema::U32),
+            ("id", Schema::U8),
+            ("tags", Schema::seq(Schema::String)),
+            ("kind", Schema::enumeration("Kind", [("B", Schema::U32)])),
+        ],
+    );
+    let report = check_compatibility(&old, &broken);
+    assert!(!report.is_compatible());
+    let breaking = Vec::from_iter(report.breaking().map(|f| f.path.to_string()));
+    assert_eq!(breaking, ["id", "delta", "kind.A", "kind.B"]);
+    assert!(report
+        .findings
+        .iter()
+        .all(|f| f.compatibility == Compatibility::Breaking));
+
+    let zigzag = check_compatibility(&Schema::I32, &Schema::U32);
+    assert!(!zigzag.is_compatible());
+    assert!(zigzag.findings[0].message.contains("zigzag"));
+}
+
+#[test]
+fn test_schema_round_trip() {
+    use crate::schema::Schema;
+
+    let schema = Schema::structure(
+        "Message",
+        [
+            ("id", Schema::U64),
+            ("parts", Schema::Tuple(vec![Schema::Bool, Schema::F64])),
+            (
+                "kind",
+                Schema::enumeration(
+                    "Kind",
+                    [("A", Schema::Unit), ("B", Schema::seq(Schema::I8))],
+                ),
+            ),
+        ],
+    );
+    assert_round_trip!(Schema, schema);
+}
+
+#[test]
+fn test_versioned_migrations() {
+    use crate::versioned::{Initial, Migrate, Versioned};
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct UserV1 {
+        name: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct UserV2 {
+        name: String,
+        age: u8,
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct UserV4 {
+        first: String,
+        last: String,
+        age: u8,
+    }
+
+    impl Migrate for UserV1 {
+        const VERSION: u64 = 1;
+        type Previous = Initial;
+        fn migrate(previous: Self::Previous) -> crate::error::Result<Self> {
+            match previous {}
+        }
+    }
+
+    impl Migrate for UserV2 {
+        const VERSION: u64 = 2;
+        type Previous = UserV1;
+        fn migrate(previous: Self::Previous) -> crate::error::Result<Self> {
+            Ok(UserV2 {
+                name: previous.name,
+                age: 0,
+            })
+        }
+    }
+
+    impl Migrate for UserV4 {
+        const VERSION: u64 = 4;
+        type Previous = UserV2;
+        fn migrate(previous: Self::Previous) -> crate::error::Result<Self> {
+            let (first, last) = previous
+                .name
+                .split_once(' ')
+                .ok_or_else(|| crate::Error::Invalid("name".into()))?;
+            Ok(UserV4 {
+                first: first.into(),
+                last: last.into(),
+                age: previous.age,
+            })
+        }
+    }
+
+    let v1 = crate::to_bytes(Versioned(UserV1 {
+        name: "Ada Lovelace".into(),
+    }))
+    .unwrap();
+    assert_eq!(v1[0], 1);
+    let v2 = crate::to_bytes(Versioned(UserV2 {
+        name: "Alan Turing".into(),
+        age: 41,
+    }))
+    .unwrap();
+
+    let decoded = crate::from_bytes::<Versioned<UserV4>>(&v1).unwrap();
+    assert_eq!(
+        decoded.into_inner(),
+        UserV4 {
+            first: "Ada".into(),
+            last: "Lovelace".into(),
+            age: 0
+        }
+    );
+    let decoded = crate::from_bytes::<Versioned<UserV4>>(&v2).unwrap();
+    assert_eq!(decoded.age, 41);
+
+    let latest = Versioned(UserV4 {
+        first: "Grace".into(),
+        last: "Hopper".into(),
+        age: 85,
+    });
+    assert_round_trip!(Versioned<UserV4>, latest.clone());
+    assert_eq!(crate::to_bytes(&latest).unwrap()[0], 4);
+
+    // Versions that are unknown, or too new for the reader, are rejected.
+    assert!(crate::from_bytes::<Versioned<UserV2>>(&crate::to_bytes(&latest).unwrap()).is_err());
+    let unknown = crate::to_bytes((3u64, "x")).unwrap();
+    assert!(crate::from_bytes::<Versioned<UserV4>>(&unknown).is_err());
+    // A failed migration surfaces as an error.
+    let unsplittable = crate::to_bytes(Versioned(UserV1 {
+        name: "Plato".into(),
+    }))
+    .unwrap();
+    assert!(crate::from_bytes::<Versioned<UserV4>>(&unsplittable).is_err());
+}
+
+#[test]
+fn test_container_round_trip() {
+    use crate::container::{ContainerReader, ContainerWriter, MAGIC};
+    use crate::schema::Schema;
+    use crate::value::Value;
+
+    #[derive(
+        Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize, ::arbitrary::Arbitrary,
+    )]
+    enum Shape {
+        Point,
+        Circle { radius: i64 },
+    }
+
+    #[derive(
+        Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize, ::arbitrary::Arbitrary,
+    )]
+    struct Record {
+        id: u32,
+        name: String,
+        shapes: Vec<Shape>,
+    }
+
+    let schema = Schema::structure(
+        "Record",
+        [
+            ("id", Schema::U32),
+            ("name", Schema::String),
+            (
+                "shapes",
+                Schema::seq(Schema::enumeration(
+                    "Shape",
+                    [
+                        ("Point", Schema::Unit),
+                        (
+                            "Circle",
+                            Schema::structure("Circle", [("radius", Schema::I64)]),
+                        ),
+                    ],
+                )),
+            ),
+        ],
+    );
+
+    let records = Vec::from_iter((0..10).map(|_| arbitrary_value::<Record>()));
+    let mut writer = ContainerWriter::new(Vec::new(), Some(&schema)).unwrap();
+    for record in &records {
+        writer.write(record).unwrap();
+    }
+    let buf = writer.into_inner();
+    assert_eq!(buf[..4], MAGIC);
+
+    // Typed decoding.
+    let mut reader = ContainerReader::new(&buf[..]).unwrap();
+    assert_eq!(reader.schema(), Some(&schema));
+    for record in &records {
+        assert_eq!(reader.read::<Record>().unwrap().as_ref(), Some(record));
+    }
+    assert!(reader.read::<Record>().unwrap().is_none());
+
+    // Dynamic decoding with only the embedded schema, then re-encoding.
+    let mut reader = ContainerReader::new(&buf[..]).unwrap();
+    let mut values = Vec::new();
+    while let Some(value) = reader.read_value().unwrap() {
+        values.push(value);
+    }
+    assert_eq!(values.len(), records.len());
+    for (value, record) in values.iter().zip(records.iter()) {
+        let Value::Struct(fields) = value else {
+            panic!("expected a struct, got {value:?}");
+        };
+        assert_eq!(fields[0], ("id".into(), Value::Unsigned(record.id as u64)));
+        assert_eq!(value.to_bytes().unwrap(), crate::to_bytes(record).unwrap());
+    }
+
+    // Bad magic and containers without a schema.
+    assert!(ContainerReader::new(&b"NOPE\x01\x00\x00"[..]).is_err());
+    let mut writer = ContainerWriter::new(Vec::new(), None).unwrap();
+    writer.write(&records[0]).unwrap();
+    let buf = writer.into_inner();
+    let mut reader = ContainerReader::new(&buf[..]).unwrap();
+    assert!(reader.schema().is_none());
+    assert!(reader.read_value().is_err());
+
+    // Options that aren't stored in the header are given to the reader again.
+    #[derive(Debug, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Point {
+        x: u32,
+        y: u32,
+    }
+    let numbered = crate::options::Options {
+        struct_encoding: crate::options::StructEncoding::Numbered,
+        field_numbers: Some(|_, field| Some(if field == "x" { 5 } else { 1 })),
+        ..Default::default()
+    };
+    let mut writer = ContainerWriter::with_options(Vec::new(), None, numbered).unwrap();
+    writer.write(Point { x: 1, y: 2 }).unwrap();
+    let buf = writer.into_inner();
+    assert!(ContainerReader::with_options(&buf[..], Default::default()).is_err());
+    let mut reader = ContainerReader::with_options(&buf[..], numbered).unwrap();
+    assert_eq!(reader.read().unwrap(), Some(Point { x: 1, y: 2 }));
+    let mut reader = ContainerReader::new(&buf[..]).unwrap();
+    assert!(reader.read::<Point>().is_err());
+}
+
+#[test]
+fn test_transcode_json() {
+    use crate::schema::Schema;
+    use crate::transcode::{transcode_bytes, transcode_from_to_bytes};
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    enum Event {
+        Started,
+        Moved(i16, i16),
+        Renamed { from: String, to: String },
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Log {
+        id: u64,
+        ratio: f32,
+        #[serde(with = "serde_bytes")]
+        digest: Vec<u8>,
+        events: Vec<Event>,
+    }
+
+    let schema = Schema::structure(
+        "Log",
+        [
+            ("id", Schema::U64),
+            ("ratio", Schema::F32),
+            ("digest", Schema::Bytes),
+            (
+                "events",
+                Schema::seq(Schema::enumeration(
+                    "Event",
+                    [
+                        ("Started", Schema::Unit),
+                        ("Moved", Schema::Tuple(vec![Schema::I16, Schema::I16])),
+                        (
+                            "Renamed",
+                            Schema::structure(
+                                "Renamed",
+                                [("from", Schema::String), ("to", Schema::String)],
+                            ),
+                        ),
+                    ],
+                )),
+            ),
+        ],
+    );
+
+    let log = Log {
+        id: 7,
+        ratio: 0.1,
+        digest: vec![0xde, 0xad],
+        events: vec![
+            Event::Started,
+            Event::Moved(-3, 4),
+            Event::Renamed {
+                from: "a".into(),
+                to: "b".into(),
+            },
+        ],
+    };
+
+    // SiBOR to JSON, without access to the Rust types.
+    let encoded = crate::to_bytes(&log).unwrap();
+    let mut json = Vec::new();
+    transcode_bytes(
+        &schema,
+        &encoded,
+        &mut ::serde_json::Serializer::new(&mut json),
+    )
+    .unwrap();
+    assert_eq!(json, ::serde_json::to_vec(&log).unwrap());
+
+    // JSON to SiBOR, with fields in any order.
+    let fixture = r#"{
+        "events": ["Started", {"Moved": [-3, 4]}, {"Renamed": {"to": "b", "from": "a"}}],
+        "digest": [222, 173],
+        "ratio": 0.1,
+        "id": 7
+    }"#;
+    let transcoded =
+        transcode_from_to_bytes(&schema, &mut ::serde_json::Deserializer::from_str(fixture))
+            .unwrap();
+    assert_eq!(transcoded, encoded);
+    assert_eq!(crate::from_bytes::<Log>(&transcoded).unwrap(), log);
+
+    // Values that don't fit the schema are rejected.
+    for bad in [
+        r#"{"id": -1, "ratio": 0, "digest": [], "events": []}"#,
+        r#"{"id": 1, "ratio": 0, "digest": [], "events": ["Unknown"]}"#,
+        r#"{"id": 1, "ratio": 0, "digest": []}"#,
+    ] {
+        assert!(
+            transcode_from_to_bytes(&schema, &mut ::serde_json::Deserializer::from_str(bad))
+                .is_err()
+        );
+    }
+}
+
+#[test]
+fn test_trace_spans() {
+    use crate::schema::Schema;
+    use crate::trace::{render, trace_bytes, trace_value, Kind};
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    enum Shape {
+        Point,
+        Line(i8, i8),
+        Circle { radius: u32 },
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Drawing {
+        name: String,
+        visible: bool,
+        shapes: Vec<Shape>,
+        scale: (f64, f64),
+    }
+
+    let schema = Schema::structure(
+        "Drawing",
+        [
+            ("name", Schema::String),
+            ("visible", Schema::Bool),
+            (
+                "shapes",
+                Schema::seq(Schema::enumeration(
+                    "Shape",
+                    [
+                        ("Point", Schema::Unit),
+                        ("Line", Schema::Tuple(vec![Schema::I8, Schema::I8])),
+                        (
+                            "Circle",
+                            Schema::structure("Circle", [("radius", Schema::U32)]),
+                        ),
+                    ],
+                )),
+            ),
+            ("scale", Schema::Tuple(vec![Schema::F64, Schema::F64])),
+        ],
+    );
+
+    let drawing = Drawing {
+        name: "sketch".into(),
+        visible: true,
+        shapes: vec![
+            Shape::Point,
+            Shape::Line(-1, 1),
+            Shape::Circle { radius: 300 },
+        ],
+        scale: (1.0, 0.5),
+    };
+    let (buf, spans) = trace_value(&drawing).unwrap();
+    assert_eq!(buf, crate::to_bytes(&drawing).unwrap());
+
+    let described = Vec::from_iter(
+        spans
+            .iter()
+            .map(|span| (span.path.to_string(), span.kind, span.len())),
+    );
+    assert_eq!(
+        described,
+        [
+            ("name".to_string(), Kind::String, 7),
+            ("visible".into(), Kind::Bool, 1),
+            ("shapes".into(), Kind::Length, 1),
+            ("shapes[0]".into(), Kind::Variant, 1),
+            ("shapes[1]".into(), Kind::Variant, 1),
+            ("shapes[1].Line.0".into(), Kind::Signed, 1),
+            ("shapes[1].Line.1".into(), Kind::Signed, 1),
+            ("shapes[2]".into(), Kind::Variant, 1),
+            ("shapes[2].Circle.radius".into(), Kind::Unsigned, 2),
+            ("scale.0".into(), Kind::Float, 9),
+            ("scale.1".into(), Kind::Float, 9),
+        ]
+    );
+    assert_eq!(spans.last().unwrap().end, buf.len());
+
+    // Decoding with a schema finds the same spans.
+    assert_eq!(trace_bytes(&schema, &buf).unwrap(), spans);
+
+    let dump = render(&buf, &spans);
+    assert_eq!(dump.lines().count(), spans.len());
+    assert!(dump.contains("shapes[2].Circle.radius = 300"), "{dump}");
+    assert!(dump.contains("name = \"sketch\""), "{dump}");
+
+    // Bytes that no span covers are still shown.
+    let mut padded = buf.clone();
+    padded.push(0xff);
+    assert_eq!(render(&padded, &spans).lines().count(), spans.len() + 1);
+}
+
+#[test]
+fn test_profile_report() {
+    use crate::path::{Path, Segment};
+    use crate::profile::{Profiler, SortBy};
+    use crate::schema::Schema;
+    use crate::trace::Kind;
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Point {
+        x: f64,
+        y: u8,
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Track {
+        name: String,
+        points: Vec<Point>,
+    }
+
+    let track = Track {
+        name: "run".into(),
+        points: vec![Point { x: 1.5, y: 1 }, Point { x: -2.25, y: 200 }],
+    };
+
+    let mut profiler = Profiler::new();
+    profiler.add(&track).unwrap();
+    let schema = Schema::structure(
+        "Track",
+        [
+            ("name", Schema::String),
+            (
+                "points",
+                Schema::seq(Schema::structure(
+                    "Point",
+                    [("x", Schema::F64), ("y", Schema::U8)],
+                )),
+            ),
+        ],
+    );
+    profiler
+        .add_bytes(&schema, &crate::to_bytes(&track).unwrap())
+        .unwrap();
+
+    let mut report = profiler.report();
+    let encoded_size = crate::encoded_size(&track).unwrap();
+    assert_eq!(report.values, 2);
+    assert_eq!(report.total_bytes, 2 * encoded_size);
+
+    // Floats are stored as 9 or 10 byte varints of their bits, and dominate the encoding.
+    let x = Path::new()
+        .join(Segment::Field("points".into()))
+        .join(Segment::Index(None))
+        .join(Segment::Field("x".into()));
+    let top = &report.paths[0];
+    assert_eq!((&top.path, top.kind), (&x, Kind::Float));
+    assert_eq!((top.usage.bytes, top.usage.count), (38, 4));
+    assert_eq!(report.kinds[0].0, Kind::Float);
+
+    // `y` is one byte for 1 and two bytes for 200.
+    let unsigned = report
+        .varint_lengths
+        .iter()
+        .find(|(kind, _)| *kind == Kind::Unsigned)
+        .unwrap();
+    assert_eq!(unsigned.1, vec![(1, 2), (2, 2)]);
+    let string = report
+        .varint_lengths
+        .iter()
+        .find(|(kind, _)| *kind == Kind::String)
+        .unwrap();
+    assert_eq!(string.1, vec![(1, 2)]);
+
+    report.sort_by(SortBy::Path);
+    assert!(report.paths.windows(2).all(|w| w[0].path <= w[1].path));
+    assert!(report.to_string().contains("points[].x"));
+}
+
+#[test]
+fn test_view_seek() {
+    use crate::path::{Path, Segment};
+    use crate::schema::Schema;
+    use crate::view::View;
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Header {
+        id: u64,
+        route: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    enum Body {
+        Empty,
+        Items(Vec<(String, i32)>),
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Message {
+        header: Header,
+        body: Body,
+        trailer: bool,
+    }
+
+    let schema = Schema::structure(
+        "Message",
+        [
+            (
+                "header",
+                Schema::structure("Header", [("id", Schema::U64), ("route", Schema::String)]),
+            ),
+            (
+                "body",
+                Schema::enumeration(
+                    "Body",
+                    [
+                        ("Empty", Schema::Unit),
+                        (
+                            "Items",
+                            Schema::seq(Schema::Tuple(vec![Schema::String, Schema::I32])),
+                        ),
+                    ],
+                ),
+            ),
+            ("trailer", Schema::Bool),
+        ],
+    );
+
+    let items = Vec::from_iter((0..100).map(|i| (format!("item {i}"), -i)));
+    let message = Message {
+        header: Header {
+            id: 1234567,
+            route: "a/b".into(),
+        },
+        body: Body::Items(items.clone()),
+        trailer: true,
+    };
+    let buf = crate::to_bytes(&message).unwrap();
+    let view = View::new(&schema, &buf);
+
+    assert_eq!(
+        view.seek("header.id").unwrap().decode::<u64>().unwrap(),
+        1234567
+    );
+    assert_eq!(
+        view.seek("header.route")
+            .unwrap()
+            .decode::<String>()
+            .unwrap(),
+        "a/b"
+    );
+    assert_eq!(
+        view.seek("body.Items[42].0")
+            .unwrap()
+            .decode::<String>()
+            .unwrap(),
+        "item 42"
+    );
+    assert!(view.seek("trailer").unwrap().decode::<bool>().unwrap());
+    assert_eq!(view.seek("").unwrap().decode::<Message>().unwrap(), message);
+    assert_eq!(view.bytes().unwrap(), &buf[..]);
+
+    let seq = view.seek("body.Items").unwrap();
+    assert_eq!(seq.len().unwrap(), 100);
+    let decoded = Vec::from_iter(
+        seq.elements()
+            .unwrap()
+            .map(|e| e.unwrap().decode::<(String, i32)>().unwrap()),
+    );
+    assert_eq!(decoded, items);
+
+    assert!(view.seek("body.Empty").is_err());
+    assert!(view.seek("body.Items[100]").is_err());
+    assert!(view.seek("header.missing").is_err());
+    assert!(view.seek("header[0]").is_err());
+    assert!(View::new(&schema, &buf[..20]).seek("trailer").is_err());
+
+    // Skipping any number of zero-sized elements takes no time.
+    let mut forged = crate::to_bytes(u64::MAX).unwrap();
+    forged.push(42);
+    for element in [
+        Schema::Unit,
+        Schema::Tuple(vec![]),
+        Schema::Tuple(vec![Schema::Unit]),
+    ] {
+        let schema = Schema::Tuple(vec![Schema::seq(element), Schema::U8]);
+        let view = View::new(&schema, &forged);
+        assert_eq!(view.element(1).unwrap().decode::<u8>().unwrap(), 42);
+    }
+
+    let path: Path = "items[42].name.0[]".parse().unwrap();
+    assert_eq!(
+        path.segments(),
+        &[
+            Segment::Field("items".into()),
+            Segment::Index(Some(42)),
+            Segment::Field("name".into()),
+            Segment::Element(0),
+            Segment::Index(None),
+        ]
+    );
+    assert_eq!(path.to_string(), "items[42].name.0[]");
+    assert!("a..b".parse::<Path>().is_err());
+    assert!("a[x]".parse::<Path>().is_err());
+    assert!(".a".parse::<Path>().is_err());
+}
+
+#[test]
+fn test_raw_payloads() {
+    use crate::raw::{Raw, RawSibor};
+    use ::serde::Deserialize as _;
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Order {
+        id: u64,
+        items: Vec<String>,
+    }
+
+    #[derive(Debug, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Envelope<'a> {
+        topic: &'a str,
+        #[serde(borrow)]
+        payload: Raw<'a, Order>,
+    }
+
+    #[derive(Debug, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Forward<'a> {
+        hops: u8,
+        #[serde(borrow)]
+        payload: RawSibor<'a>,
+    }
+
+    let order = Order {
+        id: 7,
+        items: vec!["tea".into(), "milk".into()],
+    };
+    let envelope = Envelope {
+        topic: "orders",
+        payload: Raw::new(&order).unwrap(),
+    };
+    let buf = crate::to_bytes(&envelope).unwrap();
+
+    // The payload is embedded as a length-prefixed blob.
+    let encoded_order = crate::to_bytes(&order).unwrap();
+    let mut expected = crate::to_bytes(("orders", encoded_order.len() as u64)).unwrap();
+    expected.extend(&encoded_order);
+    assert_eq!(buf, expected);
+
+    let decoded: Envelope = crate::from_bytes(&buf).unwrap();
+    assert_eq!(decoded, envelope);
+    assert!(decoded.payload.as_raw().is_borrowed());
+    assert_eq!(decoded.payload.decode().unwrap(), order);
+
+    // Passing the payload through does not decode it.
+    let forward = Forward {
+        hops: 1,
+        payload: decoded.payload.into_raw(),
+    };
+    let forwarded = crate::to_bytes(&forward).unwrap();
+    let forward: Forward = crate::from_bytes(&forwarded).unwrap();
+    assert_eq!(forward.payload.decode::<Order>().unwrap(), order);
+
+    // Reading from an `io::Read` copies the payload.
+    let mut de = crate::de::Deserializer::new(&buf[..]);
+    let (_, raw) = <(String, RawSibor)>::deserialize(&mut de).unwrap();
+    assert!(!raw.is_borrowed());
+    assert_eq!(raw.decode::<Order>().unwrap(), order);
+
+    let mut trailing = encoded_order.clone();
+    trailing.push(0);
+    assert!(RawSibor::new(trailing).decode::<Order>().is_err());
+}
+
+#[test]
+fn test_length_prefixed_structs() {
+    use crate::options::{Options, StructEncoding};
+    use crate::schema::{check_compatibility_with_options, Schema};
+    use crate::trace::{trace_bytes_with_options, Kind};
+    use crate::view::View;
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct PointV1 {
+        x: i32,
+        label: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct PointV2 {
+        x: i32,
+        label: String,
+        #[serde(default)]
+        y: i32,
+        #[serde(default)]
+        tags: Vec<String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    enum Shape<P> {
+        Dot(P),
+        Line { from: P, to: P },
+    }
+
+    let options = Options {
+        struct_encoding: StructEncoding::LengthPrefixed,
+        ..Options::default()
+    };
+    let v1 = PointV1 {
+        x: -3,
+        label: "a".into(),
+    };
+    let v2 = PointV2 {
+        x: 7,
+        label: "b".into(),
+        y: 9,
+        tags: vec!["t".into()],
+    };
+
+    // The default options keep the plain encoding.
+    assert_eq!(
+        crate::to_bytes_with_options(&v2, Options::default()).unwrap(),
+        crate::to_bytes(&v2).unwrap()
+    );
+
+    // Each struct body is prefixed with its length, and starts with its number of fields.
+    let encoded = crate::to_bytes_with_options(&v1, options).unwrap();
+    assert_eq!(encoded, [4, 2, 5, 1, b'a']);
+
+    // New readers fill missing trailing fields from their defaults.
+    let shapes = vec![
+        Shape::Line {
+            from: v1.clone(),
+            to: v1.clone(),
+        },
+        Shape::Dot(v1.clone()),
+    ];
+    let buf = crate::to_bytes_with_options((&shapes, 42u8), options).unwrap();
+    let (upgraded, after): (Vec<Shape<PointV2>>, u8) =
+        crate::from_bytes_with_options(&buf, options).unwrap();
+    let defaulted = PointV2 {
+        x: -3,
+        label: "a".into(),
+        y: 0,
+        tags: vec![],
+    };
+    assert_eq!(
+        upgraded,
+        vec![
+            Shape::Line {
+                from: defaulted.clone(),
+                to: defaulted.clone(),
+            },
+            Shape::Dot(defaulted),
+        ]
+    );
+    assert_eq!(after, 42);
+
+    // Old readers skip unknown trailing fields, from slices and from readers.
+    let buf = crate::to_bytes_with_options((&v2, 42u8), options).unwrap();
+    let (downgraded, after): (PointV1, u8) = crate::from_bytes_with_options(&buf, options).unwrap();
+    assert_eq!(
+        (downgraded.x, downgraded.label.as_str(), after),
+        (7, "b", 42)
+    );
+    let mut de = crate::de::Deserializer::with_options(&buf[..], options);
+    let (downgraded, after) =
+        <(PointV1, u8) as ::serde::Deserialize>::deserialize(&mut de).unwrap();
+    assert_eq!((downgraded.x, after), (7, 42));
+
+    // Missing fields without a default are still an error.
+    let buf = crate::to_bytes_with_options(&v1, options).unwrap();
+    assert!(crate::from_bytes_with_options::<(PointV1, u8)>(&buf, options).is_err());
+    assert!(crate::from_bytes_with_options::<PointV1>(&[1, 2], options).is_err());
+
+    // Schema-driven readers honor the options too.
+    let v1_schema = Schema::structure("Point", [("x", Schema::I32), ("label", Schema::String)]);
+    let v2_schema = Schema::structure(
+        "Point",
+        [
+            ("x", Schema::I32),
+            ("label", Schema::String),
+            ("y", Schema::I32),
+            ("tags", Schema::seq(Schema::String)),
+        ],
+    );
+    let schema = Schema::Tuple(vec![v1_schema.clone(), Schema::U8]);
+    let buf = crate::to_bytes_with_options((&v2, 42u8), options).unwrap();
+    let mut de = crate::de::Deserializer::with_options(&buf[..], options);
+    let value = crate::value::Value::decode(&schema, &mut de).unwrap();
+    let expected = PointV1 {
+        x: 7,
+        label: "b".into(),
+    };
+    assert_eq!(
+        crate::to_bytes_with_options(&value, options).unwrap(),
+        crate::to_bytes_with_options((&expected, 42u8), options).unwrap()
+    );
+    let view = View::with_options(&schema, &buf, options);
+    assert_eq!(view.seek("1").unwrap().decode::<u8>().unwrap(), 42);
+    assert_eq!(
+        view.seek("0.label").unwrap().decode::<String>().unwrap(),
+        "b"
+    );
+    let old = crate::to_bytes_with_options(&v1, options).unwrap();
+    assert!(View::with_options(&v2_schema, &old, options)
+        .seek("y")
+        .is_err());
+
+    let spans = trace_bytes_with_options(&schema, &buf, options).unwrap();
+    assert_eq!(spans[0].kind, Kind::Length);
+    assert_eq!(spans[0].path.to_string(), "0");
+    assert_eq!(spans.last().unwrap().path.to_string(), "1");
+    let mut profiler = crate::profile::Profiler::with_options(options);
+    profiler.add(&shapes).unwrap();
+    let traced = crate::to_bytes_with_options(&shapes, options).unwrap();
+    assert_eq!(profiler.report().total_bytes, traced.len());
+
+    // Appending and removing trailing fields is only wire-safe with length prefixes.
+    assert!(!crate::schema::check_compatibility(&v1_schema, &v2_schema).is_compatible());
+    assert!(check_compatibility_with_options(&v1_schema, &v2_schema, &options).is_compatible());
+    assert!(check_compatibility_with_options(&v2_schema, &v1_schema, &options).is_compatible());
+    let reordered = Schema::structure("Point", [("label", Schema::String), ("x", Schema::I32)]);
+    assert!(!check_compatibility_with_options(&v1_schema, &reordered, &options).is_compatible());
+}
+
+#[test]
+fn test_numbered_fields() {
+    use crate::options::{Options, StructEncoding};
+    use crate::schema::{check_compatibility_with_options, Schema};
+    use crate::trace::{trace_bytes_with_options, Kind};
+    use crate::view::View;
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct UserV1 {
+        id: u64,
+        name: String,
+        email: String,
+    }
+
+    // Drops `email`, moves `name` to the end, and adds `tags`.
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct UserV2 {
+        id: u64,
+        #[serde(default)]
+        tags: Vec<String>,
+        name: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    enum Event {
+        Joined { user: UserV1, at: u64 },
+    }
+
+    fn numbers(container: &str, field: &str) -> Option<u64> {
+        match (container, field) {
+            ("UserV1" | "UserV2" | "User", "id") => Some(1),
+            ("UserV1" | "UserV2" | "User", "name") => Some(2),
+            ("UserV1" | "User", "email") => Some(3),
+            ("UserV2" | "User", "tags") => Some(4),
+            ("Event::Joined", "at") => Some(9),
+            _ => None,
+        }
+    }
+
+    let options = Options {
+        struct_encoding: StructEncoding::Numbered,
+        field_numbers: Some(numbers),
+        ..Options::default()
+    };
+    let v1 = UserV1 {
+        id: 5,
+        name: "a".into(),
+        email: "e".into(),
+    };
+
+    // The field count, then a key and a value per field: `id` is a single varint,
+    // the others are length-prefixed.
+    let encoded = crate::to_bytes_with_options(&v1, options).unwrap();
+    assert_eq!(
+        encoded,
+        [3, 1 << 3, 5, 2 << 3 | 2, 2, 1, b'a', 3 << 3 | 2, 2, 1, b'e']
+    );
+
+    // New readers skip `email` and fill `tags` from its default, regardless of order.
+    let buf = crate::to_bytes_with_options((&v1, 42u8), options).unwrap();
+    let (v2, after): (UserV2, u8) = crate::from_bytes_with_options(&buf, options).unwrap();
+    assert_eq!(
+        (v2.clone(), after),
+        (
+            UserV2 {
+                id: 5,
+                tags: vec![],
+                name: "a".into(),
+            },
+            42
+        )
+    );
+
+    // Old readers skip `tags` and fail on the missing `email`, which has no default.
+    let buf = crate::to_bytes_with_options(&v2, options).unwrap();
+    assert!(crate::from_bytes_with_options::<UserV1>(&buf, options).is_err());
+
+    // Struct variants are numbered as `Enum::Variant`, and the rest by index.
+    let event = Event::Joined {
+        user: v1.clone(),
+        at: 7,
+    };
+    let buf = crate::to_bytes_with_options(&event, options).unwrap();
+    // Variant 0 with two fields, then `user` as field 0, which is length-prefixed.
+    assert_eq!(&buf[..3], [0, 2, 2]);
+    assert_eq!(&buf[buf.len() - 2..], [9 << 3, 7]);
+    let mut de = crate::de::Deserializer::with_options(&buf[..], options);
+    assert_eq!(
+        <Event as ::serde::Deserialize>::deserialize(&mut de).unwrap(),
+        event
+    );
+
+    // Schema-driven readers match fields by number too.
+    let v1_schema = Schema::structure(
+        "User",
+        [
+            ("id", Schema::U64),
+            ("name", Schema::String),
+            ("email", Schema::String),
+        ],
+    );
+    let v2_schema = Schema::structure(
+        "User",
+        [
+            ("id", Schema::U64),
+            ("tags", Schema::seq(Schema::String)),
+            ("name", Schema::String),
+        ],
+    );
+    let buf = crate::to_bytes_with_options(&v1, options).unwrap();
+    let mut de = crate::de::Deserializer::with_options(&buf[..], options);
+    let value = crate::value::Value::decode(&v1_schema, &mut de).unwrap();
+    assert_eq!(
+        crate::to_bytes_with_options(&value, options).unwrap(),
+        crate::to_bytes_with_options(
+            &v1,
+            Options {
+                field_numbers: None,
+                ..options
+            }
+        )
+        .unwrap()
+    );
+    let view = View::with_options(&v2_schema, &buf, options);
+    assert_eq!(view.seek("name").unwrap().decode::<String>().unwrap(), "a");
+    assert_eq!(view.seek("id").unwrap().decode::<u64>().unwrap(), 5);
+    assert!(view.seek("tags").is_err());
+    assert_eq!(view.bytes().unwrap(), buf);
+
+    let spans = trace_bytes_with_options(&v2_schema, &buf, options).unwrap();
+    let keys: Vec<_> = spans
+        .iter()
+        .filter(|span| span.kind == Kind::Key)
+        .map(|span| span.path.to_string())
+        .collect();
+    assert_eq!(keys, ["id", "name", ""]);
+
+    // Fields can be added and removed, but not renumbered.
+    assert!(!crate::schema::check_compatibility(&v1_schema, &v2_schema).is_compatible());
+    assert!(check_compatibility_with_options(&v1_schema, &v2_schema, &options).is_compatible());
+    assert!(check_compatibility_with_options(&v2_schema, &v1_schema, &options).is_compatible());
+    let by_index = Options {
+        field_numbers: None,
+        ..options
+    };
+    assert!(!check_compatibility_with_options(&v1_schema, &v2_schema, &by_index).is_compatible());
+}
+
+#[test]
+fn test_unknown_variants() {
+    use crate::options::{EnumEncoding, Options};
+    use crate::raw::Open;
+    use crate::schema::Schema;
+    use crate::trace::{trace_bytes_with_options, Kind};
+    use crate::view::View;
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    enum EventV1 {
+        Ping,
+        Message(String),
+        #[serde(other)]
+        Other,
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    enum EventV2 {
+        Ping,
+        Message(String),
+        Other,
+        Moved { x: i32, y: i32 },
+        Tagged(u8, String),
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    enum Closed {
+        Ping,
+        Message(String),
+    }
+
+    let options = Options {
+        enum_encoding: EnumEncoding::LengthPrefixed,
+        ..Options::default()
+    };
+    let events = vec![
+        EventV2::Moved { x: 1, y: -1 },
+        EventV2::Message("hi".into()),
+        EventV2::Tagged(7, "t".into()),
+        EventV2::Ping,
+    ];
+
+    // Each payload is prefixed with its length.
+    assert_eq!(
+        crate::to_bytes_with_options(EventV2::Ping, options).unwrap(),
+        [0, 0]
+    );
+    assert_eq!(
+        crate::to_bytes_with_options(EventV2::Moved { x: 1, y: -1 }, options).unwrap(),
+        [3, 2, 2, 1]
+    );
+
+    // Old readers skip the payloads of unknown variants into `#[serde(other)]`.
+    let buf = crate::to_bytes_with_options((&events, 42u8), options).unwrap();
+    let (old, after): (Vec<EventV1>, u8) = crate::from_bytes_with_options(&buf, options).unwrap();
+    assert_eq!(
+        old,
+        [
+            EventV1::Other,
+            EventV1::Message("hi".into()),
+            EventV1::Other,
+            EventV1::Ping
+        ]
+    );
+    assert_eq!(after, 42);
+
+    // Without length prefixes, unknown payloads can't be skipped.
+    let plain = crate::to_bytes(&events).unwrap();
+    assert!(crate::from_bytes::<Vec<EventV1>>(&plain).is_err());
+    assert!(crate::from_bytes::<Vec<Open<Closed>>>(&plain).is_err());
+
+    // Open enums capture unknown variants, and forward them unchanged.
+    let mut de = crate::de::Deserializer::with_options(&buf[..], options);
+    let (open, after) =
+        <(Vec<Open<Closed>>, u8) as ::serde::Deserialize>::deserialize(&mut de).unwrap();
+    assert_eq!(
+        open,
+        [
+            Open::Unknown {
+                index: 3,
+                payload: vec![2, 1],
+            },
+            Open::Known(Closed::Message("hi".into())),
+            Open::Unknown {
+                index: 4,
+                payload: vec![7, 1, b't'],
+            },
+            Open::Known(Closed::Ping),
+        ]
+    );
+    assert_eq!(after, 42);
+    assert_eq!(
+        crate::to_bytes_with_options((&open, 42u8), options).unwrap(),
+        buf
+    );
+    assert!(crate::to_bytes(&open).is_err());
+    let (forwarded, _): (Vec<EventV2>, u8) = crate::from_bytes_with_options(&buf, options).unwrap();
+    assert_eq!(forwarded, events);
+
+    // Schema-driven readers skip to the end of each payload.
+    let point = Schema::structure("Moved", [("x", Schema::I32), ("y", Schema::I32)]);
+    let schema = Schema::seq(Schema::enumeration(
+        "Event",
+        [
+            ("Ping", Schema::Unit),
+            ("Message", Schema::String),
+            ("Other", Schema::Unit),
+            ("Moved", point),
+            ("Tagged", Schema::Tuple(vec![Schema::U8, Schema::String])),
+        ],
+    ));
+    let mut de = crate::de::Deserializer::with_options(&buf[..], options);
+    let value = crate::value::Value::decode(&schema, &mut de).unwrap();
+    assert_eq!(
+        crate::to_bytes_with_options(&value, options).unwrap(),
+        crate::to_bytes_with_options(&events, options).unwrap()
+    );
+    let view = View::with_options(&schema, &buf, options);
+    assert_eq!(
+        view.seek("[2].Tagged.1")
+            .unwrap()
+            .decode::<String>()
+            .unwrap(),
+        "t"
+    );
+    assert_eq!(
+        view.seek("[0].Moved.y").unwrap().decode::<i32>().unwrap(),
+        -1
+    );
+    let spans = trace_bytes_with_options(&schema, &buf, options).unwrap();
+    assert_eq!(
+        spans
+            .iter()
+            .filter(|span| span.kind == Kind::Length)
+            .count(),
+        5
+    );
+
+    // The option is stored in container headers.
+    assert_eq!(Options::from_bits(options.to_bits()).unwrap(), options);
+}
+
+#[test]
+fn test_open_nested_enums() {
+    use crate::options::{EnumEncoding, Options};
+    use crate::raw::Open;
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    enum KindV1 {
+        Small,
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    enum KindV2 {
+        Small,
+        Large(u32),
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Wrapper<K> {
+        tag: u8,
+        kind: K,
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Newtype<K>(K);
+
+    let options = Options {
+        enum_encoding: EnumEncoding::LengthPrefixed,
+        ..Options::default()
+    };
+
+    // Enums nested inside compound values are not the enum that is open.
+    let wrapped = Wrapper {
+        tag: 1,
+        kind: KindV2::Large(7),
+    };
+    let buf = crate::to_bytes_with_options(&wrapped, options).unwrap();
+    assert!(crate::from_bytes_with_options::<Open<Wrapper<KindV1>>>(&buf, options).is_err());
+    let buf = crate::to_bytes_with_options((1u8, KindV2::Large(7)), options).unwrap();
+    assert!(crate::from_bytes_with_options::<Open<(u8, KindV1)>>(&buf, options).is_err());
+
+    // Newtypes share the encoding of the enum they wrap, so it stays open.
+    let buf = crate::to_bytes_with_options(Newtype(KindV2::Large(7)), options).unwrap();
+    assert_eq!(
+        crate::from_bytes_with_options::<Open<Newtype<KindV1>>>(&buf, options).unwrap(),
+        Open::Unknown {
+            index: 1,
+            payload: vec![7],
+        }
+    );
+}
+
+#[test]
+fn test_canonical_encoding() {
+    use std::collections::{BTreeMap, HashMap, HashSet};
+
+    use crate::options::{Canonical, Options};
+    use crate::trace::Kind;
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Document {
+        attributes: HashMap<String, u32>,
+        #[serde(with = "crate::set")]
+        tags: HashSet<String>,
+        score: f64,
+    }
+
+    let options = Options {
+        canonical: Some(Canonical::default()),
+        ..Options::default()
+    };
+    let document = |n: usize| Document {
+        attributes: (0..n as u32).map(|i| (format!("key{i}"), i)).collect(),
+        tags: (0..n).map(|i| "t".repeat(n - i)).collect(),
+        score: -0.0,
+    };
+
+    // Maps are encoded as sequences of key-value pairs.
+    let map = BTreeMap::from([("aa".to_string(), 1u8), ("b".to_string(), 2u8)]);
+    let plain = crate::to_bytes(&map).unwrap();
+    assert_eq!(plain, [2, 2, b'a', b'a', 1, 1, b'b', 2]);
+    assert_eq!(
+        crate::from_bytes::<BTreeMap<String, u8>>(&plain).unwrap(),
+        map
+    );
+
+    // Canonical maps and sets are sorted by their encoded keys, so equal values produce
+    // the same bytes regardless of the iteration order of their collections.
+    let canonical = crate::to_bytes_with_options(&map, options).unwrap();
+    assert_eq!(canonical, [2, 1, b'b', 2, 2, b'a', b'a', 1]);
+    let encodings: HashSet<_> = (0..8)
+        .map(|_| crate::to_bytes_with_options(document(16), options).unwrap())
+        .collect();
+    assert_eq!(encodings.len(), 1);
+    let encoded = encodings.into_iter().next().unwrap();
+    let decoded: Document = crate::from_bytes_with_options(&encoded, options).unwrap();
+    assert_eq!(decoded, document(16));
+
+    // Floats are normalized as configured.
+    let nan = f64::from_bits(f64::NAN.to_bits() | 1);
+    assert_eq!(
+        crate::to_bytes_with_options((-0.0f64, -nan), options).unwrap(),
+        crate::to_bytes((0.0f64, f64::NAN)).unwrap()
+    );
+    let keep_zero = Options {
+        canonical: Some(Canonical {
+            normalize_negative_zero: false,
+            ..Canonical::default()
+        }),
+        ..Options::default()
+    };
+    assert_eq!(
+        crate::to_bytes_with_options(-0.0f64, keep_zero).unwrap(),
+        crate::to_bytes(-0.0f64).unwrap()
+    );
+
+    // Canonical deserializers reject input that is not canonical.
+    assert!(crate::from_bytes_with_options::<BTreeMap<String, u8>>(&plain, options).is_err());
+    let duplicate = [2, 1, b'b', 2, 1, b'b', 3];
+    assert!(crate::from_bytes_with_options::<BTreeMap<String, u8>>(&duplicate, options).is_err());
+    assert_eq!(crate::from_bytes::<u64>(&[0x81, 0x00]).unwrap(), 1);
+    assert!(crate::from_bytes_with_options::<u64>(&[0x81, 0x00], options).is_err());
+    assert!(
+        crate::from_bytes_with_options::<f64>(&crate::to_bytes(-0.0f64).unwrap(), options).is_err()
+    );
+    assert!(
+        crate::from_bytes_with_options::<f64>(&crate::to_bytes(nan).unwrap(), options).is_err()
+    );
+    let tags = |tags: [&str; 2]| {
+        let attributes: Vec<(String, u32)> = vec![];
+        crate::to_bytes((attributes, tags.to_vec(), 1.5f64)).unwrap()
+    };
+    assert!(crate::from_bytes_with_options::<Document>(&tags(["a", "bb"]), options).is_ok());
+    assert!(crate::from_bytes_with_options::<Document>(&tags(["bb", "a"]), options).is_err());
+    assert!(crate::from_bytes::<Document>(&tags(["bb", "a"])).is_ok());
+
+    // Traces of sorted entries have their final indices.
+    let mut ser = crate::ser::Serializer::with_options(Vec::new(), options);
+    ser.enable_trace();
+    ::serde::Serialize::serialize(&map, &mut ser).unwrap();
+    let trace = ser.take_trace().unwrap();
+    let strings: Vec<_> = trace
+        .spans()
+        .iter()
+        .filter(|span| span.kind == Kind::String)
+        .map(|span| span.path.to_string())
+        .collect();
+    assert_eq!(strings, ["[0].0", "[1].0"]);
+    assert_eq!(trace.spans()[1].start, 1);
+}
+
+#[test]
+fn test_canonical_bodies() {
+    use crate::options::{Canonical, EnumEncoding, Options, StructEncoding};
+    use crate::schema::Schema;
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Point {
+        x: u32,
+        y: u32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    enum Shape {
+        Dot(u32),
+    }
+
+    let canonical = Some(Canonical::default());
+    let prefixed = Options {
+        struct_encoding: StructEncoding::LengthPrefixed,
+        enum_encoding: EnumEncoding::LengthPrefixed,
+        ..Options::default()
+    };
+    let numbered = Options {
+        struct_encoding: StructEncoding::Numbered,
+        ..Options::default()
+    };
+    let point = Point { x: 5, y: 6 };
+    let schema = Schema::structure("Point", [("x", Schema::U32), ("y", Schema::U32)]);
+
+    // Canonical readers reject padding after the fields of a body or a payload...
+    let padded = [4, 2, 5, 6, 0];
+    assert_eq!(
+        crate::from_bytes_with_options::<Point>(&padded, prefixed).unwrap(),
+        point
+    );
+    let prefixed = Options {
+        canonical,
+        ..prefixed
+    };
+    assert!(crate::from_bytes_with_options::<Point>(&padded, prefixed).is_err());
+    let mut de = crate::de::Deserializer::with_options(&padded[..], prefixed);
+    assert!(crate::value::Value::decode(&schema, &mut de).is_err());
+    assert!(crate::from_bytes_with_options::<Shape>(&[0, 1, 5], prefixed).is_ok());
+    assert!(crate::from_bytes_with_options::<Shape>(&[0, 2, 5, 0], prefixed).is_err());
+
+    // ...but still skip fields they don't know.
+    assert_eq!(
+        crate::from_bytes_with_options::<Point>(&[4, 3, 5, 6, 7], prefixed).unwrap(),
+        point
+    );
+
+    // Numbered fields must be in increasing order of their numbers, without duplicates.
+    let ordered = crate::to_bytes_with_options(&point, numbered).unwrap();
+    assert_eq!(ordered, [2, 0, 5, 8, 6]);
+    assert_eq!(
+        crate::from_bytes_with_options::<Point>(&[2, 8, 6, 0, 5], numbered).unwrap(),
+        point
+    );
+    let numbered = Options {
+        canonical,
+        ..numbered
+    };
+    for unordered in [[2, 8, 6, 0, 5], [2, 0, 5, 0, 6]] {
+        assert!(crate::from_bytes_with_options::<Point>(&unordered, numbered).is_err());
+        let mut de = crate::de::Deserializer::with_options(&unordered[..], numbered);
+        assert!(crate::value::Value::decode(&schema, &mut de).is_err());
+    }
+
+    // Canonical writers sort fields whose numbers are out of order.
+    let reversed = Options {
+        field_numbers: Some(|_, field| Some(if field == "x" { 2 } else { 1 })),
+        ..numbered
+    };
+    let buf = crate::to_bytes_with_options(&point, reversed).unwrap();
+    assert_eq!(buf, [2, 8, 6, 16, 5]);
+    assert_eq!(
+        crate::from_bytes_with_options::<Point>(&buf, reversed).unwrap(),
+        point
+    );
+}
+
+#[test]
+fn test_hash_value() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashMap;
+    use std::hash::Hasher;
+
+    use crate::hash::Sink;
+    use crate::options::{Canonical, Options};
+
+    // A hasher that records how its input was split.
+    #[derive(Default)]
+    struct Recorder(Vec<Vec<u8>>);
+
+    impl Hasher for Recorder {
+        fn finish(&self) -> u64 {
+            0
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            self.0.push(bytes.to_vec());
+        }
+    }
+
+    let value = (
+        "x".repeat(300),
+        vec![1u64, 1 << 40, 3],
+        -2.5f64,
+        (true, -7i32),
+    );
+    let encoded = crate::to_bytes(&value).unwrap();
+
+    // The hasher sees exactly the encoded bytes, in full blocks.
+    let mut recorder = Recorder::default();
+    crate::hash_value(&value, &mut recorder).unwrap();
+    assert_eq!(recorder.0.concat(), encoded);
+    assert_eq!(recorder.0.len(), encoded.len().div_ceil(256));
+    assert!(recorder
+        .0
+        .iter()
+        .rev()
+        .skip(1)
+        .all(|block| block.len() == 256));
+
+    let hash = |v: &dyn Fn(&mut DefaultHasher)| {
+        let mut hasher = DefaultHasher::new();
+        v(&mut hasher);
+        hasher.finish()
+    };
+    assert_eq!(
+        hash(&|h| crate::hash_value(&value, h).unwrap()),
+        hash(&|h| crate::hash_value(value.clone(), h).unwrap())
+    );
+
+    // Sinks feed any incremental digest.
+    let mut digest = Vec::new();
+    let mut sink = Sink::new(|bytes: &[u8]| digest.extend_from_slice(bytes));
+    crate::to_writer(&value, &mut sink).unwrap();
+    sink.finish();
+    assert_eq!(digest, encoded);
+
+    // Canonical options make hashes independent of iteration order.
+    let options = Options {
+        canonical: Some(Canonical::default()),
+        ..Options::default()
+    };
+    let hashes: std::collections::HashSet<_> = (0..8)
+        .map(|_| {
+            let map: HashMap<u32, u32> = (0..32).map(|i| (i, i * i)).collect();
+            hash(&|h| crate::hash_value_with_options(&map, h, options).unwrap())
+        })
+        .collect();
+    assert_eq!(hashes.len(), 1);
+}
+
+#[test]
+fn test_key_order() {
+    use std::collections::BTreeMap;
+
+    #[derive(
+        Debug, Clone, PartialEq, Eq, PartialOrd, Ord, ::serde::Deserialize, ::serde::Serialize,
+    )]
+    enum Shape {
+        Empty,
+        Line(i16),
+        Rect { w: u32, h: u32 },
+    }
+
+    // Sorting the keys sorts the values, and every key decodes back to its value.
+    fn assert_key_order<T>(mut values: Vec<T>)
+    where
+        T: ::std::fmt::Debug + Ord + ::serde::Serialize + ::serde::de::DeserializeOwned,
+    {
+        let mut keys: Vec<_> = values
+            .iter()
+            .map(|v| crate::key::to_bytes(v).unwrap())
+            .collect();
+        keys.sort();
+        values.sort();
+        let decoded: Vec<T> = keys
+            .iter()
+            .map(|k| crate::key::from_bytes(k).unwrap())
+            .collect();
+        assert_eq!(decoded, values);
+    }
+
+    assert_key_order(vec![i64::MIN, -300, -1, 0, 1, 255, 256, i64::MAX]);
+    assert_key_order(vec![i8::MIN, -1, 0, 1, i8::MAX]);
+    assert_key_order(vec![0u64, 1, 255, 256, 1 << 40, u64::MAX]);
+    assert_key_order(vec![
+        String::new(),
+        "\0".into(),
+        "\0\0".into(),
+        "\0a".into(),
+        "a".into(),
+        "a\0".into(),
+        "ab".into(),
+        "b".into(),
+    ]);
+    assert_key_order(vec![vec![], vec![0u8], vec![0, 0], vec![0, 1], vec![1]]);
+    assert_key_order(vec![
+        (String::from("a"), 2u8),
+        ("a".into(), 1),
+        ("".into(), 9),
+        ("a\0".into(), 0),
+    ]);
+    assert_key_order(vec![
+        vec![],
+        vec![String::new()],
+        vec!["a".into()],
+        vec!["a".into(), String::new()],
+        vec!["b".into()],
+    ]);
+    assert_key_order(vec![None, Some(-1i32), Some(0), Some(1)]);
+    assert_key_order(vec![
+        Shape::Rect { w: 1, h: 2 },
+        Shape::Line(-5),
+        Shape::Empty,
+        Shape::Rect { w: 1, h: 1 },
+        Shape::Line(7),
+    ]);
+    assert_key_order(vec![
+        BTreeMap::new(),
+        BTreeMap::from([(1u8, 'b')]),
+        BTreeMap::from([(1u8, 'a'), (2, 'a')]),
+        BTreeMap::from([(0u8, 'z')]),
+    ]);
+
+    // Floats sort like `total_cmp`.
+    let mut floats = vec![
+        f64::NEG_INFINITY,
+        -2.5,
+        -0.0,
+        0.0,
+        1e-300,
+        3.0,
+        f64::INFINITY,
+    ];
+    let mut keys: Vec<_> = floats
+        .iter()
+        .map(|f| crate::key::to_bytes(f).unwrap())
+        .collect();
+    keys.sort();
+    floats.sort_by(f64::total_cmp);
+    for (k, f) in keys.iter().zip(&floats) {
+        assert_eq!(
+            crate::key::from_bytes::<f64>(k).unwrap().to_bits(),
+            f.to_bits()
+        );
+    }
+    let key = crate::key::to_bytes(-1.5f32).unwrap();
+    assert_eq!(crate::key::from_bytes::<f32>(&key).unwrap(), -1.5);
+
+    // Strings without zero bytes are borrowed from the key.
+    let key = crate::key::to_bytes(("abc", "a\0c")).unwrap();
+    let (plain, escaped): (&str, String) = crate::key::from_bytes(&key).unwrap();
+    assert_eq!((plain, escaped.as_str()), ("abc", "a\0c"));
+    assert!(crate::key::from_bytes::<String>(&key[..3]).is_err());
+
+    // Map entries are sorted, so hash maps encode like the equal B-tree maps.
+    let ordered: ::std::collections::BTreeMap<_, _> =
+        (0..64u32).map(|i| (format!("k{i}"), i)).collect();
+    let unordered: ::std::collections::HashMap<_, _> = ordered.clone().into_iter().collect();
+    let key = crate::key::to_bytes(&unordered).unwrap();
+    assert_eq!(key, crate::key::to_bytes(&ordered).unwrap());
+    assert_eq!(
+        crate::key::from_bytes::<::std::collections::HashMap<String, u32>>(&key).unwrap(),
+        unordered
+    );
+}
+
+#[test]
+fn test_log() {
+    use std::io::Write as _;
+
+    use crate::log::{LogReader, LogWriter};
+    use crate::options::Options;
+
+    assert_eq!(crate::checksum::crc32c(b"123456789"), 0xe306_9283);
+
+    let path = std::env::temp_dir().join(format!("sibor-test-log-{}", std::process::id()));
+    let record = |i: u64| (i, format!("record {i}"));
+    let open = || LogReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+
+    // Index blocks are written every 3 records, and after the last one.
+    let mut writer = LogWriter::create_with_options(&path, Options::default(), 3).unwrap();
+    for i in 0..10 {
+        assert_eq!(writer.append(record(i)).unwrap(), i);
+    }
+    writer.finish().unwrap();
+    let mut reader = open();
+    assert_eq!((reader.len(), reader.torn()), (10, 25));
+    for i in (0..10).rev() {
+        assert_eq!(reader.get(i).unwrap(), Some(record(i)));
+    }
+    assert_eq!(reader.get::<(u64, String)>(10).unwrap(), None);
+    let all: Vec<(u64, String)> = reader.iter().collect::<crate::error::Result<_>>().unwrap();
+    assert_eq!(all, (0..10).map(record).collect::<Vec<_>>());
+
+    // Appending removes the footer, and a crash leaves a torn record behind.
+    let mut writer = LogWriter::open(&path).unwrap();
+    assert_eq!((writer.len(), writer.truncated()), (10, 25));
+    writer.append(record(10)).unwrap();
+    writer.append(record(11)).unwrap();
+    drop(writer);
+    let torn = crate::to_bytes(record(12)).unwrap();
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(&[0, torn.len() as u8, 0, 0, 0, 1, 2, 3, 4])
+        .unwrap();
+    file.write_all(&torn[..2]).unwrap();
+    drop(file);
+
+    let mut reader = open();
+    assert_eq!((reader.len(), reader.torn()), (12, 11));
+    assert_eq!(reader.get(4).unwrap(), Some(record(4)));
+    assert_eq!(reader.get(11).unwrap(), Some(record(11)));
+    assert_eq!(reader.iter::<(u64, String)>().count(), 12);
+
+    // Reopening truncates the torn record, and keeps indexing where it left off.
+    let mut writer = LogWriter::open(&path).unwrap();
+    assert_eq!((writer.len(), writer.truncated()), (12, 11));
+    writer.append(record(12)).unwrap();
+    writer.finish().unwrap();
+    let mut reader = open();
+    assert_eq!(reader.len(), 13);
+    for i in 0..13 {
+        assert_eq!(reader.get(i).unwrap(), Some(record(i)));
+    }
+
+    // Corrupted records fail their checksum.
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[20 + 9 + 1] ^= 0x40;
+    std::fs::write(&path, &bytes).unwrap();
+    let mut reader = open();
+    assert!(matches!(
+        reader.get::<(u64, String)>(0),
+        Err(crate::Error::Checksum { .. })
+    ));
+    assert_eq!(reader.get(1).unwrap(), Some(record(1)));
+
+    // Corrupted entries that aren't at the end of an unfinished log are not truncated.
+    let mut writer = LogWriter::create_with_options(&path, Options::default(), 3).unwrap();
+    for i in 0..5 {
+        writer.append(record(i)).unwrap();
+    }
+    drop(writer);
+    let mut bytes = std::fs::read(&path).unwrap();
+    bytes[20 + 9 + 1] ^= 0x40;
+    std::fs::write(&path, &bytes).unwrap();
+    assert!(matches!(
+        LogWriter::open(&path),
+        Err(crate::Error::Checksum { .. })
+    ));
+    assert_eq!(std::fs::read(&path).unwrap(), bytes);
+    assert!(LogReader::new(std::fs::File::open(&path).unwrap()).is_err());
+
+    // Every time a log is finished, its last records get an index block of their own.
+    let mut writer = LogWriter::create_with_options(&path, Options::default(), 3).unwrap();
+    writer.append(record(0)).unwrap();
+    writer.finish().unwrap();
+    for i in 1..3 {
+        let mut writer = LogWriter::open(&path).unwrap();
+        writer.append(record(i)).unwrap();
+        writer.finish().unwrap();
+    }
+    let mut reader = open();
+    assert_eq!(reader.len(), 3);
+    assert_eq!(reader.get(2).unwrap(), Some(record(2)));
+
+    // Forged footers and index blocks are rejected instead of trusted.
+    let mut writer = LogWriter::create_with_options(&path, Options::default(), 3).unwrap();
+    for i in 0..6 {
+        writer.append(record(i)).unwrap();
+    }
+    writer.finish().unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    let (entries, footer) = bytes.split_at(bytes.len() - 25);
+    let last_index = u64::from_le_bytes(footer[9..17].try_into().unwrap());
+    let entry = |tag: u8, body: &[u8]| {
+        let mut entry = vec![tag];
+        entry.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        let mut crc = crate::checksum::Crc32c::new();
+        crc.update(&entry);
+        crc.update(body);
+        entry.extend_from_slice(&crc.finish().to_le_bytes());
+        entry.extend_from_slice(body);
+        entry
+    };
+    let forge = |entries: &[u8], last_index: u64, records: u64| {
+        let mut forged = entries.to_vec();
+        let body = [last_index.to_le_bytes(), records.to_le_bytes()].concat();
+        forged.extend_from_slice(&entry(2, &body));
+        LogReader::new(::std::io::Cursor::new(forged))
+    };
+    assert_eq!(forge(entries, last_index, 6).unwrap().len(), 6);
+    let mut reader = forge(entries, u64::MAX, 6).unwrap();
+    assert!(reader.get::<(u64, String)>(0).is_err());
+    assert!(forge(entries, 20, 6).is_err());
+    assert!(forge(entries, last_index, 1).is_err());
+    let mut cycle = entries[..last_index as usize].to_vec();
+    let mut body = entries[last_index as usize + 9..].to_vec();
+    body[8..16].copy_from_slice(&last_index.to_le_bytes());
+    cycle.extend_from_slice(&entry(1, &body));
+    assert!(forge(&cycle, last_index, 6).is_err());
+
+    // Options that aren't stored in the header are given again when reopening.
+    #[derive(Debug, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Point {
+        x: u32,
+        y: u32,
+    }
+    let numbered = Options {
+        struct_encoding: crate::options::StructEncoding::Numbered,
+        field_numbers: Some(|_, field| Some(if field == "x" { 5 } else { 1 })),
+        ..Options::default()
+    };
+    let point = |i: u32| Point { x: i, y: i + 1 };
+    let mut writer = LogWriter::create_with_options(&path, numbered, 3).unwrap();
+    writer.append(point(0)).unwrap();
+    writer.finish().unwrap();
+    assert!(LogWriter::open_with_options(&path, Options::default()).is_err());
+    let mut writer = LogWriter::open_with_options(&path, numbered).unwrap();
+    writer.append(point(1)).unwrap();
+    writer.finish().unwrap();
+    let file = || std::fs::File::open(&path).unwrap();
+    assert!(LogReader::with_options(file(), Options::default()).is_err());
+    let mut reader = LogReader::with_options(file(), numbered).unwrap();
+    assert_eq!(reader.get(0).unwrap(), Some(point(0)));
+    assert_eq!(reader.get(1).unwrap(), Some(point(1)));
+    assert!(open().get::<Point>(1).is_err());
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_sync_markers() {
+    use crate::options::Options;
+    use crate::sync::{Loss, SyncReader, SyncWriter};
+
+    let record = |i: u64| (i, "x".repeat(i as usize % 7));
+    let mut writer = SyncWriter::with_options(Vec::new(), Options::default(), 16);
+    let mut offsets = Vec::new();
+    for i in 0..100 {
+        offsets.push(writer.get_ref().len());
+        writer.write(record(i)).unwrap();
+    }
+    let encoded = writer.into_inner();
+
+    let read_all = |bytes: &[u8]| {
+        let mut reader = SyncReader::new(bytes);
+        let mut records = Vec::new();
+        while let Some(r) = reader.read::<(u64, String)>().unwrap() {
+            records.push(r.0);
+        }
+        (records, reader.loss())
+    };
+
+    // An intact stream loses nothing.
+    let (records, loss) = read_all(&encoded);
+    assert_eq!(records, (0..100).collect::<Vec<_>>());
+    assert_eq!(loss, Loss::default());
+
+    // A flipped bit in record 40 loses the records up to the sync point before record 48.
+    let mut corrupted = encoded.clone();
+    corrupted[offsets[40] + 10] ^= 0x08;
+    let (records, loss) = read_all(&corrupted);
+    assert_eq!(records, (0..40).chain(48..100).collect::<Vec<_>>());
+    assert_eq!(loss.bytes, (offsets[48] - offsets[40]) as u64);
+    assert_eq!(
+        loss,
+        Loss {
+            resyncs: 1,
+            bytes: loss.bytes,
+            records: 8
+        }
+    );
+
+    // Inserted garbage is skipped without losing records, and a torn tail loses one record.
+    let mut garbage = encoded[..offsets[16]].to_vec();
+    garbage.extend_from_slice(&[0xff, 0, 1, 2, 0xff]);
+    garbage.extend_from_slice(&encoded[offsets[16]..encoded.len() - 3]);
+    let (records, loss) = read_all(&garbage);
+    assert_eq!(records, (0..99).collect::<Vec<_>>());
+    assert_eq!(
+        loss,
+        Loss {
+            resyncs: 2,
+            bytes: 5 + 9,
+            records: 1
+        }
+    );
+}
+
+#[test]
+fn test_checksummed_frames() {
+    use crate::frame::{FrameReader, FrameWriter};
+    use crate::options::Options;
+
+    let values = vec![
+        (1u32, "one".to_string()),
+        (2, "two".into()),
+        (3, "three".into()),
+    ];
+    let mut writer = FrameWriter::new(Vec::new());
+    for v in &values {
+        writer.write(v).unwrap();
+    }
+    let encoded = writer.into_inner();
+
+    let mut reader = FrameReader::new(&encoded[..]);
+    for v in &values {
+        assert_eq!(reader.read::<(u32, String)>().unwrap().as_ref(), Some(v));
+    }
+    assert_eq!(reader.read::<(u32, String)>().unwrap(), None);
+
+    // A flipped bit that would still decode is caught by the checksum.
+    let mut corrupted = encoded.clone();
+    corrupted[1] ^= 0x04;
+    assert_eq!(
+        crate::from_bytes::<(u32, String)>(&corrupted[1..6])
+            .unwrap()
+            .0,
+        5
+    );
+    assert!(matches!(
+        crate::frame::from_reader::<(u32, String), _>(&corrupted[..]),
+        Err(crate::Error::Checksum { .. })
+    ));
+
+    // Frames longer than the limit are rejected before they are read.
+    let mut reader = FrameReader::with_options(&encoded[..], Options::default(), 4);
+    assert!(reader.read_raw().is_err());
+
+    let mut buf = Vec::new();
+    crate::frame::to_writer(&values, &mut buf).unwrap();
+    assert_eq!(
+        crate::frame::from_reader::<Vec<(u32, String)>, _>(&buf[..]).unwrap(),
+        values
+    );
+}
+
+#[test]
+fn test_frame_decoder() {
+    use crate::frame::{Decoded, FrameDecoder, FrameWriter};
+
+    let values: Vec<(u32, String)> = (0..5).map(|i| (i, "y".repeat(i as usize * 60))).collect();
+    let mut writer = FrameWriter::new(Vec::new());
+    for v in &values {
+        writer.write(v).unwrap();
+    }
+    let encoded = writer.into_inner();
+
+    // Bytes arrive one at a time, and the decoder knows how many more each frame needs.
+    let mut decoder = FrameDecoder::new();
+    let mut decoded = Vec::new();
+    let mut needed = Vec::new();
+    for b in &encoded {
+        decoder.feed(std::slice::from_ref(b));
+        loop {
+            match decoder.decode::<(u32, String)>().unwrap() {
+                Decoded::Frame(v) => decoded.push(v),
+                Decoded::NeedMore(n) => {
+                    needed.push(n);
+                    break;
+                }
+            }
+        }
+    }
+    assert_eq!(decoded, values);
+    assert_eq!(decoder.buffered(), 0);
+    // The last frame has a two-byte header, and needs one more byte after each of them.
+    let last = 2 + crate::encoded_size(&values[4]).unwrap() + 4;
+    assert_eq!(needed[needed.len() - last..][..2], [1, last - 2]);
+
+    // Chunks may hold several frames, and a corrupted frame doesn't stop the next ones.
+    let mut corrupted = encoded.clone();
+    corrupted[2] ^= 0x01;
+    let mut decoder = FrameDecoder::new();
+    decoder.feed(&corrupted);
+    assert!(matches!(
+        decoder.decode::<(u32, String)>(),
+        Err(crate::Error::Checksum { .. })
+    ));
+    assert_eq!(
+        decoder.decode::<(u32, String)>().unwrap(),
+        Decoded::Frame(values[1].clone())
+    );
+}
+
+#[cfg(feature = "futures-io")]
+#[test]
+fn test_async_frames_futures() {
+    use crate::async_io::futures::{AsyncFrameReader, AsyncFrameWriter};
+
+    let values: Vec<(u32, String)> = (0..4).map(|i| (i, "z".repeat(i as usize * 50))).collect();
+    ::futures::executor::block_on(async {
+        let mut writer = AsyncFrameWriter::new(::futures::io::Cursor::new(Vec::new()));
+        for v in &values {
+            writer.write(v).await.unwrap();
+        }
+        let encoded = writer.into_inner().into_inner();
+
+        // The frames are the same as the blocking ones.
+        let mut blocking = crate::frame::FrameWriter::new(Vec::new());
+        for v in &values {
+            blocking.write(v).unwrap();
+        }
+        assert_eq!(encoded, blocking.into_inner());
+
+        let mut reader = AsyncFrameReader::new(&encoded[..]);
+        for v in &values {
+            assert_eq!(
+                reader.read::<(u32, String)>().await.unwrap().as_ref(),
+                Some(v)
+            );
+        }
+        assert_eq!(reader.read::<(u32, String)>().await.unwrap(), None);
+
+        let mut reader = AsyncFrameReader::with_options(&encoded[..], Default::default(), 100);
+        reader.read_raw().await.unwrap();
+        reader.read_raw().await.unwrap();
+        assert!(reader.read_raw().await.is_err());
+
+        // Payloads arrive in bounded chunks, and a forged length without a payload fails
+        // without allocating it.
+        let large = vec![7u8; 200_000];
+        let mut blocking = crate::frame::FrameWriter::new(Vec::new());
+        blocking.write_raw(&large).unwrap();
+        let encoded = blocking.into_inner();
+        let mut reader = AsyncFrameReader::new(&encoded[..]);
+        assert_eq!(reader.read_raw().await.unwrap(), Some(large));
+        let forged = crate::to_bytes(60u64 << 20).unwrap();
+        let mut reader = AsyncFrameReader::new(&forged[..]);
+        assert!(reader.read_raw().await.is_err());
+    });
+}
+
+#[cfg(feature = "tokio")]
+#[::tokio::test]
+async fn test_async_frames_tokio() {
+    use crate::async_io::tokio::{from_async_reader, to_async_writer, AsyncFrameReader};
+
+    let (client, server) = ::tokio::io::duplex(64);
+    let (mut read, mut write) = ::tokio::io::split(server);
+    let echo = ::tokio::spawn(async move {
+        let mut reader = AsyncFrameReader::new(&mut read);
+        while let Some(v) = reader.read::<Vec<String>>().await.unwrap() {
+            to_async_writer(v.len(), &mut write).await.unwrap();
+        }
+    });
+
+    let (mut read, mut write) = ::tokio::io::split(client);
+    for n in [0, 3, 40] {
+        let v = vec!["abc".to_string(); n];
+        to_async_writer(&v, &mut write).await.unwrap();
+        assert_eq!(from_async_reader::<usize, _>(&mut read).await.unwrap(), n);
+    }
+    // The server sees the end of the stream once both halves are dropped.
+    drop((read, write));
+    echo.await.unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_rpc_channel() {
+    use std::os::unix::net::UnixStream;
+
+    use crate::rpc::{Channel, Header, Kind};
+
+    const ADD: u64 = 1;
+    const DIVIDE: u64 = 2;
+
+    let (client, server) = UnixStream::pair().unwrap();
+    let handle = std::thread::spawn(move || {
+        let mut channel = Channel::new(server.try_clone().unwrap(), server);
+        let mut seen = Vec::new();
+        while let Some(message) = channel.receive().unwrap() {
+            let request = message.header;
+            seen.push(request);
+            match request.method {
+                ADD => {
+                    let (a, b): (i64, i64) = message.decode().unwrap();
+                    channel.respond(&request, a + b).unwrap();
+                }
+                DIVIDE => match message.decode::<(i64, i64)>().unwrap() {
+                    (_, 0) => channel.respond_error(&request, "division by zero").unwrap(),
+                    (a, b) => channel.respond(&request, a / b).unwrap(),
+                },
+                _ => channel.cancel(&request).unwrap(),
+            }
+            channel.flush().unwrap();
+        }
+        seen
+    });
+
+    let mut channel = Channel::new(client.try_clone().unwrap(), client);
+    assert_eq!(
+        channel.call::<_, i64, String>(ADD, (2i64, 3i64)).unwrap(),
+        Ok(5)
+    );
+    assert_eq!(
+        channel
+            .call::<_, i64, String>(DIVIDE, (7i64, 2i64))
+            .unwrap(),
+        Ok(3)
+    );
+    assert_eq!(
+        channel
+            .call::<_, i64, String>(DIVIDE, (7i64, 0i64))
+            .unwrap(),
+        Err("division by zero".to_string())
+    );
+    assert!(channel.call::<_, (), String>(99, ()).is_err());
+    drop(channel);
+
+    let seen = handle.join().unwrap();
+    assert_eq!(seen.len(), 4);
+    assert_eq!(
+        seen[3],
+        Header {
+            kind: Kind::Request,
+            id: 3,
+            method: 99
+        }
+    );
+}
+
+#[test]
+fn test_streaming_sequences() {
+    use ::serde::{Deserialize as _, Serialize as _};
+
+    use crate::de::{Deserializer, SeqReader};
+    use crate::ser::{SeqWriter, Serializer};
+
+    let element = |i: u32| (i, format!("row {i}"));
+
+    // The elements are written one at a time between other fields.
+    let mut ser = Serializer::new(Vec::new());
+    "export".serialize(&mut ser).unwrap();
+    let mut rows = SeqWriter::new(&mut ser, 1000).unwrap();
+    for i in 0..1000 {
+        rows.write(&element(i)).unwrap();
+    }
+    assert_eq!(rows.remaining(), 0);
+    rows.finish().unwrap();
+    true.serialize(&mut ser).unwrap();
+    let encoded = ser.into_inner();
+
+    let expected = (
+        "export".to_string(),
+        (0..1000).map(element).collect::<Vec<_>>(),
+        true,
+    );
+    assert_eq!(crate::to_bytes(&expected).unwrap(), encoded);
+
+    // The elements are read one at a time from a reader.
+    let mut de = Deserializer::new(&encoded[..]);
+    assert_eq!(String::deserialize(&mut de).unwrap(), "export");
+    let mut rows = SeqReader::<_, (u32, String)>::new(&mut de).unwrap();
+    assert_eq!(rows.remaining(), 1000);
+    for (i, row) in rows.by_ref().enumerate() {
+        assert_eq!(row.unwrap(), element(i as u32));
+    }
+    assert!(rows.next().is_none());
+    assert!(bool::deserialize(&mut de).unwrap());
+
+    // The count is checked.
+    let mut ser = Serializer::new(Vec::new());
+    let mut rows = SeqWriter::new(&mut ser, 1).unwrap();
+    rows.write(&element(0)).unwrap();
+    assert!(rows.write(&element(1)).is_err());
+    let rows = SeqWriter::new(&mut ser, 2).unwrap();
+    assert!(rows.finish().is_err());
+}
+
+#[test]
+fn test_streaming_bytes() {
+    use ::serde::{Deserialize as _, Serialize as _};
+
+    use crate::de::Deserializer;
+    use crate::ser::Serializer;
+
+    let attachment: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+
+    // The contents are copied from a reader after the length header.
+    let mut ser = Serializer::new(Vec::new());
+    "report.bin".serialize(&mut ser).unwrap();
+    ser.write_bytes_from(attachment.len() as u64, &attachment[..])
+        .unwrap();
+    7u32.serialize(&mut ser).unwrap();
+    let encoded = ser.into_inner();
+    let expected = ("report.bin", ::serde_bytes::Bytes::new(&attachment), 7u32);
+    assert_eq!(crate::to_bytes(expected).unwrap(), encoded);
+
+    // The contents are read through a bounded reader, which ends with the byte string.
+    let mut de = Deserializer::new(&encoded[..]);
+    assert_eq!(String::deserialize(&mut de).unwrap(), "report.bin");
+    let mut contents = de.read_bytes_reader(0, usize::MAX).unwrap();
+    assert_eq!(contents.remaining(), attachment.len());
+    let mut copied = Vec::new();
+    std::io::copy(&mut contents, &mut copied).unwrap();
+    assert_eq!(copied, attachment);
+    assert_eq!(u32::deserialize(&mut de).unwrap(), 7);
+
+    // Unread contents can be skipped, and lengths are checked.
+    let mut de = Deserializer::new(&encoded[..]);
+    String::deserialize(&mut de).unwrap();
+    let mut contents = de.read_bytes_reader(0, usize::MAX).unwrap();
+    std::io::Read::read_exact(&mut contents, &mut [0u8; 10]).unwrap();
+    contents.finish().unwrap();
+    assert_eq!(u32::deserialize(&mut de).unwrap(), 7);
+    let mut de = Deserializer::new(&encoded[..]);
+    String::deserialize(&mut de).unwrap();
+    assert!(de.read_bytes_reader(0, 1024).is_err());
+
+    let mut ser = Serializer::new(Vec::new());
+    assert!(ser.write_bytes_from(10, &[1u8, 2, 3][..]).is_err());
+}
+
+#[test]
+fn test_gather_writer() {
+    use crate::gather::GatherWriter;
+    use crate::options::Options;
+
+    let big = vec![0xabu8; 10_000];
+    let small = b"tiny".to_vec();
+
+    let mut writer = GatherWriter::new();
+    writer.write("upload").unwrap();
+    writer.write_bytes(&big).unwrap();
+    writer.write_bytes(&small).unwrap();
+    writer.write(7u32).unwrap();
+
+    let expected = crate::to_bytes((
+        "upload",
+        ::serde_bytes::Bytes::new(&big),
+        ::serde_bytes::Bytes::new(&small),
+        7u32,
+    ))
+    .unwrap();
+    assert_eq!(writer.len(), expected.len());
+    assert_eq!(writer.to_vec(), expected);
+
+    // The large byte string is referenced, and the small one is copied inline.
+    let slices = writer.io_slices();
+    assert_eq!(slices.len(), 3);
+    assert_eq!(slices[1].as_ptr(), big.as_ptr());
+    assert_eq!(slices[2].len(), 1 + small.len() + 1);
+
+    let mut sent = Vec::new();
+    writer.write_to(&mut sent).unwrap();
+    assert_eq!(sent, expected);
+
+    // With a lower threshold, both byte strings are referenced.
+    let mut writer = GatherWriter::with_options(Options::default(), 4);
+    writer.write_bytes(&big).unwrap();
+    writer.write_bytes(&small).unwrap();
+    assert_eq!(writer.io_slices().len(), 4);
+    assert_eq!(
+        crate::from_bytes::<(::serde_bytes::ByteBuf, ::serde_bytes::ByteBuf)>(&writer.to_vec())
+            .unwrap(),
+        (
+            ::serde_bytes::ByteBuf::from(big.clone()),
+            ::serde_bytes::ByteBuf::from(small.clone())
+        )
+    );
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn test_shared_bytes() {
+    use crate::shared::{from_bytes_shared, to_bytes_mut};
+
+    #[derive(Debug, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Packet {
+        id: u32,
+        #[serde(with = "crate::shared")]
+        payload: ::bytes::Bytes,
+    }
+
+    let packet = Packet {
+        id: 7,
+        payload: ::bytes::Bytes::from(vec![0x5au8; 1000]),
+    };
+    let mut buf = ::bytes::BytesMut::from(&b"prefix"[..]);
+    to_bytes_mut(&packet, &mut buf).unwrap();
+    assert_eq!(&buf[6..], &crate::to_bytes(&packet).unwrap()[..]);
+
+    let input = buf.freeze().slice(6..);
+    let decoded: Packet = from_bytes_shared(input.clone()).unwrap();
+    assert_eq!(decoded, packet);
+    // The payload is a slice of the input, not a copy.
+    let range = input.as_ptr_range();
+    assert!(range.contains(&decoded.payload.as_ptr()));
+
+    // Outside of `from_bytes_shared`, the payload is copied.
+    let copied: Packet = crate::from_bytes(&input).unwrap();
+    assert_eq!(copied, packet);
+    assert!(!range.contains(&copied.payload.as_ptr()));
+}
+
+#[test]
+fn test_forged_lengths_from_reader() {
+    // A length of about 70 TB, followed by a few bytes.
+    let forged = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x0f, b'a', b'b'];
+    assert!(matches!(
+        crate::from_reader::<String, _>(&forged[..]),
+        Err(crate::Error::Io(e)) if e.kind() == ::std::io::ErrorKind::UnexpectedEof
+    ));
+    assert!(crate::from_reader::<::serde_bytes::ByteBuf, _>(&forged[..]).is_err());
+
+    // Lengths that are longer than the buffer that is allocated up front still work.
+    let long = vec![7u8; 200_000];
+    let encoded = crate::to_bytes(::serde_bytes::Bytes::new(&long)).unwrap();
+    let decoded: ::serde_bytes::ByteBuf = crate::from_reader(&encoded[..]).unwrap();
+    assert_eq!(decoded.into_vec(), long);
+}
+
+#[test]
+fn test_skipped_fields() {
+    use crate::options::{Options, StructEncoding};
+
+    fn is_zero(v: &u32) -> bool {
+        *v == 0
+    }
+
+    #[derive(Debug, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct S {
+        a: u32,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        b: u32,
+        #[serde(default, skip_serializing_if = "is_zero")]
+        c: u32,
+    }
+
+    let prefixed = Options {
+        struct_encoding: StructEncoding::LengthPrefixed,
+        ..Options::default()
+    };
+    let numbered = Options {
+        struct_encoding: StructEncoding::Numbered,
+        ..Options::default()
+    };
+
+    // Numbered fields can be skipped anywhere.
+    let middle = S { a: 1, b: 0, c: 7 };
+    let buf = crate::to_bytes_with_options(&middle, numbered).unwrap();
+    assert_eq!(buf, [2, 0, 1, 16, 7]);
+    assert_eq!(
+        crate::from_bytes_with_options::<S>(&buf, numbered).unwrap(),
+        middle
+    );
+
+    // Length-prefixed bodies count the fields that were written, so trailing fields can
+    // be skipped, but fields after a skipped one would be read in its place.
+    let trailing = S { a: 1, b: 2, c: 0 };
+    let buf = crate::to_bytes_with_options(&trailing, prefixed).unwrap();
+    assert_eq!(buf, [3, 2, 1, 2]);
+    assert_eq!(
+        crate::from_bytes_with_options::<S>(&buf, prefixed).unwrap(),
+        trailing
+    );
+    assert!(crate::to_bytes_with_options(&middle, prefixed).is_err());
+}
+
+#[test]
+fn test_length_prefixed_zero_sized_fields() {
+    use crate::options::{Options, StructEncoding};
+    use crate::schema::Schema;
+    use crate::value::Value;
+    use crate::view::View;
+
+    #[derive(Debug, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Marker;
+
+    #[derive(Debug, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct A {
+        x: u8,
+        m: (),
+        marker: Marker,
+        p: ::std::marker::PhantomData<u32>,
+    }
+
+    let options = Options {
+        struct_encoding: StructEncoding::LengthPrefixed,
+        ..Options::default()
+    };
+    let a = A {
+        x: 1,
+        m: (),
+        marker: Marker,
+        p: ::std::marker::PhantomData,
+    };
+    let buf = crate::to_bytes_with_options((&a, 42u8), options).unwrap();
+    assert_eq!(buf, [2, 4, 1, 42]);
+    assert_eq!(
+        crate::from_bytes_with_options::<(A, u8)>(&buf, options).unwrap(),
+        (a, 42)
+    );
+
+    // Schema-driven readers find the trailing fields too.
+    let schema = Schema::Tuple(vec![
+        Schema::structure(
+            "A",
+            [
+                ("x", Schema::U8),
+                ("m", Schema::Unit),
+                ("marker", Schema::Unit),
+                ("p", Schema::Unit),
+            ],
+        ),
+        Schema::U8,
+    ]);
+    let mut de = crate::de::Deserializer::from_slice_with_options(&buf, options);
+    let value = Value::decode(&schema, &mut de).unwrap();
+    assert_eq!(crate::to_bytes_with_options(&value, options).unwrap(), buf);
+    let view = View::with_options(&schema, &buf, options);
+    assert_eq!(view.seek("0.p").unwrap().value().unwrap(), Value::Unit);
+    assert_eq!(view.seek("1").unwrap().decode::<u8>().unwrap(), 42);
+    crate::trace::trace_bytes_with_options(&schema, &buf, options).unwrap();
+}
+
+#[test]
+fn test_numbered_field_collisions() {
+    use crate::options::{Options, StructEncoding};
+    use crate::schema::{check_compatibility_with_options, Schema};
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    struct Clash {
+        a: u8,
+        b: u8,
+    }
+
+    #[derive(Debug, Clone, PartialEq, ::serde::Deserialize, ::serde::Serialize)]
+    enum Event {
+        Moved { a: u8, b: u8 },
+    }
+
+    // `b` is numbered 0, like `a` is by its index.
+    fn numbers(container: &str, field: &str) -> Option<u64> {
+        match (container, field) {
+            ("Clash" | "Event::Moved", "b") => Some(0),
+            _ => None,
+        }
+    }
+
+    let plain = Options {
+        struct_encoding: StructEncoding::Numbered,
+        ..Options::default()
+    };
+    let clashing = Options {
+        field_numbers: Some(numbers),
+        ..plain
+    };
+    let clash = Clash { a: 1, b: 2 };
+    let event = Event::Moved { a: 1, b: 2 };
+    assert!(crate::to_bytes_with_options(&clash, clashing).is_err());
+    assert!(crate::to_bytes_with_options(&event, clashing).is_err());
+
+    let buf = crate::to_bytes_with_options(&clash, plain).unwrap();
+    assert!(crate::from_bytes_with_options::<Clash>(&buf, clashing).is_err());
+    let buf = crate::to_bytes_with_options(&event, plain).unwrap();
+    assert!(crate::from_bytes_with_options::<Event>(&buf, clashing).is_err());
+
+    let schema = Schema::structure("Clash", [("a", Schema::U8), ("b", Schema::U8)]);
+    let buf = crate::to_bytes_with_options(&clash, plain).unwrap();
+    let mut de = crate::de::Deserializer::from_slice_with_options(&buf, clashing);
+    assert!(crate::value::Value::decode(&schema, &mut de).is_err());
+    let extended = Schema::structure(
+        "Clash",
+        [("a", Schema::U8), ("b", Schema::U8), ("c", Schema::U8)],
+    );
+    let report = check_compatibility_with_options(&schema, &extended, &clashing);
+    assert!(!report.is_compatible(), "{report}");
+}
@@ -0,0 +1,435 @@
+use crate::de::Deserializer;
+use crate::error::{Error, Result};
+use crate::options::{EnumEncoding, Options, StructEncoding, WIRE_LEN, WIRE_VARINT};
+use crate::path::{Path, Segment};
+use crate::schema::{Schema, StructSchema};
+use crate::ser::Serializer;
+
+/// The kind of primitive that produced a span of bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Kind {
+    /// A boolean byte.
+    Bool,
+    /// An unsigned varint.
+    Unsigned,
+    /// A signed zigzag varint.
+    Signed,
+    /// The bits of a float, encoded as a varint.
+    Float,
+    /// A length-prefixed utf8 string.
+    String,
+    /// A length-prefixed byte string.
+    Bytes,
+    /// The element count of a sequence.
+    Length,
+    /// The variant index of an enum.
+    Variant,
+    /// The key of a numbered struct field, made from its number and wire type.
+    Key,
+    /// A single raw byte.
+    Byte,
+}
+
+impl ::std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        f.pad(match self {
+            Kind::Bool => "bool",
+            Kind::Unsigned => "unsigned",
+            Kind::Signed => "signed",
+            Kind::Float => "float",
+            Kind::String => "string",
+            Kind::Bytes => "bytes",
+            Kind::Length => "length",
+            Kind::Variant => "variant",
+            Kind::Key => "key",
+            Kind::Byte => "byte",
+        })
+    }
+}
+
+/// A range of encoded bytes that was produced by a single primitive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// The offset of the first byte of the span.
+    pub start: usize,
+    /// The offset one past the last byte of the span.
+    pub end: usize,
+    /// The path of the value that the span belongs to.
+    pub path: Path,
+    /// The kind of primitive that produced the span.
+    pub kind: Kind,
+}
+
+impl Span {
+    /// Get the number of bytes in the span.
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Check whether the span is empty.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// A record of the spans written by a [`Serializer`].
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    spans: Vec<Span>,
+    path: Path,
+    position: usize,
+    depth: usize,
+}
+
+impl Trace {
+    /// Get the recorded spans, in the order they were written.
+    pub fn spans(&self) -> &[Span] {
+        &self.spans[..]
+    }
+
+    /// Consume the trace and get the recorded spans.
+    pub fn into_spans(self) -> Vec<Span> {
+        self.spans
+    }
+
+    /// Create an empty trace for a buffered body, at the current path.
+    pub(crate) fn nested(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            ..Self::default()
+        }
+    }
+
+    /// Append the spans of a buffered body, which starts at the current position.
+    pub(crate) fn splice(&mut self, body: Trace) {
+        let offset = self.position;
+        self.spans.extend(body.spans.into_iter().map(|span| Span {
+            start: span.start + offset,
+            end: span.end + offset,
+            ..span
+        }));
+    }
+
+    /// Append the spans of a buffered element of a sequence, which starts at the current
+    /// position. The element was traced at the index `[]`, because its index was not known
+    /// until the sequence was sorted.
+    pub(crate) fn splice_element(&mut self, body: Trace, index: usize) {
+        let offset = self.position;
+        let depth = self.path.segments().len();
+        self.spans.extend(body.spans.into_iter().map(|mut span| {
+            span.path.set_index(depth, index);
+            Span {
+                start: span.start + offset,
+                end: span.end + offset,
+                ..span
+            }
+        }));
+    }
+
+    pub(crate) fn advance(&mut self, n: usize) {
+        self.position += n;
+    }
+
+    pub(crate) fn push(&mut self, segment: Segment) {
+        self.path.push(segment);
+    }
+
+    pub(crate) fn pop(&mut self) {
+        self.path.pop();
+    }
+
+    /// Start a primitive, and get its start offset.
+    pub(crate) fn enter(&mut self) -> usize {
+        self.depth += 1;
+        self.position
+    }
+
+    /// Finish a primitive. Only the outermost primitive is recorded, so that a string
+    /// is recorded as a single span rather than as a length followed by raw bytes.
+    pub(crate) fn exit(&mut self, start: usize, kind: Kind) {
+        self.depth -= 1;
+        if self.depth == 0 {
+            self.spans.push(Span {
+                start,
+                end: self.position,
+                path: self.path.clone(),
+                kind,
+            });
+        }
+    }
+}
+
+/// Encode a value, and get the encoded bytes along with the span of every primitive.
+pub fn trace_value<V>(v: V) -> Result<(Vec<u8>, Vec<Span>)>
+where
+    V: ::serde::Serialize,
+{
+    let mut ser = Serializer::new_traced(Vec::<u8>::new());
+    v.serialize(&mut ser)?;
+    let spans = ser.take_trace().map(Trace::into_spans).unwrap_or_default();
+    Ok((ser.into_inner(), spans))
+}
+
+/// Decode a value with the given schema, and get the span of every primitive.
+pub fn trace_bytes(schema: &Schema, buf: &[u8]) -> Result<Vec<Span>> {
+    trace_bytes_with_options(schema, buf, Options::default())
+}
+
+/// Decode a value with the given schema that was encoded with the given options,
+/// and get the span of every primitive.
+///
+/// Unknown fields at the end of length-prefixed structs are skipped, and are not
+/// covered by any span.
+pub fn trace_bytes_with_options(
+    schema: &Schema,
+    buf: &[u8],
+    options: Options,
+) -> Result<Vec<Span>> {
+    let mut tracer = Tracer {
+        buf,
+        rest: buf,
+        options,
+        path: Path::new(),
+        spans: Vec::new(),
+    };
+    tracer.trace(schema)?;
+    Ok(tracer.spans)
+}
+
+/// A helper that walks a schema over a byte slice, recording spans as it goes.
+struct Tracer<'a> {
+    buf: &'a [u8],
+    rest: &'a [u8],
+    options: Options,
+    path: Path,
+    spans: Vec<Span>,
+}
+
+impl<'a> Tracer<'a> {
+    fn read<T>(
+        &mut self,
+        kind: Kind,
+        f: impl FnOnce(&mut Deserializer<&mut &'a [u8]>) -> Result<T>,
+    ) -> Result<T> {
+        let start = self.buf.len() - self.rest.len();
+        let v = f(&mut Deserializer::new(&mut self.rest))?;
+        self.spans.push(Span {
+            start,
+            end: self.buf.len() - self.rest.len(),
+            path: self.path.clone(),
+            kind,
+        });
+        Ok(v)
+    }
+
+    fn offset(&self) -> usize {
+        self.buf.len() - self.rest.len()
+    }
+
+    /// Read a length, and get the offset where a value of that length ends.
+    fn read_end(&mut self) -> Result<usize> {
+        let len = self.read(Kind::Length, |de| de.read_uvarint())?;
+        usize::try_from(len)
+            .ok()
+            .filter(|len| *len <= self.rest.len())
+            .map(|len| self.offset() + len)
+            .ok_or_else(|| Error::Invalid(format!("length: {len}")))
+    }
+
+    /// Skip to the end of a length-prefixed value.
+    fn skip_to(&mut self, end: usize) -> Result<()> {
+        let unread = end
+            .checked_sub(self.offset())
+            .ok_or_else(|| Error::Invalid("length-prefixed value: read past its end".into()))?;
+        self.rest = &self.rest[unread..];
+        Ok(())
+    }
+
+    /// Trace a field of a numbered struct. Fields with unknown numbers are skipped.
+    fn trace_numbered_field(&mut self, s: &StructSchema, numbers: &[u64]) -> Result<()> {
+        let key = Deserializer::new(self.rest).read_uvarint()?;
+        let field = numbers
+            .iter()
+            .position(|n| *n == key >> 3)
+            .map(|i| &s.fields[i]);
+        if let Some(field) = field {
+            self.path.push(Segment::Field(field.name.clone()));
+        }
+        self.read(Kind::Key, |de| de.read_uvarint())?;
+        let end = match key & 0b111 {
+            WIRE_VARINT => None,
+            WIRE_LEN => Some(self.read_end()?),
+            wire => return Err(Error::Invalid(format!("wire type: {wire}"))),
+        };
+        match (field, end) {
+            (Some(field), end) => {
+                self.trace(&field.schema)?;
+                if let Some(end) = end {
+                    self.skip_to(end)?;
+                }
+                self.path.pop();
+            }
+            (None, Some(end)) => self.skip_to(end)?,
+            (None, None) => {
+                Deserializer::new(&mut self.rest).read_uvarint()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn nested(&mut self, segment: Segment, schema: &Schema) -> Result<()> {
+        self.path.push(segment);
+        self.trace(schema)?;
+        self.path.pop();
+        Ok(())
+    }
+
+    fn trace(&mut self, schema: &Schema) -> Result<()> {
+        match schema {
+            Schema::Unit => {}
+            Schema::Bool => {
+                self.read(Kind::Bool, |de| de.read_bool())?;
+            }
+            Schema::U8 | Schema::U16 | Schema::U32 | Schema::U64 => {
+                self.read(Kind::Unsigned, |de| de.read_uvarint())?;
+            }
+            Schema::I8 | Schema::I16 | Schema::I32 | Schema::I64 => {
+                self.read(Kind::Signed, |de| de.read_ivarint())?;
+            }
+            Schema::F32 | Schema::F64 => {
+                self.read(Kind::Float, |de| de.read_float())?;
+            }
+            Schema::String => {
+                self.read(Kind::String, |de| de.read_string(0, usize::MAX))?;
+            }
+            Schema::Bytes => {
+                self.read(Kind::Bytes, |de| de.read_bytes(0, usize::MAX))?;
+            }
+            Schema::Seq(element) => {
+                let len = self.read(Kind::Length, |de| de.read_uvarint())?;
+                for i in 0..len {
+                    let i = usize::try_from(i).map_err(|e| Error::Generic(e.to_string()))?;
+                    self.nested(Segment::Index(Some(i)), element)?;
+                }
+            }
+            Schema::Tuple(elements) => {
+                for (i, element) in elements.iter().enumerate() {
+                    self.nested(Segment::Element(i), element)?;
+                }
+            }
+            Schema::Struct(s) => match self.options.struct_encoding {
+                StructEncoding::Tuple => {
+                    for field in &s.fields {
+                        self.nested(Segment::Field(field.name.clone()), &field.schema)?;
+                    }
+                }
+                StructEncoding::LengthPrefixed => {
+                    let end = self.read_end()?;
+                    let len = self.read(Kind::Length, |de| de.read_uvarint())?;
+                    for (i, field) in s.fields.iter().enumerate() {
+                        if i as u64 >= len {
+                            return Err(Error::Invalid(format!(
+                                "struct {}: missing field {}",
+                                s.name, field.name
+                            )));
+                        }
+                        self.nested(Segment::Field(field.name.clone()), &field.schema)?;
+                    }
+                    self.skip_to(end)?;
+                }
+                StructEncoding::Numbered => {
+                    let names = s.fields.iter().map(|field| field.name.as_str());
+                    let numbers = self.options.field_numbers_of(&s.name, None, names)?;
+                    let len = self.read(Kind::Length, |de| de.read_uvarint())?;
+                    for _ in 0..len {
+                        self.trace_numbered_field(s, &numbers)?;
+                    }
+                }
+            },
+            Schema::Enum(e) => {
+                let index = self.read(Kind::Variant, |de| de.read_uvarint())?;
+                let variant = usize::try_from(index)
+                    .ok()
+                    .and_then(|index| e.variants.get(index))
+                    .ok_or_else(|| Error::Invalid(format!("variant index: {index}")))?;
+                let segment = Segment::Variant(variant.name.clone());
+                match self.options.enum_encoding {
+                    EnumEncoding::Plain => self.nested(segment, &variant.schema)?,
+                    EnumEncoding::LengthPrefixed => {
+                        let end = self.read_end()?;
+                        self.nested(segment, &variant.schema)?;
+                        self.skip_to(end)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Render an annotated hex dump of encoded bytes, with one line per span.
+///
+/// Each line shows the offset and bytes of the span, the kind of primitive that
+/// produced it, the path of the value it belongs to, and the decoded value.
+/// Bytes that are not covered by any span are shown as unannotated.
+pub fn render(buf: &[u8], spans: &[Span]) -> String {
+    use ::std::fmt::Write as _;
+
+    const MAX_HEX_BYTES: usize = 12;
+
+    let mut out = String::new();
+    let mut line = |start: usize, end: usize, annotation: &str| {
+        let bytes = &buf[start.min(buf.len())..end.min(buf.len())];
+        let mut hex =
+            Vec::from_iter(bytes.iter().take(MAX_HEX_BYTES).map(|b| format!("{b:02x}"))).join(" ");
+        if bytes.len() > MAX_HEX_BYTES {
+            hex.push_str(" ..");
+        }
+        let _ = writeln!(out, "{start:08x}  {hex:<38}  {annotation}");
+    };
+
+    let mut position = 0;
+    for span in spans {
+        if span.start > position {
+            line(position, span.start, "?");
+        }
+        let path = match span.path.is_root() {
+            true => "<root>".to_string(),
+            false => span.path.to_string(),
+        };
+        let value = describe(
+            span.kind,
+            &buf[span.start.min(buf.len())..span.end.min(buf.len())],
+        );
+        line(
+            span.start,
+            span.end,
+            &format!("{:<8}  {path} = {value}", span.kind),
+        );
+        position = position.max(span.end);
+    }
+    if position < buf.len() {
+        line(position, buf.len(), "?");
+    }
+    out
+}
+
+/// Decode the bytes of a span for display.
+fn describe(kind: Kind, mut bytes: &[u8]) -> String {
+    let mut de = Deserializer::new(&mut bytes);
+    let described = match kind {
+        Kind::Bool => de.read_bool().map(|v| v.to_string()),
+        Kind::Unsigned | Kind::Length | Kind::Variant => de.read_uvarint().map(|v| v.to_string()),
+        Kind::Signed => de.read_ivarint().map(|v| v.to_string()),
+        Kind::Float => de.read_float().map(|v| v.to_string()),
+        Kind::String => de.read_string(0, usize::MAX).map(|v| format!("{v:?}")),
+        Kind::Bytes => de
+            .read_bytes(0, usize::MAX)
+            .map(|v| format!("{} bytes", v.len())),
+        Kind::Byte => de.read_u8().map(|v| format!("{v:#04x}")),
+        Kind::Key => de
+            .read_uvarint()
+            .map(|v| format!("field {} (wire type {})", v >> 3, v & 0b111)),
+    };
+    described.unwrap_or_else(|e| format!("<{e}>"))
+}
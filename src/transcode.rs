@@ -0,0 +1,236 @@
+use std::cell::RefCell;
+
+use crate::de::Deserializer;
+use crate::error::{Error, Result};
+use crate::options::StructEncoding;
+use crate::schema::Schema;
+use crate::ser::Serializer;
+use crate::value::{Value, ValueSeed};
+
+/// Decode a SiBOR value with the given schema, and serialize it with another serde format.
+///
+/// The value is streamed from the deserializer into the serializer without being
+/// materialized. Structs are serialized as maps keyed by field name, and enums use
+/// serde's externally tagged representation: unit variants are serialized as their
+/// name, and other variants as a map with a single entry from name to payload.
+pub fn transcode<R, S>(
+    schema: &Schema,
+    de: &mut Deserializer<R>,
+    s: S,
+) -> ::std::result::Result<S::Ok, S::Error>
+where
+    R: ::std::io::Read,
+    S: ::serde::Serializer,
+{
+    ::serde::Serialize::serialize(
+        &Transcoder {
+            schema,
+            de: &RefCell::new(de),
+        },
+        s,
+    )
+}
+
+/// Decode a SiBOR value with the given schema from a byte slice, and serialize it with
+/// another serde format. See [`transcode`] for details.
+pub fn transcode_bytes<S>(
+    schema: &Schema,
+    buf: &[u8],
+    s: S,
+) -> ::std::result::Result<S::Ok, S::Error>
+where
+    S: ::serde::Serializer,
+{
+    transcode(schema, &mut Deserializer::new(buf), s)
+}
+
+/// Deserialize a value from a self-describing serde format with the given schema,
+/// and encode it as SiBOR into the serializer.
+///
+/// This accepts the same representation that [`transcode`] produces.
+pub fn transcode_from<'de, D, W>(schema: &Schema, d: D, ser: &mut Serializer<W>) -> Result<()>
+where
+    D: ::serde::Deserializer<'de>,
+    W: ::std::io::Write,
+{
+    let value: Value = ::serde::de::DeserializeSeed::deserialize(ValueSeed::new(schema), d)
+        .map_err(|e| Error::Generic(e.to_string()))?;
+    value.encode(ser)
+}
+
+/// Deserialize a value from a self-describing serde format with the given schema,
+/// and encode it as SiBOR into a byte vector. See [`transcode_from`] for details.
+pub fn transcode_from_to_bytes<'de, D>(schema: &Schema, d: D) -> Result<Vec<u8>>
+where
+    D: ::serde::Deserializer<'de>,
+{
+    let mut buf = Vec::<u8>::new();
+    transcode_from(schema, d, &mut Serializer::new(&mut buf))?;
+    Ok(buf)
+}
+
+/// A helper that serializes the next value of a shared deserializer according to a schema.
+struct Transcoder<'a, 'b, R> {
+    schema: &'a Schema,
+    de: &'a RefCell<&'b mut Deserializer<R>>,
+}
+
+impl<'a, 'b, R> Transcoder<'a, 'b, R> {
+    fn with(&self, schema: &'a Schema) -> Self {
+        Self {
+            schema,
+            de: self.de,
+        }
+    }
+}
+
+impl<'a, 'b, R> ::serde::Serialize for Transcoder<'a, 'b, R>
+where
+    R: ::std::io::Read,
+{
+    fn serialize<S>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: ::serde::Serializer,
+    {
+        use ::serde::ser::{Error as _, SerializeMap, SerializeSeq, SerializeTuple};
+
+        match self.schema {
+            Schema::Seq(element) => {
+                let len64 = self
+                    .de
+                    .borrow_mut()
+                    .read_uvarint()
+                    .map_err(S::Error::custom)?;
+                let len = usize::try_from(len64).map_err(S::Error::custom)?;
+                let mut seq = s.serialize_seq(Some(len))?;
+                for _ in 0..len {
+                    seq.serialize_element(&self.with(element))?;
+                }
+                seq.end()
+            }
+            Schema::Tuple(elements) => {
+                let mut tuple = s.serialize_tuple(elements.len())?;
+                for element in elements {
+                    tuple.serialize_element(&self.with(element))?;
+                }
+                tuple.end()
+            }
+            Schema::Struct(st)
+                if self.de.borrow().options().struct_encoding == StructEncoding::Numbered =>
+            {
+                let len64 = self
+                    .de
+                    .borrow_mut()
+                    .read_uvarint()
+                    .map_err(S::Error::custom)?;
+                let names = st.fields.iter().map(|field| field.name.as_str());
+                let numbers = self
+                    .de
+                    .borrow()
+                    .options()
+                    .field_numbers_of(&st.name, None, names)
+                    .map_err(S::Error::custom)?;
+                let mut map = s.serialize_map(None)?;
+                let mut previous = None;
+                for _ in 0..len64 {
+                    let (number, end) = self
+                        .de
+                        .borrow_mut()
+                        .read_field_key(&mut previous)
+                        .map_err(S::Error::custom)?;
+                    match numbers.iter().position(|n| *n == number) {
+                        Some(i) => {
+                            let field = &st.fields[i];
+                            map.serialize_entry(&field.name, &self.with(&field.schema))?;
+                            self.de
+                                .borrow_mut()
+                                .end_body(end)
+                                .map_err(S::Error::custom)?;
+                        }
+                        None => self
+                            .de
+                            .borrow_mut()
+                            .skip_field_value(end)
+                            .map_err(S::Error::custom)?,
+                    }
+                }
+                map.end()
+            }
+            Schema::Struct(st) => {
+                let body = self
+                    .de
+                    .borrow_mut()
+                    .begin_struct()
+                    .map_err(S::Error::custom)?;
+                let mut map = s.serialize_map(Some(st.fields.len()))?;
+                for (i, field) in st.fields.iter().enumerate() {
+                    if !self.de.borrow().has_field(body, i) {
+                        return Err(S::Error::custom(Error::Invalid(format!(
+                            "struct {}: missing field {}",
+                            st.name, field.name
+                        ))));
+                    }
+                    map.serialize_entry(&field.name, &self.with(&field.schema))?;
+                }
+                self.de
+                    .borrow_mut()
+                    .end_struct(body, st.fields.len())
+                    .map_err(S::Error::custom)?;
+                map.end()
+            }
+            Schema::Enum(e) => {
+                let index64 = self
+                    .de
+                    .borrow_mut()
+                    .read_uvarint()
+                    .map_err(S::Error::custom)?;
+                let variant = usize::try_from(index64)
+                    .ok()
+                    .and_then(|index| e.variants.get(index))
+                    .ok_or_else(|| {
+                        S::Error::custom(Error::Invalid(format!("variant index: {index64}")))
+                    })?;
+                let end = self
+                    .de
+                    .borrow_mut()
+                    .begin_variant()
+                    .map_err(S::Error::custom)?;
+                if variant.schema == Schema::Unit {
+                    self.de
+                        .borrow_mut()
+                        .end_body(end)
+                        .map_err(S::Error::custom)?;
+                    return s.serialize_str(&variant.name);
+                }
+                let mut map = s.serialize_map(Some(1))?;
+                map.serialize_entry(&variant.name, &self.with(&variant.schema))?;
+                self.de
+                    .borrow_mut()
+                    .end_body(end)
+                    .map_err(S::Error::custom)?;
+                map.end()
+            }
+            scalar => {
+                let value =
+                    Value::decode(scalar, &mut self.de.borrow_mut()).map_err(S::Error::custom)?;
+                match (scalar, value) {
+                    (_, Value::Unit) => s.serialize_unit(),
+                    (_, Value::Bool(v)) => s.serialize_bool(v),
+                    (Schema::U8, Value::Unsigned(v)) => s.serialize_u8(v as u8),
+                    (Schema::U16, Value::Unsigned(v)) => s.serialize_u16(v as u16),
+                    (Schema::U32, Value::Unsigned(v)) => s.serialize_u32(v as u32),
+                    (_, Value::Unsigned(v)) => s.serialize_u64(v),
+                    (Schema::I8, Value::Signed(v)) => s.serialize_i8(v as i8),
+                    (Schema::I16, Value::Signed(v)) => s.serialize_i16(v as i16),
+                    (Schema::I32, Value::Signed(v)) => s.serialize_i32(v as i32),
+                    (_, Value::Signed(v)) => s.serialize_i64(v),
+                    (Schema::F32, Value::Float(v)) => s.serialize_f32(v as f32),
+                    (_, Value::Float(v)) => s.serialize_f64(v),
+                    (_, Value::String(v)) => s.serialize_str(&v),
+                    (_, Value::Bytes(v)) => s.serialize_bytes(&v),
+                    _ => unreachable!("scalar schemas decode into scalar values"),
+                }
+            }
+        }
+    }
+}
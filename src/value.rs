@@ -0,0 +1,492 @@
+use crate::de::Deserializer;
+use crate::error::{Error, Result};
+use crate::options::StructEncoding;
+use crate::schema::Schema;
+use crate::ser::Serializer;
+
+/// A dynamically typed SiBOR value.
+///
+/// Since SiBOR is not self-describing, a value can only be decoded with the help of a [`Schema`].
+/// Encoding a value does not need a schema, because the value carries its own structure.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A value that occupies no bytes.
+    Unit,
+    /// A boolean.
+    Bool(bool),
+    /// An unsigned integer of any width.
+    Unsigned(u64),
+    /// A signed integer of any width.
+    Signed(i64),
+    /// A floating point number of any width.
+    Float(f64),
+    /// A utf8 string.
+    String(String),
+    /// A byte string.
+    Bytes(Vec<u8>),
+    /// A sequence of values.
+    Seq(Vec<Value>),
+    /// A fixed number of values.
+    Tuple(Vec<Value>),
+    /// The named fields of a struct, in declaration order.
+    Struct(Vec<(String, Value)>),
+    /// A variant of an enum.
+    Enum {
+        /// The index of the variant.
+        index: u32,
+        /// The name of the variant.
+        name: String,
+        /// The payload of the variant.
+        value: Box<Value>,
+    },
+}
+
+impl Value {
+    /// Decode a value with the given schema from a deserializer.
+    pub fn decode<'de, R>(schema: &Schema, de: &mut Deserializer<R>) -> Result<Self>
+    where
+        R: crate::de::Read<'de>,
+    {
+        fn unsigned(v: u64, max: u64) -> Result<Value> {
+            if v > max {
+                return Err(Error::Invalid(format!("unsigned integer: {v}")));
+            }
+            Ok(Value::Unsigned(v))
+        }
+
+        fn signed(v: i64, min: i64, max: i64) -> Result<Value> {
+            if v < min || v > max {
+                return Err(Error::Invalid(format!("signed integer: {v}")));
+            }
+            Ok(Value::Signed(v))
+        }
+
+        match schema {
+            Schema::Unit => Ok(Value::Unit),
+            Schema::Bool => Ok(Value::Bool(de.read_bool()?)),
+            Schema::U8 => unsigned(de.read_uvarint()?, u8::MAX as u64),
+            Schema::U16 => unsigned(de.read_uvarint()?, u16::MAX as u64),
+            Schema::U32 => unsigned(de.read_uvarint()?, u32::MAX as u64),
+            Schema::U64 => unsigned(de.read_uvarint()?, u64::MAX),
+            Schema::I8 => signed(de.read_ivarint()?, i8::MIN as i64, i8::MAX as i64),
+            Schema::I16 => signed(de.read_ivarint()?, i16::MIN as i64, i16::MAX as i64),
+            Schema::I32 => signed(de.read_ivarint()?, i32::MIN as i64, i32::MAX as i64),
+            Schema::I64 => signed(de.read_ivarint()?, i64::MIN, i64::MAX),
+            Schema::F32 | Schema::F64 => Ok(Value::Float(de.read_float()?)),
+            Schema::String => Ok(Value::String(de.read_string(0, usize::MAX)?)),
+            Schema::Bytes => Ok(Value::Bytes(de.read_bytes(0, usize::MAX)?)),
+            Schema::Seq(element) => {
+                let len64 = de.read_uvarint()?;
+                let len = usize::try_from(len64).map_err(|e| Error::Generic(e.to_string()))?;
+                let mut elements = Vec::new();
+                for _ in 0..len {
+                    elements.push(Value::decode(element, de)?);
+                }
+                Ok(Value::Seq(elements))
+            }
+            Schema::Tuple(elements) => Ok(Value::Tuple(
+                elements
+                    .iter()
+                    .map(|element| Value::decode(element, de))
+                    .collect::<Result<_>>()?,
+            )),
+            Schema::Struct(s) if de.options().struct_encoding == StructEncoding::Numbered => {
+                let len64 = de.read_uvarint()?;
+                let names = s.fields.iter().map(|field| field.name.as_str());
+                let numbers = de.options().field_numbers_of(&s.name, None, names)?;
+                let mut values = Vec::from_iter(s.fields.iter().map(|_| None));
+                let mut previous = None;
+                for _ in 0..len64 {
+                    let (number, end) = de.read_field_key(&mut previous)?;
+                    match numbers.iter().position(|n| *n == number) {
+                        Some(i) => {
+                            values[i] = Some(Value::decode(&s.fields[i].schema, de)?);
+                            de.end_body(end)?;
+                        }
+                        None => de.skip_field_value(end)?,
+                    }
+                }
+                let fields = s.fields.iter().zip(values).map(|(field, value)| {
+                    let value = value.ok_or_else(|| {
+                        Error::Invalid(format!("struct {}: missing field {}", s.name, field.name))
+                    })?;
+                    Ok((field.name.clone(), value))
+                });
+                Ok(Value::Struct(fields.collect::<Result<_>>()?))
+            }
+            Schema::Struct(s) => {
+                let body = de.begin_struct()?;
+                let mut fields = Vec::new();
+                for (i, field) in s.fields.iter().enumerate() {
+                    if !de.has_field(body, i) {
+                        return Err(Error::Invalid(format!(
+                            "struct {}: missing field {}",
+                            s.name, field.name
+                        )));
+                    }
+                    fields.push((field.name.clone(), Value::decode(&field.schema, de)?));
+                }
+                de.end_struct(body, s.fields.len())?;
+                Ok(Value::Struct(fields))
+            }
+            Schema::Enum(e) => {
+                let index64 = de.read_uvarint()?;
+                let variant = usize::try_from(index64)
+                    .ok()
+                    .and_then(|index| e.variants.get(index))
+                    .ok_or_else(|| Error::Invalid(format!("variant index: {index64}")))?;
+                let end = de.begin_variant()?;
+                let value = Value::decode(&variant.schema, de)?;
+                de.end_body(end)?;
+                Ok(Value::Enum {
+                    index: index64 as u32,
+                    name: variant.name.clone(),
+                    value: Box::new(value),
+                })
+            }
+        }
+    }
+
+    /// Decode a value with the given schema from a byte slice.
+    pub fn from_bytes(schema: &Schema, buf: &[u8]) -> Result<Self> {
+        Value::decode(schema, &mut Deserializer::from_slice(buf))
+    }
+
+    /// Encode this value into a serializer.
+    pub fn encode<W>(&self, ser: &mut Serializer<W>) -> Result<()>
+    where
+        W: ::std::io::Write,
+    {
+        ::serde::Serialize::serialize(self, ser)
+    }
+
+    /// Encode this value into a byte vector.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        crate::to_bytes(self)
+    }
+}
+
+/// Values serialize with the same data model calls as the types they were decoded from,
+/// so encoding a value produces the same bytes as encoding the original. Struct, field and
+/// variant names are not available to the serializer, so use [`crate::transcode`] to
+/// convert a value into a self-describing format.
+impl ::serde::Serialize for Value {
+    fn serialize<S>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: ::serde::Serializer,
+    {
+        use ::serde::ser::SerializeTupleVariant;
+        use ::serde::ser::{SerializeStruct, SerializeStructVariant, SerializeTuple};
+
+        match self {
+            Value::Unit => s.serialize_unit(),
+            Value::Bool(v) => s.serialize_bool(*v),
+            Value::Unsigned(v) => s.serialize_u64(*v),
+            Value::Signed(v) => s.serialize_i64(*v),
+            Value::Float(v) => s.serialize_f64(*v),
+            Value::String(v) => s.serialize_str(v),
+            Value::Bytes(v) => s.serialize_bytes(v),
+            Value::Seq(elements) => s.collect_seq(elements),
+            Value::Tuple(elements) => {
+                let mut tuple = s.serialize_tuple(elements.len())?;
+                for element in elements {
+                    tuple.serialize_element(element)?;
+                }
+                tuple.end()
+            }
+            Value::Struct(fields) => {
+                let mut st = s.serialize_struct("", fields.len())?;
+                for (_, field) in fields {
+                    st.serialize_field("", field)?;
+                }
+                st.end()
+            }
+            Value::Enum { index, value, .. } => match value.as_ref() {
+                Value::Unit => s.serialize_unit_variant("", *index, ""),
+                Value::Tuple(elements) => {
+                    let mut tuple = s.serialize_tuple_variant("", *index, "", elements.len())?;
+                    for element in elements {
+                        tuple.serialize_field(element)?;
+                    }
+                    tuple.end()
+                }
+                Value::Struct(fields) => {
+                    let mut st = s.serialize_struct_variant("", *index, "", fields.len())?;
+                    for (_, field) in fields {
+                        st.serialize_field("", field)?;
+                    }
+                    st.end()
+                }
+                value => s.serialize_newtype_variant("", *index, "", value),
+            },
+        }
+    }
+}
+
+/// A seed that deserializes a [`Value`] with the given schema from any self-describing serde format.
+///
+/// Structs are accepted as maps keyed by field name or as sequences of fields, and enums
+/// are accepted in serde's externally tagged representation.
+#[derive(Debug, Clone, Copy)]
+pub struct ValueSeed<'a> {
+    schema: &'a Schema,
+}
+
+impl<'a> ValueSeed<'a> {
+    /// Create a new seed for values of the given schema.
+    pub fn new(schema: &'a Schema) -> Self {
+        Self { schema }
+    }
+}
+
+impl<'de, 'a> ::serde::de::DeserializeSeed<'de> for ValueSeed<'a> {
+    type Value = Value;
+
+    fn deserialize<D>(self, d: D) -> ::std::result::Result<Self::Value, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        match self.schema {
+            Schema::Unit => d.deserialize_unit(self),
+            Schema::Bool => d.deserialize_bool(self),
+            Schema::U8 | Schema::U16 | Schema::U32 | Schema::U64 => d.deserialize_u64(self),
+            Schema::I8 | Schema::I16 | Schema::I32 | Schema::I64 => d.deserialize_i64(self),
+            Schema::F32 | Schema::F64 => d.deserialize_f64(self),
+            Schema::String => d.deserialize_string(self),
+            Schema::Bytes => d.deserialize_byte_buf(self),
+            Schema::Seq(_) => d.deserialize_seq(self),
+            Schema::Tuple(elements) => d.deserialize_tuple(elements.len(), self),
+            Schema::Struct(_) | Schema::Enum(_) => d.deserialize_any(self),
+        }
+    }
+}
+
+impl<'de, 'a> ::serde::de::Visitor<'de> for ValueSeed<'a> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "a value of type {}", self.schema)
+    }
+
+    fn visit_unit<E>(self) -> ::std::result::Result<Self::Value, E>
+    where
+        E: ::serde::de::Error,
+    {
+        match self.schema {
+            Schema::Unit => Ok(Value::Unit),
+            _ => Err(E::invalid_type(::serde::de::Unexpected::Unit, &self)),
+        }
+    }
+
+    fn visit_bool<E>(self, v: bool) -> ::std::result::Result<Self::Value, E>
+    where
+        E: ::serde::de::Error,
+    {
+        match self.schema {
+            Schema::Bool => Ok(Value::Bool(v)),
+            _ => Err(E::invalid_type(::serde::de::Unexpected::Bool(v), &self)),
+        }
+    }
+
+    fn visit_u64<E>(self, v: u64) -> ::std::result::Result<Self::Value, E>
+    where
+        E: ::serde::de::Error,
+    {
+        let max = match self.schema {
+            Schema::U8 => u8::MAX as u64,
+            Schema::U16 => u16::MAX as u64,
+            Schema::U32 => u32::MAX as u64,
+            Schema::U64 => u64::MAX,
+            Schema::I8 | Schema::I16 | Schema::I32 | Schema::I64 => {
+                let signed = i64::try_from(v)
+                    .map_err(|_| E::invalid_value(::serde::de::Unexpected::Unsigned(v), &self))?;
+                return self.visit_i64(signed);
+            }
+            Schema::F32 | Schema::F64 => return self.visit_f64(v as f64),
+            _ => return Err(E::invalid_type(::serde::de::Unexpected::Unsigned(v), &self)),
+        };
+        if v > max {
+            return Err(E::invalid_value(
+                ::serde::de::Unexpected::Unsigned(v),
+                &self,
+            ));
+        }
+        Ok(Value::Unsigned(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> ::std::result::Result<Self::Value, E>
+    where
+        E: ::serde::de::Error,
+    {
+        let (min, max) = match self.schema {
+            Schema::I8 => (i8::MIN as i64, i8::MAX as i64),
+            Schema::I16 => (i16::MIN as i64, i16::MAX as i64),
+            Schema::I32 => (i32::MIN as i64, i32::MAX as i64),
+            Schema::I64 => (i64::MIN, i64::MAX),
+            Schema::U8 | Schema::U16 | Schema::U32 | Schema::U64 => {
+                let unsigned = u64::try_from(v)
+                    .map_err(|_| E::invalid_value(::serde::de::Unexpected::Signed(v), &self))?;
+                return self.visit_u64(unsigned);
+            }
+            Schema::F32 | Schema::F64 => return self.visit_f64(v as f64),
+            _ => return Err(E::invalid_type(::serde::de::Unexpected::Signed(v), &self)),
+        };
+        if v < min || v > max {
+            return Err(E::invalid_value(::serde::de::Unexpected::Signed(v), &self));
+        }
+        Ok(Value::Signed(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> ::std::result::Result<Self::Value, E>
+    where
+        E: ::serde::de::Error,
+    {
+        match self.schema {
+            Schema::F32 => Ok(Value::Float(v as f32 as f64)),
+            Schema::F64 => Ok(Value::Float(v)),
+            _ => Err(E::invalid_type(::serde::de::Unexpected::Float(v), &self)),
+        }
+    }
+
+    fn visit_str<E>(self, v: &str) -> ::std::result::Result<Self::Value, E>
+    where
+        E: ::serde::de::Error,
+    {
+        match self.schema {
+            Schema::String => Ok(Value::String(v.into())),
+            Schema::Bytes => Ok(Value::Bytes(v.as_bytes().into())),
+            Schema::Enum(e) => {
+                let (index, variant) = e
+                    .variants
+                    .iter()
+                    .enumerate()
+                    .find(|(_, variant)| variant.name == v)
+                    .ok_or_else(|| E::invalid_value(::serde::de::Unexpected::Str(v), &self))?;
+                if variant.schema != Schema::Unit {
+                    return Err(E::invalid_type(::serde::de::Unexpected::UnitVariant, &self));
+                }
+                Ok(Value::Enum {
+                    index: index as u32,
+                    name: variant.name.clone(),
+                    value: Box::new(Value::Unit),
+                })
+            }
+            _ => Err(E::invalid_type(::serde::de::Unexpected::Str(v), &self)),
+        }
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> ::std::result::Result<Self::Value, E>
+    where
+        E: ::serde::de::Error,
+    {
+        match self.schema {
+            Schema::Bytes => Ok(Value::Bytes(v.into())),
+            _ => Err(E::invalid_type(::serde::de::Unexpected::Bytes(v), &self)),
+        }
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> ::std::result::Result<Self::Value, A::Error>
+    where
+        A: ::serde::de::SeqAccess<'de>,
+    {
+        use ::serde::de::Error as _;
+
+        let mut next = |schema: &Schema, i: usize| {
+            seq.next_element_seed(ValueSeed::new(schema))?
+                .ok_or_else(|| A::Error::invalid_length(i, &self))
+        };
+
+        match self.schema {
+            Schema::Bytes => {
+                let mut bytes = Vec::new();
+                while let Some(b) = seq.next_element::<u8>()? {
+                    bytes.push(b);
+                }
+                Ok(Value::Bytes(bytes))
+            }
+            Schema::Seq(element) => {
+                let mut elements = Vec::new();
+                while let Some(v) = seq.next_element_seed(ValueSeed::new(element))? {
+                    elements.push(v);
+                }
+                Ok(Value::Seq(elements))
+            }
+            Schema::Tuple(elements) => {
+                let values = elements
+                    .iter()
+                    .enumerate()
+                    .map(|(i, element)| next(element, i))
+                    .collect::<::std::result::Result<_, _>>()?;
+                if seq.next_element::<::serde::de::IgnoredAny>()?.is_some() {
+                    return Err(A::Error::invalid_length(elements.len() + 1, &self));
+                }
+                Ok(Value::Tuple(values))
+            }
+            Schema::Struct(s) => Ok(Value::Struct(
+                s.fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, field)| Ok((field.name.clone(), next(&field.schema, i)?)))
+                    .collect::<::std::result::Result<_, _>>()?,
+            )),
+            _ => Err(A::Error::invalid_type(::serde::de::Unexpected::Seq, &self)),
+        }
+    }
+
+    fn visit_map<A>(self, mut map: A) -> ::std::result::Result<Self::Value, A::Error>
+    where
+        A: ::serde::de::MapAccess<'de>,
+    {
+        use ::serde::de::Error as _;
+
+        match self.schema {
+            Schema::Struct(s) => {
+                let mut fields = vec![None; s.fields.len()];
+                while let Some(key) = map.next_key::<String>()? {
+                    let i = s
+                        .fields
+                        .iter()
+                        .position(|field| field.name == key)
+                        .ok_or_else(|| A::Error::unknown_field(&key, &[]))?;
+                    if fields[i].is_some() {
+                        return Err(A::Error::custom(format!("duplicate field `{key}`")));
+                    }
+                    fields[i] = Some(map.next_value_seed(ValueSeed::new(&s.fields[i].schema))?);
+                }
+                Ok(Value::Struct(
+                    s.fields
+                        .iter()
+                        .zip(fields)
+                        .map(|(field, value)| {
+                            let value = value.ok_or_else(|| {
+                                A::Error::custom(format!("missing field `{}`", field.name))
+                            })?;
+                            Ok((field.name.clone(), value))
+                        })
+                        .collect::<::std::result::Result<_, _>>()?,
+                ))
+            }
+            Schema::Enum(e) => {
+                let name = map
+                    .next_key::<String>()?
+                    .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+                let (index, variant) = e
+                    .variants
+                    .iter()
+                    .enumerate()
+                    .find(|(_, variant)| variant.name == name)
+                    .ok_or_else(|| A::Error::unknown_variant(&name, &[]))?;
+                let value = map.next_value_seed(ValueSeed::new(&variant.schema))?;
+                if map.next_key::<::serde::de::IgnoredAny>()?.is_some() {
+                    return Err(A::Error::invalid_length(2, &self));
+                }
+                Ok(Value::Enum {
+                    index: index as u32,
+                    name: variant.name.clone(),
+                    value: Box::new(value),
+                })
+            }
+            _ => Err(A::Error::invalid_type(::serde::de::Unexpected::Map, &self)),
+        }
+    }
+}
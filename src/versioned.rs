@@ -0,0 +1,171 @@
+use crate::error::{Error, Result};
+
+/// A type that belongs to a chain of historic versions.
+///
+/// Each version names the type of the version that came before it, and knows
+/// how to upgrade a value of that type. Together, the chain acts as a registry
+/// of decoders for every version that was ever written: an old value is decoded
+/// with its historic type and then upgraded one step at a time until it reaches
+/// the requested type. The first version in a chain uses [`Initial`] as its
+/// previous version.
+pub trait Migrate: ::serde::Serialize + ::serde::de::DeserializeOwned {
+    /// The version number that is written when encoding this type.
+    /// Version numbers must increase along the chain.
+    const VERSION: u64;
+
+    /// The type of the previous version.
+    type Previous: Migrate;
+
+    /// Upgrade a value of the previous version into this version.
+    fn migrate(previous: Self::Previous) -> Result<Self>;
+
+    /// Deserialize a value that was written with the given version,
+    /// migrating it into this version if necessary.
+    fn deserialize_version<'de, D>(version: u64, d: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        use ::serde::de::Error as _;
+
+        if version == Self::VERSION {
+            Self::deserialize(d)
+        } else if version < Self::VERSION {
+            let previous = Self::Previous::deserialize_version(version, d)?;
+            Self::migrate(previous).map_err(D::Error::custom)
+        } else {
+            Err(D::Error::custom(format!(
+                "version {version} is newer than version {}",
+                Self::VERSION
+            )))
+        }
+    }
+}
+
+/// The start of every version chain. No value of this type exists,
+/// and no version number decodes into it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ::serde::Serialize, ::serde::Deserialize)]
+pub enum Initial {}
+
+impl Migrate for Initial {
+    const VERSION: u64 = 0;
+
+    type Previous = Initial;
+
+    fn migrate(previous: Self::Previous) -> Result<Self> {
+        match previous {}
+    }
+
+    fn deserialize_version<'de, D>(version: u64, _: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        use ::serde::de::Error as _;
+        Err(D::Error::custom(Error::Unsupported(format!(
+            "version {version}"
+        ))))
+    }
+}
+
+/// A value that is encoded with its version number in front of it.
+///
+/// The version is written as a variable-length unsigned integer, followed by the payload.
+/// Encoding always writes the latest version, [`Migrate::VERSION`], while decoding accepts
+/// any version in the chain of `T` and migrates it into a `T`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Versioned<T>(pub T);
+
+impl<T> Versioned<T> {
+    /// Unwrap the versioned value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> ::std::ops::Deref for Versioned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> ::std::ops::DerefMut for Versioned<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl<T> ::serde::Serialize for Versioned<T>
+where
+    T: Migrate,
+{
+    fn serialize<S>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: ::serde::Serializer,
+    {
+        use ::serde::ser::SerializeTuple;
+
+        let mut tuple = s.serialize_tuple(2)?;
+        tuple.serialize_element(&T::VERSION)?;
+        tuple.serialize_element(&self.0)?;
+        tuple.end()
+    }
+}
+
+impl<'de, T> ::serde::Deserialize<'de> for Versioned<T>
+where
+    T: Migrate,
+{
+    fn deserialize<D>(d: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        d.deserialize_tuple(2, VersionedVisitor(::std::marker::PhantomData))
+    }
+}
+
+/// A helper for deserializing the version number and the payload that follows it.
+struct VersionedVisitor<T>(::std::marker::PhantomData<T>);
+
+impl<'de, T> ::serde::de::Visitor<'de> for VersionedVisitor<T>
+where
+    T: Migrate,
+{
+    type Value = Versioned<T>;
+
+    fn expecting(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "a version number followed by a payload")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> ::std::result::Result<Self::Value, A::Error>
+    where
+        A: ::serde::de::SeqAccess<'de>,
+    {
+        use ::serde::de::Error as _;
+
+        let version: u64 = seq
+            .next_element()?
+            .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+        let value = seq
+            .next_element_seed(VersionSeed::<T>(version, ::std::marker::PhantomData))?
+            .ok_or_else(|| A::Error::invalid_length(1, &self))?;
+        Ok(Versioned(value))
+    }
+}
+
+/// A helper for deserializing a payload of a known version.
+struct VersionSeed<T>(u64, ::std::marker::PhantomData<T>);
+
+impl<'de, T> ::serde::de::DeserializeSeed<'de> for VersionSeed<T>
+where
+    T: Migrate,
+{
+    type Value = T;
+
+    fn deserialize<D>(self, d: D) -> ::std::result::Result<Self::Value, D::Error>
+    where
+        D: ::serde::Deserializer<'de>,
+    {
+        T::deserialize_version(self.0, d)
+    }
+}
@@ -0,0 +1,376 @@
+use crate::de::Deserializer;
+use crate::error::{Error, Result};
+use crate::options::{EnumEncoding, Options, StructEncoding, WIRE_LEN, WIRE_VARINT};
+use crate::path::{Path, Segment};
+use crate::schema::Schema;
+use crate::value::Value;
+
+/// A lazy, schema-driven view of an encoded value.
+///
+/// A view only knows where its value starts. Seeking to a nested value skips over
+/// earlier values using the varint and length prefix rules, without decoding them,
+/// so reading a single field of a large message only touches the bytes before it.
+#[derive(Debug, Clone, Copy)]
+pub struct View<'a> {
+    schema: &'a Schema,
+    buf: &'a [u8],
+    options: Options,
+}
+
+impl<'a> View<'a> {
+    /// Create a view of the value at the start of the buffer.
+    pub fn new(schema: &'a Schema, buf: &'a [u8]) -> Self {
+        Self::with_options(schema, buf, Options::default())
+    }
+
+    /// Create a view of the value at the start of the buffer, encoded with the given options.
+    pub fn with_options(schema: &'a Schema, buf: &'a [u8], options: Options) -> Self {
+        Self {
+            schema,
+            buf,
+            options,
+        }
+    }
+
+    /// Get the schema of the value.
+    pub fn schema(&self) -> &'a Schema {
+        self.schema
+    }
+
+    /// Get the encoded bytes of the value.
+    pub fn bytes(&self) -> Result<&'a [u8]> {
+        let mut rest = self.buf;
+        skip(self.schema, &self.options, &mut rest)?;
+        Ok(&self.buf[..self.buf.len() - rest.len()])
+    }
+
+    /// Decode the value.
+    pub fn decode<T>(&self) -> Result<T>
+    where
+        T: ::serde::Deserialize<'a>,
+    {
+        crate::from_bytes_with_options(self.bytes()?, self.options)
+    }
+
+    /// Decode the value as a dynamically typed value.
+    pub fn value(&self) -> Result<Value> {
+        let mut de = Deserializer::from_slice_with_options(self.bytes()?, self.options);
+        Value::decode(self.schema, &mut de)
+    }
+
+    /// Get a view of a field of a struct.
+    pub fn field(&self, name: &str) -> Result<Self> {
+        let Schema::Struct(s) = self.schema else {
+            return Err(self.mismatch("struct"));
+        };
+        if self.options.struct_encoding == StructEncoding::Numbered {
+            let (i, field) = s
+                .fields
+                .iter()
+                .enumerate()
+                .find(|(_, field)| field.name == name)
+                .ok_or_else(|| Error::Invalid(format!("field: {}.{name}", s.name)))?;
+            let names = s.fields.iter().map(|field| field.name.as_str());
+            let number = self.options.field_numbers_of(&s.name, None, names)?[i];
+            let mut rest = self.buf;
+            let len = Deserializer::new(&mut rest).read_uvarint()?;
+            for _ in 0..len {
+                let (n, value) = numbered_field(&mut rest)?;
+                if n == number {
+                    return Ok(self.with(&field.schema, value));
+                }
+            }
+            return Err(Error::Invalid(format!(
+                "field: {}.{name} is missing",
+                s.name
+            )));
+        }
+        let (len, mut rest) = struct_body(&self.options, self.buf)?;
+        for (i, field) in s.fields.iter().enumerate() {
+            if field.name == name {
+                if len.is_some_and(|len| i as u64 >= len) {
+                    return Err(Error::Invalid(format!(
+                        "field: {}.{name} is missing",
+                        s.name
+                    )));
+                }
+                return Ok(self.with(&field.schema, rest));
+            }
+            skip(&field.schema, &self.options, &mut rest)?;
+        }
+        Err(Error::Invalid(format!("field: {}.{name}", s.name)))
+    }
+
+    /// Get a view of an element of a tuple or a sequence.
+    pub fn element(&self, index: usize) -> Result<Self> {
+        match self.schema {
+            Schema::Tuple(elements) => {
+                let element = elements
+                    .get(index)
+                    .ok_or_else(|| Error::Invalid(format!("tuple element: {index}")))?;
+                let mut rest = self.buf;
+                for element in &elements[..index] {
+                    skip(element, &self.options, &mut rest)?;
+                }
+                Ok(self.with(element, rest))
+            }
+            Schema::Seq(_) => self
+                .elements()?
+                .nth(index)
+                .ok_or_else(|| Error::Invalid(format!("sequence index: {index}")))?,
+            _ => Err(self.mismatch("tuple or sequence")),
+        }
+    }
+
+    /// Get the index and name of the variant of an enum, and a view of its payload.
+    pub fn variant(&self) -> Result<(u32, &'a str, Self)> {
+        let Schema::Enum(e) = self.schema else {
+            return Err(self.mismatch("enum"));
+        };
+        let mut rest = self.buf;
+        let index = Deserializer::new(&mut rest).read_uvarint()?;
+        let (index, variant) = u32::try_from(index)
+            .ok()
+            .and_then(|index| Some((index, e.variants.get(index as usize)?)))
+            .ok_or_else(|| Error::Invalid(format!("variant index: {index}")))?;
+        let payload = match self.options.enum_encoding {
+            EnumEncoding::Plain => rest,
+            EnumEncoding::LengthPrefixed => prefixed(&mut rest)?,
+        };
+        Ok((index, &variant.name, self.with(&variant.schema, payload)))
+    }
+
+    /// Get the number of elements of a sequence.
+    pub fn len(&self) -> Result<usize> {
+        Ok(self.elements()?.remaining)
+    }
+
+    /// Check whether a sequence is empty.
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Iterate over views of the elements of a sequence.
+    pub fn elements(&self) -> Result<Elements<'a>> {
+        let Schema::Seq(element) = self.schema else {
+            return Err(self.mismatch("sequence"));
+        };
+        let mut rest = self.buf;
+        let len = Deserializer::new(&mut rest).read_uvarint()?;
+        let remaining = usize::try_from(len).map_err(|e| Error::Generic(e.to_string()))?;
+        Ok(Elements {
+            schema: element,
+            rest,
+            remaining,
+            options: self.options,
+        })
+    }
+
+    /// Get a view of the value at a path.
+    ///
+    /// Field segments also select the variant of an enum with that name, and fail if
+    /// another variant is present.
+    pub fn get(&self, path: &Path) -> Result<Self> {
+        let mut view = *self;
+        for segment in path.segments() {
+            view = match (segment, view.schema) {
+                (Segment::Field(name) | Segment::Variant(name), Schema::Enum(_)) => {
+                    let (_, variant, payload) = view.variant()?;
+                    if variant != name {
+                        return Err(Error::Invalid(format!(
+                            "path {path}: variant is {variant}, not {name}"
+                        )));
+                    }
+                    payload
+                }
+                (Segment::Field(name), _) => view.field(name)?,
+                (Segment::Element(index) | Segment::Index(Some(index)), _) => {
+                    view.element(*index)?
+                }
+                (Segment::Index(None) | Segment::Variant(_), _) => {
+                    return Err(Error::Invalid(format!(
+                        "path {path}: {segment:?} does not select a single value"
+                    )))
+                }
+            };
+        }
+        Ok(view)
+    }
+
+    /// Get a view of the value at a path such as `header.id` or `items[42].name`.
+    pub fn seek(&self, path: &str) -> Result<Self> {
+        self.get(&path.parse()?)
+    }
+
+    fn with(&self, schema: &'a Schema, buf: &'a [u8]) -> Self {
+        Self::with_options(schema, buf, self.options)
+    }
+
+    fn mismatch(&self, expected: &str) -> Error {
+        Error::Invalid(format!("view: expected {expected}, found {}", self.schema))
+    }
+}
+
+/// An iterator over views of the elements of a sequence.
+///
+/// Each element is skipped when the next one is requested, so the elements are never
+/// decoded unless the caller decodes their views.
+#[derive(Debug, Clone)]
+pub struct Elements<'a> {
+    schema: &'a Schema,
+    rest: &'a [u8],
+    remaining: usize,
+    options: Options,
+}
+
+impl<'a> Iterator for Elements<'a> {
+    type Item = Result<View<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let view = View::with_options(self.schema, self.rest, self.options);
+        match skip(self.schema, &self.options, &mut self.rest) {
+            Ok(()) => self.remaining -= 1,
+            Err(e) => {
+                self.remaining = 0;
+                return Some(Err(e));
+            }
+        }
+        Some(Ok(view))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
+    }
+}
+
+/// Get the number of fields of a struct at the start of the buffer, and the bytes of its
+/// fields.
+///
+/// With length-prefixed structs these are the number of fields and the rest of the body,
+/// and otherwise the number of fields is not stored and the bytes are the rest of the
+/// buffer. Numbered structs are not positional, so their fields are found with
+/// [`numbered_field`] instead.
+fn struct_body<'a>(options: &Options, buf: &'a [u8]) -> Result<(Option<u64>, &'a [u8])> {
+    let mut rest = buf;
+    match options.struct_encoding {
+        StructEncoding::Tuple | StructEncoding::Numbered => Ok((None, rest)),
+        StructEncoding::LengthPrefixed => {
+            let mut body = prefixed(&mut rest)?;
+            let len = Deserializer::new(&mut body).read_uvarint()?;
+            Ok((Some(len), body))
+        }
+    }
+}
+
+/// Read a length-prefixed value, and get its bytes without the prefix.
+fn prefixed<'a>(rest: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = Deserializer::new(&mut *rest).read_uvarint()?;
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|len| *len <= rest.len())
+        .ok_or_else(|| Error::Invalid(format!("length: {len}")))?;
+    let (value, tail) = rest.split_at(len);
+    *rest = tail;
+    Ok(value)
+}
+
+/// Read the key of a field of a numbered struct, and get its number and the bytes of
+/// its value.
+fn numbered_field<'a>(rest: &mut &'a [u8]) -> Result<(u64, &'a [u8])> {
+    let key = Deserializer::new(&mut *rest).read_uvarint()?;
+    let value = match key & 0b111 {
+        WIRE_VARINT => {
+            let start = *rest;
+            Deserializer::new(&mut *rest).read_uvarint()?;
+            &start[..start.len() - rest.len()]
+        }
+        WIRE_LEN => prefixed(rest)?,
+        wire => return Err(Error::Invalid(format!("wire type: {wire}"))),
+    };
+    Ok((key >> 3, value))
+}
+
+/// Skip over an encoded value with the given schema and options, without decoding it.
+pub fn skip(schema: &Schema, options: &Options, rest: &mut &[u8]) -> Result<()> {
+    let mut de = Deserializer::new(&mut *rest);
+    match schema {
+        Schema::Unit => {}
+        Schema::Bool => {
+            de.read_bool()?;
+        }
+        Schema::U8
+        | Schema::U16
+        | Schema::U32
+        | Schema::U64
+        | Schema::I8
+        | Schema::I16
+        | Schema::I32
+        | Schema::I64
+        | Schema::F32
+        | Schema::F64 => {
+            de.read_uvarint()?;
+        }
+        Schema::String | Schema::Bytes => {
+            let len = de.read_uvarint()?;
+            let len = usize::try_from(len)
+                .ok()
+                .filter(|len| *len <= rest.len())
+                .ok_or_else(|| Error::Invalid(format!("length: {len}")))?;
+            *rest = &rest[len..];
+        }
+        Schema::Seq(element) => {
+            let len = de.read_uvarint()?;
+            // Elements of zero-sized types such as units or empty tuples have no bytes, so
+            // if the first element has none, there is nothing to skip however many there are.
+            let before = rest.len();
+            for i in 0..len {
+                skip(element, options, rest)?;
+                if i == 0 && rest.len() == before {
+                    break;
+                }
+            }
+        }
+        Schema::Tuple(elements) => {
+            for element in elements {
+                skip(element, options, rest)?;
+            }
+        }
+        Schema::Struct(s) => match options.struct_encoding {
+            StructEncoding::Tuple => {
+                for field in &s.fields {
+                    skip(&field.schema, options, rest)?;
+                }
+            }
+            StructEncoding::LengthPrefixed => {
+                let len = de.read_uvarint()?;
+                *rest = usize::try_from(len)
+                    .ok()
+                    .and_then(|len| rest.get(len..))
+                    .ok_or_else(|| Error::Invalid(format!("struct length: {len}")))?;
+            }
+            StructEncoding::Numbered => {
+                let len = de.read_uvarint()?;
+                for _ in 0..len {
+                    numbered_field(rest)?;
+                }
+            }
+        },
+        Schema::Enum(e) => {
+            let index = de.read_uvarint()?;
+            let variant = usize::try_from(index)
+                .ok()
+                .and_then(|index| e.variants.get(index))
+                .ok_or_else(|| Error::Invalid(format!("variant index: {index}")))?;
+            match options.enum_encoding {
+                EnumEncoding::Plain => skip(&variant.schema, options, rest)?,
+                EnumEncoding::LengthPrefixed => {
+                    prefixed(rest)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use serde_sibor::Schema;
+
+/// Two `Point` values, `{ x: 5, name: "hi" }` and `{ x: 7, name: "a" }`, as a hex dump.
+const POINTS: &str = "05 02 68 69 07 01 61";
+
+/// Write the schema of the test input to a file, and return its path.
+fn schema_file(name: &str, schema: &Schema) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("sibor-{}-{name}.json", std::process::id()));
+    std::fs::write(&path, serde_json::to_vec(schema).unwrap()).unwrap();
+    path
+}
+
+fn points_schema(name: &str) -> std::path::PathBuf {
+    schema_file(
+        name,
+        &Schema::structure("Point", [("x", Schema::U8), ("name", Schema::String)]),
+    )
+}
+
+/// Run the tool with the given arguments and input.
+fn sibor(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sibor"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn test_decode() {
+    let schema = points_schema("decode");
+    let schema = schema.to_str().unwrap();
+    let output = sibor(&["decode", "--schema", schema, "--hex"], POINTS);
+    assert_eq!(
+        stdout(&output),
+        "{\"x\":5,\"name\":\"hi\"}\n{\"x\":7,\"name\":\"a\"}\n"
+    );
+}
+
+#[test]
+fn test_decode_rejects_forged_lengths() {
+    let schema = schema_file("forged", &Schema::String);
+    let schema = schema.to_str().unwrap();
+    let output = sibor(
+        &["decode", "--schema", schema, "--hex"],
+        "ff ff ff ff ff ff 0f",
+    );
+    // The tool reports the error instead of aborting on a huge allocation.
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("value at offset 0"));
+}
+
+#[test]
+fn test_encode() {
+    let schema = points_schema("encode");
+    let schema = schema.to_str().unwrap();
+    let output = sibor(
+        &["encode", "--schema", schema, "--hex"],
+        "{\"x\":5,\"name\":\"hi\"} {\"x\":7,\"name\":\"a\"}",
+    );
+    assert_eq!(stdout(&output), format!("{POINTS}\n"));
+}
+
+#[test]
+fn test_dump() {
+    let output = stdout(&sibor(&["dump", "--hex"], POINTS));
+    let lines = Vec::from_iter(
+        output
+            .lines()
+            .map(str::split_whitespace)
+            .map(Vec::from_iter),
+    );
+    assert_eq!(
+        lines,
+        [
+            vec!["0", "05", "u=5", "i=-3"],
+            vec!["1", "02", "68", "69", "len=2", "\"hi\""],
+            vec!["4", "07", "u=7", "i=-4"],
+            vec!["5", "01", "61", "len=1", "\"a\""],
+        ]
+    );
+}
+
+#[test]
+fn test_trace() {
+    let schema = points_schema("trace");
+    let schema = schema.to_str().unwrap();
+    let output = stdout(&sibor(&["trace", "--schema", schema, "--hex"], POINTS));
+    assert!(output.starts_with("value 0:\n"), "{output}");
+    assert!(output.contains("value 1:\n"), "{output}");
+    assert!(output.contains("name"), "{output}");
+}
+
+#[test]
+fn test_validate() {
+    let schema = points_schema("validate");
+    let schema = schema.to_str().unwrap();
+    let output = sibor(&["validate", "--schema", schema, "--hex"], POINTS);
+    assert_eq!(stdout(&output), "ok: 2 values\n");
+
+    // A truncated value is reported with its offset.
+    let output = sibor(
+        &["validate", "--schema", schema, "--hex"],
+        "05 02 68 69 07 05 61",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("value at offset 4"));
+}
+
+#[test]
+fn test_stats() {
+    let schema = points_schema("stats");
+    let schema = schema.to_str().unwrap();
+    let output = stdout(&sibor(&["stats", "--schema", schema, "--hex"], POINTS));
+    assert!(
+        output.starts_with("values: 2\ntotal bytes: 7\nmin bytes: 3\nmax bytes: 4\n"),
+        "{output}"
+    );
+    assert!(output.contains("name"), "{output}");
+}